@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates DROP COLUMN image_height;
+ALTER TABLE photo_templates DROP COLUMN image_width;
\ No newline at end of file
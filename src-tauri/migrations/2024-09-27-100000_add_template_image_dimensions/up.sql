@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN image_width INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE photo_templates ADD COLUMN image_height INTEGER NOT NULL DEFAULT 0;
\ No newline at end of file
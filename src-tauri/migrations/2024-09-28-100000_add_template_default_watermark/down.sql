@@ -0,0 +1 @@
+ALTER TABLE photo_templates DROP COLUMN default_watermark;
\ No newline at end of file
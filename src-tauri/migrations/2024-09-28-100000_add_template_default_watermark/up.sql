@@ -0,0 +1 @@
+ALTER TABLE photo_templates ADD COLUMN default_watermark TEXT NOT NULL DEFAULT '';
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE template_edits;
\ No newline at end of file
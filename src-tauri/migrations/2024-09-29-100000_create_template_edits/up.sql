@@ -0,0 +1,12 @@
+CREATE TABLE template_edits (
+  id INTEGER PRIMARY KEY NOT NULL,
+  template_id INTEGER NOT NULL REFERENCES photo_templates(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  crop_photo TEXT NOT NULL,
+  crop_number TEXT NOT NULL,
+  template_img TEXT NOT NULL,
+  image_width INTEGER NOT NULL,
+  image_height INTEGER NOT NULL,
+  default_watermark TEXT NOT NULL
+);
+CREATE INDEX template_edits_template_id ON template_edits (template_id);
@@ -0,0 +1 @@
+DROP TABLE generation_presets;
\ No newline at end of file
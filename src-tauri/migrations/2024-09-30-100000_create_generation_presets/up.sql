@@ -0,0 +1,5 @@
+CREATE TABLE generation_presets (
+  id INTEGER PRIMARY KEY NOT NULL,
+  name TEXT NOT NULL UNIQUE,
+  settings TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN font_size;
+ALTER TABLE photo_templates DROP COLUMN font_size;
\ No newline at end of file
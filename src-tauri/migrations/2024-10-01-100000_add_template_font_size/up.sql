@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN font_size REAL NOT NULL DEFAULT 0;
+ALTER TABLE template_edits ADD COLUMN font_size REAL NOT NULL DEFAULT 0;
\ No newline at end of file
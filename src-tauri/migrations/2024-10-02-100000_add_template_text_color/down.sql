@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN text_color;
+ALTER TABLE photo_templates DROP COLUMN text_color;
\ No newline at end of file
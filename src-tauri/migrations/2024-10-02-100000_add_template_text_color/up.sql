@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN text_color TEXT NOT NULL DEFAULT '#000000';
+ALTER TABLE template_edits ADD COLUMN text_color TEXT NOT NULL DEFAULT '#000000';
\ No newline at end of file
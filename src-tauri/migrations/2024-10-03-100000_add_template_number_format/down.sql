@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN number_format;
+ALTER TABLE photo_templates DROP COLUMN number_format;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN number_format TEXT NOT NULL DEFAULT 'N° {n}';
+ALTER TABLE template_edits ADD COLUMN number_format TEXT NOT NULL DEFAULT 'N° {n}';
\ No newline at end of file
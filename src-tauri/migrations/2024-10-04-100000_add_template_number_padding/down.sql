@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN number_padding;
+ALTER TABLE photo_templates DROP COLUMN number_padding;
@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN number_padding INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE template_edits ADD COLUMN number_padding INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates DROP COLUMN updated_at;
+ALTER TABLE photo_templates DROP COLUMN created_at;
@@ -0,0 +1,4 @@
+-- SQLite can't add a column defaulting to CURRENT_TIMESTAMP, existing rows get the migration time
+ALTER TABLE photo_templates ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE photo_templates ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+UPDATE photo_templates SET created_at = CURRENT_TIMESTAMP, updated_at = CURRENT_TIMESTAMP;
@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN default_output_folder;
+ALTER TABLE photo_templates DROP COLUMN default_output_folder;
@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN default_output_folder TEXT NOT NULL DEFAULT '';
+ALTER TABLE template_edits ADD COLUMN default_output_folder TEXT NOT NULL DEFAULT '';
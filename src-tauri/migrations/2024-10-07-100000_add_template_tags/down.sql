@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN tags;
+ALTER TABLE photo_templates DROP COLUMN tags;
@@ -0,0 +1,2 @@
+ALTER TABLE photo_templates ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE template_edits ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,2 @@
+ALTER TABLE template_edits DROP COLUMN font_id;
+ALTER TABLE photo_templates DROP COLUMN font_id;
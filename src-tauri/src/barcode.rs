@@ -0,0 +1,145 @@
+use barcoders::sym::code128::Code128;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+
+/// Blank modules kept around the code so scanners find its edges
+const CODE128_QUIET_ZONE: usize = 10;
+const QR_QUIET_ZONE: usize = 4;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    Code128,
+    Qr,
+}
+
+/// Barcode of the photo number drawn in a box of the output, alongside the text number.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BarcodeOptions {
+    pub symbology: Symbology,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Draw `value` as a barcode on a white background filling the barcode box.
+///
+/// Modules are whole pixels so the code stays sharp, the code is centered in the box.
+pub fn apply_barcode(image: DynamicImage, barcode: &BarcodeOptions, value: &str) -> Result<DynamicImage, String> {
+    let box_width = barcode.width.max(0.0) as u32;
+    let box_height = barcode.height.max(0.0) as u32;
+    let mut layer = RgbaImage::from_pixel(box_width.max(1), box_height.max(1), Rgba([255, 255, 255, 255]));
+
+    match barcode.symbology {
+        Symbology::Code128 => {
+            // Character set B encodes digits as well as any printable ASCII in a number format
+            let modules = Code128::new(format!("\u{0181}{}", value))
+                .map_err(|e| format!("Error encoding {:?} as Code128: {}", value, e))?
+                .encode();
+            let total_modules = modules.len() + 2 * CODE128_QUIET_ZONE;
+            let module_width = box_width / total_modules as u32;
+            if module_width == 0 {
+                return Err(format!(
+                    "Barcode box is {} pixels wide, a Code128 of {:?} needs at least {}",
+                    box_width, value, total_modules
+                ));
+            }
+            let left = (box_width - modules.len() as u32 * module_width) / 2;
+            for (index, _) in modules.iter().enumerate().filter(|(_, module)| **module == 1) {
+                let bar_x = left + index as u32 * module_width;
+                for x in bar_x..bar_x + module_width {
+                    for y in 0..box_height {
+                        layer.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+                    }
+                }
+            }
+        }
+        Symbology::Qr => {
+            let code = qrcode::QrCode::new(value.as_bytes())
+                .map_err(|e| format!("Error encoding {:?} as QR code: {}", value, e))?;
+            let size = code.width();
+            let module_size = box_width.min(box_height) / (size + 2 * QR_QUIET_ZONE) as u32;
+            if module_size == 0 {
+                return Err(format!(
+                    "Barcode box is {}x{} pixels, a QR code of {:?} needs at least {} on each side",
+                    box_width, box_height, value, size + 2 * QR_QUIET_ZONE
+                ));
+            }
+            let left = (box_width - size as u32 * module_size) / 2;
+            let top = (box_height - size as u32 * module_size) / 2;
+            for (index, color) in code.to_colors().into_iter().enumerate() {
+                if color != qrcode::Color::Dark {
+                    continue;
+                }
+                let module_x = left + (index % size) as u32 * module_size;
+                let module_y = top + (index / size) as u32 * module_size;
+                for x in module_x..module_x + module_size {
+                    for y in module_y..module_y + module_size {
+                        layer.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut result = image.to_rgba8();
+    image::imageops::replace(&mut result, &layer, barcode.x.round() as i64, barcode.y.round() as i64);
+    Ok(DynamicImage::ImageRgba8(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Read the Code128 drawn on row `y` back into its text, through the symbols of character set B.
+    fn read_code128(image: &RgbaImage, y: u32) -> String {
+        let row: Vec<bool> = (0..image.width()).map(|x| image.get_pixel(x, y)[0] < 128).collect();
+        let first = row.iter().position(|&dark| dark).unwrap();
+        let last = row.iter().rposition(|&dark| dark).unwrap();
+        let mut runs: Vec<(bool, usize)> = Vec::new();
+        for &dark in &row[first..=last] {
+            match runs.last_mut() {
+                Some((color, length)) if *color == dark => *length += 1,
+                _ => runs.push((dark, 1)),
+            }
+        }
+        let module_width = runs.iter().map(|&(_, length)| length).min().unwrap();
+        let modules: Vec<u8> = runs
+            .iter()
+            .flat_map(|&(dark, length)| std::iter::repeat_n(dark as u8, length / module_width))
+            .collect();
+
+        // Each data symbol follows the 11 modules of the start symbol when encoded alone
+        let symbol = |character: char| Code128::new(format!("\u{0181}{}", character)).unwrap().encode()[11..22].to_vec();
+        let symbols: Vec<(Vec<u8>, char)> = (' '..='~').map(|character| (symbol(character), character)).collect();
+        // Start symbol first, checksum and the 13 modules of the stop symbol last
+        modules[11..modules.len() - 24]
+            .chunks(11)
+            .map(|chunk| symbols.iter().find(|(modules, _)| modules == chunk).unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn code128_reads_back_as_the_number() {
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(400, 200, Rgba([255, 255, 255, 255])));
+        let barcode = BarcodeOptions { symbology: Symbology::Code128, x: 20.0, y: 120.0, width: 360.0, height: 60.0 };
+
+        let output = apply_barcode(white, &barcode, "0042").unwrap().to_rgba8();
+
+        assert_eq!(read_code128(&output, 150), "0042");
+        // Only the barcode box was drawn on
+        let outside_box = |x: u32, y: u32| !(20..380).contains(&x) || !(120..180).contains(&y);
+        assert!(output.enumerate_pixels().filter(|(x, y, _)| outside_box(*x, *y)).all(|(_, _, pixel)| pixel.0 == [255; 4]));
+    }
+
+    #[test]
+    fn barcode_box_too_small_for_the_code_is_refused() {
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255])));
+        let narrow = BarcodeOptions { symbology: Symbology::Code128, x: 0.0, y: 0.0, width: 40.0, height: 20.0 };
+        let tiny = BarcodeOptions { symbology: Symbology::Qr, x: 0.0, y: 0.0, width: 10.0, height: 10.0 };
+
+        assert!(apply_barcode(white.clone(), &narrow, "0042").unwrap_err().contains("needs at least"));
+        assert!(apply_barcode(white, &tiny, "0042").unwrap_err().contains("needs at least"));
+    }
+}
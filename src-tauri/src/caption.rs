@@ -0,0 +1,164 @@
+use image::{DynamicImage, Rgba};
+use serde::Deserialize;
+
+use crate::text;
+use crate::CropCoordinates;
+
+/// Prefixes cameras and phones put in front of their file names.
+const CAMERA_PREFIXES: &[&str] = &["img", "dsc", "dscn", "dscf", "pxl", "vid", "dji", "gopr"];
+
+/// Caption rendered in a box of the output from the source file name.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FilenameCaptionOptions {
+    #[serde(rename = "box")]
+    pub caption_box: CropCoordinates,
+    #[serde(default)]
+    pub transform: CaptionTransform,
+    /// Black when unset
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+}
+
+/// How a file name is cleaned up into a caption, e.g. `IMG_wedding_anna_0042` becomes `wedding anna`.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct CaptionTransform {
+    /// Turn `_` and `-` into spaces
+    pub separators_to_spaces: bool,
+    /// Drop words made only of digits
+    pub strip_numbers: bool,
+    /// Drop a leading camera prefix such as `IMG` or `DSC`
+    pub strip_camera_prefix: bool,
+    pub case: CaptionCase,
+}
+
+impl Default for CaptionTransform {
+    fn default() -> Self {
+        CaptionTransform {
+            separators_to_spaces: true,
+            strip_numbers: true,
+            strip_camera_prefix: true,
+            case: CaptionCase::Keep,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionCase {
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+    Title,
+}
+
+/// Clean up a file stem (no extension) into caption text.
+pub fn caption_from_filename(stem: &str, transform: &CaptionTransform) -> String {
+    let spaced = if transform.separators_to_spaces {
+        stem.replace(['_', '-'], " ")
+    } else {
+        stem.to_string()
+    };
+
+    let mut words: Vec<&str> = spaced.split_whitespace().collect();
+    if transform.strip_camera_prefix
+        && words.first().is_some_and(|word| CAMERA_PREFIXES.contains(&word.to_lowercase().as_str()))
+    {
+        words.remove(0);
+    }
+    if transform.strip_numbers {
+        words.retain(|word| !word.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    let caption = words.join(" ");
+    match transform.case {
+        CaptionCase::Keep => caption,
+        CaptionCase::Lower => caption.to_lowercase(),
+        CaptionCase::Upper => caption.to_uppercase(),
+        CaptionCase::Title => caption
+            .split(' ')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                    None => String::new(),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" "),
+    }
+}
+
+/// Draw the caption derived from `stem` into the caption box, leaving the image untouched when it is empty.
+pub fn apply_filename_caption(
+    image: DynamicImage,
+    caption: &FilenameCaptionOptions,
+    stem: &str,
+    gradient: Option<&text::TextGradient>,
+) -> DynamicImage {
+    let text = caption_from_filename(stem, &caption.transform);
+    if text.is_empty() {
+        return image;
+    }
+
+    let caption_box = &caption.caption_box;
+    text::draw_keeping_depth(image, |result| {
+        text::draw_text_in_box(
+            result,
+            text::default_font(),
+            &text,
+            (caption_box.x, caption_box.y, caption_box.width, caption_box.height),
+            None,
+            Rgba(caption.color.unwrap_or([0, 0, 0, 255])),
+            gradient,
+            None,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn camera_file_names_become_readable_captions() {
+        let transform = CaptionTransform::default();
+
+        assert_eq!(caption_from_filename("IMG_wedding_anna", &transform), "wedding anna");
+        assert_eq!(caption_from_filename("DSC-0042-beach_day", &transform), "beach day");
+        let title = CaptionTransform { case: CaptionCase::Title, ..transform };
+        assert_eq!(caption_from_filename("IMG_wedding_anna_0042", &title), "Wedding Anna");
+        let untouched = CaptionTransform {
+            separators_to_spaces: false,
+            strip_numbers: false,
+            strip_camera_prefix: false,
+            case: CaptionCase::Keep,
+        };
+        assert_eq!(caption_from_filename("IMG_wedding_anna", &untouched), "IMG_wedding_anna");
+    }
+
+    #[test]
+    fn caption_is_drawn_inside_its_box_only() {
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(300, 200, Rgba([255, 255, 255, 255])));
+        let caption = FilenameCaptionOptions {
+            caption_box: CropCoordinates { x: 50.0, y: 120.0, width: 200.0, height: 60.0 },
+            transform: CaptionTransform::default(),
+            color: None,
+        };
+
+        let captioned = apply_filename_caption(white.clone(), &caption, "IMG_wedding_anna", None).to_rgba8();
+
+        let inside_box = |x: u32, y: u32| (50..250).contains(&x) && (120..180).contains(&y);
+        let changed: Vec<(u32, u32)> = captioned
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0 != [255, 255, 255, 255])
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert!(!changed.is_empty());
+        assert!(changed.iter().all(|&(x, y)| inside_box(x, y)));
+        // Nothing left once cleaned up, nothing drawn
+        assert_eq!(apply_filename_caption(white.clone(), &caption, "IMG_0042", None).to_rgba8(), white.to_rgba8());
+    }
+}
@@ -0,0 +1,119 @@
+use image::{DynamicImage, Rgba};
+use imageproc::edges::canny;
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+
+/// Largest skew corrected, in degrees, anything steeper is more likely a deliberate tilt
+const MAX_SKEW_DEGREES: f32 = 5.0;
+const ANGLE_STEP_DEGREES: f32 = 0.1;
+/// Smaller skews are left alone, rotating would only soften the photo
+const MIN_SKEW_DEGREES: f32 = 0.2;
+/// Edges are detected on a copy scaled down to this size, plenty for the angle
+const ANALYSIS_DIMENSION: u32 = 600;
+
+/// Estimate how far the content of a scan is rotated clockwise, in degrees.
+///
+/// Projection profile of the edges: when the rotation is undone, straight edges (photo borders,
+/// horizons, walls) pile up into few rows and columns, which maximizes the sum of their squared counts.
+pub fn estimate_skew(image: &DynamicImage) -> f32 {
+    let gray = image.thumbnail(ANALYSIS_DIMENSION, ANALYSIS_DIMENSION).to_luma8();
+    let edges = canny(&gray, 20.0, 60.0);
+    let points: Vec<(f32, f32)> = edges
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[0] > 0)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    if points.is_empty() {
+        return 0.0;
+    }
+
+    // Rotated coordinates stay within one diagonal on each side of the origin
+    let diagonal = (gray.width() as f32).hypot(gray.height() as f32).ceil();
+    let mut rows = vec![0u32; 2 * diagonal as usize + 1];
+    let mut columns = vec![0u32; 2 * diagonal as usize + 1];
+    let steps = (MAX_SKEW_DEGREES / ANGLE_STEP_DEGREES).round() as i32;
+    let mut best = (0.0, 0.0);
+    // From the smallest angles outwards so ties keep the smallest correction
+    for step in (0..=steps).flat_map(|step| [step, -step]) {
+        let angle = step as f32 * ANGLE_STEP_DEGREES;
+        let (sin, cos) = angle.to_radians().sin_cos();
+        rows.fill(0);
+        columns.fill(0);
+        for &(x, y) in &points {
+            rows[(y * cos - x * sin + diagonal) as usize] += 1;
+            columns[(x * cos + y * sin + diagonal) as usize] += 1;
+        }
+        let score: f64 = rows.iter().chain(&columns).map(|&count| (count as f64).powi(2)).sum();
+        if score > best.1 {
+            best = (angle, score);
+        }
+    }
+    best.0
+}
+
+/// Straighten a slightly rotated scan, cropping the corners the rotation leaves empty.
+pub fn deskew(image: DynamicImage) -> DynamicImage {
+    let angle = estimate_skew(&image);
+    if angle.abs() < MIN_SKEW_DEGREES {
+        return image;
+    }
+
+    let theta = -angle.to_radians();
+    let is_16_bit = matches!(
+        image,
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_)
+    );
+    let rotated = if is_16_bit {
+        DynamicImage::ImageRgba16(rotate_about_center(&image.to_rgba16(), theta, Interpolation::Bilinear, Rgba([0; 4])))
+    } else {
+        DynamicImage::ImageRgba8(rotate_about_center(&image.to_rgba8(), theta, Interpolation::Bilinear, Rgba([0; 4])))
+    };
+
+    // Largest centered rectangle of the same shape that the rotated scan still covers entirely
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let (sin, cos) = (theta.abs().sin(), theta.abs().cos());
+    let scale = (width / (width * cos + height * sin)).min(height / (width * sin + height * cos));
+    let (crop_width, crop_height) = ((width * scale).floor() as u32, (height * scale).floor() as u32);
+    rotated.crop_imm(
+        (image.width() - crop_width) / 2,
+        (image.height() - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    /// Scan of a dark print with a light border, rotated clockwise by `degrees`.
+    fn skewed_scan(degrees: f32) -> DynamicImage {
+        let print = RgbaImage::from_fn(600, 400, |x, y| {
+            let inside = (100..500).contains(&x) && (80..320).contains(&y);
+            if inside { Rgba([40, 40, 40, 255]) } else { Rgba([230, 230, 230, 255]) }
+        });
+        let background = Rgba([230, 230, 230, 255]);
+        DynamicImage::ImageRgba8(rotate_about_center(&print, degrees.to_radians(), Interpolation::Bilinear, background))
+    }
+
+    #[test]
+    fn three_degree_skew_is_measured_and_straightened() {
+        let scan = skewed_scan(3.0);
+
+        let skew = estimate_skew(&scan);
+        assert!((skew - 3.0).abs() <= 0.3, "{}", skew);
+
+        let straightened = deskew(scan);
+        assert!(estimate_skew(&straightened).abs() < MIN_SKEW_DEGREES, "{}", estimate_skew(&straightened));
+        assert!(straightened.width() < 600 && straightened.height() < 400);
+    }
+
+    #[test]
+    fn straight_and_steep_scans_are_left_alone() {
+        let straight = skewed_scan(0.0);
+        assert_eq!(deskew(straight.clone()).to_rgba8(), straight.to_rgba8());
+
+        // Beyond the corrected range, the estimate stays within it
+        assert!(estimate_skew(&skewed_scan(20.0)).abs() <= MAX_SKEW_DEGREES);
+    }
+}
@@ -0,0 +1,124 @@
+use image::{DynamicImage, Pixel};
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Read the EXIF orientation tag (1-8) of an image file, defaulting to 1 when absent.
+pub fn read_exif_orientation(path: &Path) -> u32 {
+    let Ok(file) = fs::File::open(path) else {
+        return 1;
+    };
+    let mut reader = BufReader::new(file);
+    exif::Reader::new()
+        .read_from_container(&mut reader)
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+/// Rotate/flip an image so it is displayed upright according to its EXIF orientation.
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Stretch the color range so the darkest channel value maps to 0 and the brightest to 255.
+///
+/// A single min/max is used for all channels so the stretch does not shift colors.
+pub fn auto_levels(image: DynamicImage) -> DynamicImage {
+    let mut rgba = image.to_rgba8();
+
+    let (min, max) = rgba.pixels().fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+        let channels = &pixel.channels()[..3];
+        let pixel_min = *channels.iter().min().unwrap_or(&0);
+        let pixel_max = *channels.iter().max().unwrap_or(&0);
+        (min.min(pixel_min), max.max(pixel_max))
+    });
+
+    if max <= min {
+        return DynamicImage::ImageRgba8(rgba);
+    }
+
+    let range = (max - min) as f32;
+    for pixel in rgba.pixels_mut() {
+        for channel in pixel.channels_mut().iter_mut().take(3) {
+            *channel = (((*channel - min) as f32 / range) * 255.0).round() as u8;
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Apply EXIF orientation correction followed by an auto-levels stretch.
+pub fn auto_enhance(image: DynamicImage, source_path: &Path) -> DynamicImage {
+    let oriented = apply_orientation(image, read_exif_orientation(source_path));
+    auto_levels(oriented)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn value_range(image: &DynamicImage) -> (u8, u8) {
+        image.to_rgba8().pixels().fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+            let channels = &pixel.channels()[..3];
+            (min.min(*channels.iter().min().unwrap()), max.max(*channels.iter().max().unwrap()))
+        })
+    }
+
+    #[test]
+    fn auto_levels_widens_a_low_contrast_image() {
+        let low_contrast = RgbaImage::from_fn(16, 16, |x, _| {
+            let value = 100 + (x * 3) as u8;
+            Rgba([value, value, value, 255])
+        });
+        let image = DynamicImage::ImageRgba8(low_contrast);
+        let (min, max) = value_range(&image);
+
+        let enhanced = auto_levels(image);
+
+        let (enhanced_min, enhanced_max) = value_range(&enhanced);
+        assert_eq!((enhanced_min, enhanced_max), (0, 255));
+        assert!(enhanced_max - enhanced_min > max - min);
+        // Alpha is left untouched
+        assert!(enhanced.to_rgba8().pixels().all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn auto_levels_leaves_a_flat_image_alone() {
+        let flat = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([80, 80, 80, 255])));
+
+        assert_eq!(value_range(&auto_levels(flat)), (80, 80));
+    }
+
+    #[test]
+    fn orientations_that_rotate_swap_the_dimensions() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(30, 20));
+
+        for orientation in [1, 2, 3, 4] {
+            let oriented = apply_orientation(image.clone(), orientation);
+            assert_eq!((oriented.width(), oriented.height()), (30, 20), "orientation {}", orientation);
+        }
+        for orientation in [5, 6, 7, 8] {
+            let oriented = apply_orientation(image.clone(), orientation);
+            assert_eq!((oriented.width(), oriented.height()), (20, 30), "orientation {}", orientation);
+        }
+    }
+
+    #[test]
+    fn missing_file_has_the_default_orientation() {
+        assert_eq!(read_exif_orientation(Path::new("/nonexistent/photo.jpg")), 1);
+    }
+}
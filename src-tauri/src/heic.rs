@@ -0,0 +1,50 @@
+use image::DynamicImage;
+use std::path::Path;
+
+/// Extensions of HEIC/HEIF photos, as written by iPhones and recent Android phones.
+pub const HEIC_EXTENSIONS: &[&str] = &["heic", "heif"];
+
+pub fn is_heic(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| HEIC_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Decode the primary image of a HEIC/HEIF file, with the rotation and cropping it specifies applied.
+#[cfg(feature = "heic")]
+pub fn decode(path: &Path) -> Result<DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let path_str = path.to_str().ok_or(format!("Invalid HEIC path {:?}", path))?;
+    let context = HeifContext::read_from_file(path_str)
+        .map_err(|e| format!("Error loading image {:?}: {}", path, e))?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(|e| format!("Error loading image {:?}: {}", path, e))?;
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None)
+        .map_err(|e| format!("Error decoding image {:?}: {}", path, e))?;
+
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or(format!("Error decoding image {:?}: no interleaved RGBA plane", path))?;
+    // Rows may be padded past width * 4 bytes
+    let row_length = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_length * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_length]);
+    }
+
+    image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or(format!("Error decoding image {:?}: unexpected plane size", path))
+}
+
+#[cfg(not(feature = "heic"))]
+pub fn decode(path: &Path) -> Result<DynamicImage, String> {
+    Err(format!(
+        "Cannot load {:?}: this build has no HEIC/HEIF support, convert the photo to JPEG or rebuild with the `heic` feature",
+        path
+    ))
+}
@@ -1,24 +1,52 @@
 use diesel::prelude::*;
+use diesel::migration::MigrationSource;
+use diesel::r2d2;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use std::collections::HashMap;
 use std::fs;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
-use tauri::{AppHandle, Manager, Emitter};
-use image::{DynamicImage, Rgba};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, Emitter, State};
+use image::{DynamicImage, ImageBuffer, Pixel, Rgba};
 use std::io::Write;
 use zip::{ZipWriter, write::FileOptions};
 use walkdir::WalkDir;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use rayon::prelude::*;
 use regex::Regex;
+use sha2::{Digest, Sha256};
 
+mod barcode;
+mod caption;
+mod deskew;
+mod enhance;
+mod heic;
+mod lut;
+mod metadata;
 pub mod models;
+mod pdf;
+mod remote;
 pub mod schema;
+mod svg;
+mod text;
+mod watermark;
+#[cfg(test)]
+mod tests;
 
-use models::{NewPhotoTemplate, PhotoTemplate};
-use schema::photo_templates;
+use barcode::BarcodeOptions;
+use caption::FilenameCaptionOptions;
+use metadata::MetadataField;
+use remote::{RemoteDestination, UploadProgress};
+use text::{TextArc, TextFill, TextGradient, TextPath};
+use models::{GenerationPreset, NewGenerationPreset, NewPhotoTemplate, NewTemplateEdit, PhotoTemplate, PhotoTemplatePatch, TemplateEdit};
+use watermark::WatermarkOptions;
+use schema::{generation_presets, photo_templates, template_edits};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
 struct CropCoordinates {
     x: f32,
     y: f32,
@@ -26,6 +54,654 @@ struct CropCoordinates {
     height: f32,
 }
 
+impl CropCoordinates {
+    fn scaled(&self, scale_x: f32, scale_y: f32) -> CropCoordinates {
+        CropCoordinates {
+            x: self.x * scale_x,
+            y: self.y * scale_y,
+            width: self.width * scale_x,
+            height: self.height * scale_y,
+        }
+    }
+}
+
+/// Generation jobs, run one at a time so they never share the output directory.
+#[derive(Default)]
+struct GenerationState {
+    /// Cancellation of the running job, reset when the next job starts
+    cancelled: AtomicBool,
+    /// Held for the whole duration of a job
+    run_lock: tokio::sync::Mutex<()>,
+    jobs: Mutex<Vec<GenerationJob>>,
+    next_job_id: AtomicU64,
+}
+
+/// Number of finished jobs kept around for `list_jobs`.
+const MAX_FINISHED_JOBS: usize = 50;
+
+impl GenerationState {
+    fn enqueue(&self, template_id: i32, image_folder_path: &str) -> GenerationJob {
+        let job = GenerationJob {
+            id: self.next_job_id.fetch_add(1, Ordering::SeqCst) + 1,
+            template_id,
+            image_folder_path: image_folder_path.to_string(),
+            status: JobStatus::Queued,
+            result: None,
+            error: None,
+        };
+
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let finished = jobs.iter().filter(|job| job.status.is_finished()).count();
+        if finished >= MAX_FINISHED_JOBS {
+            if let Some(oldest) = jobs.iter().position(|job| job.status.is_finished()) {
+                jobs.remove(oldest);
+            }
+        }
+        jobs.push(job.clone());
+        job
+    }
+
+    /// Apply `update` to a job and return its new state.
+    fn update_job(&self, id: u64, update: impl FnOnce(&mut GenerationJob)) -> Option<GenerationJob> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let job = jobs.iter_mut().find(|job| job.id == id)?;
+        update(job);
+        Some(job.clone())
+    }
+
+    fn job(&self, id: u64) -> Option<GenerationJob> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    fn all_jobs(&self) -> Vec<GenerationJob> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Wait for the previous jobs to finish, then run this one with `run` while tracking its status.
+    ///
+    /// `on_update` receives the job each time its status changes.
+    async fn run_job<F, Fut>(
+        &self,
+        job_id: u64,
+        on_update: impl Fn(&GenerationJob),
+        run: F,
+    ) -> Result<GenerationResult, String>
+    where
+        F: FnOnce(GenerationJob) -> Fut,
+        Fut: std::future::Future<Output = Result<GenerationResult, String>>,
+    {
+        let _running = self.run_lock.lock().await;
+        self.cancelled.store(false, Ordering::SeqCst);
+
+        let Some(job) = self.update_job(job_id, |job| job.status = JobStatus::Running) else {
+            return Err(format!("Generation job {} not found", job_id));
+        };
+        on_update(&job);
+
+        let result = run(job).await;
+
+        let cancelled = self.cancelled.load(Ordering::SeqCst);
+        let finished = self.update_job(job_id, |job| match &result {
+            Ok(generation) => {
+                job.status = JobStatus::Completed;
+                job.result = Some(generation.clone());
+            }
+            Err(e) => {
+                job.status = if cancelled { JobStatus::Cancelled } else { JobStatus::Failed };
+                job.error = Some(e.clone());
+            }
+        });
+        if let Some(job) = finished {
+            on_update(&job);
+        }
+
+        result
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct GenerationJob {
+    id: u64,
+    template_id: i32,
+    image_folder_path: String,
+    status: JobStatus,
+    result: Option<GenerationResult>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TemplateCrops {
+    crop_photo: CropCoordinates,
+    crop_number: Option<CropCoordinates>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum TemplateImageStatus {
+    Ok,
+    Missing,
+    Corrupt,
+}
+
+#[derive(Serialize)]
+struct TemplateImageReport {
+    id: i32,
+    name: String,
+    template_img: String,
+    status: TemplateImageStatus,
+    error: Option<String>,
+}
+
+/// Template definitions kept outside the database, matched to rows by name.
+#[derive(Deserialize)]
+struct TemplatesConfig {
+    templates: Vec<TemplateConfigEntry>,
+}
+
+#[derive(Deserialize)]
+struct TemplateConfigEntry {
+    name: String,
+    crop_photo: CropCoordinates,
+    crop_number: Option<CropCoordinates>,
+    template_img: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+struct TemplateSyncReport {
+    inserted: Vec<String>,
+    updated: Vec<String>,
+    unchanged: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct RegionColor {
+    /// Mean RGBA of the sampled pixels
+    color: [u8; 4],
+    /// `#000000` or `#FFFFFF`, whichever contrasts best with `color`
+    suggested_text_color: String,
+}
+
+#[derive(Serialize)]
+struct TextSize {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Serialize)]
+struct SourceThumbnail {
+    filename: String,
+    number: String,
+    data_url: String,
+}
+
+/// Upper bound for thumbnails sent to the frontend, whatever size is requested
+const MAX_THUMBNAIL_DIMENSION: u32 = 512;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GenerationOptions {
+    /// Store the number as XMP, only JPEG and PNG outputs can carry it
+    write_number_to_metadata: bool,
+    metadata_field: MetadataField,
+    output_format: OutputFormat,
+    /// Only used for JPEG outputs
+    jpeg_quality: JpegQuality,
+    /// Only used for JPEG outputs
+    jpeg_subsampling: JpegSubsampling,
+    color_type: OutputColorType,
+    auto_enhance: bool,
+    /// Straighten sources scanned slightly askew, up to a few degrees
+    deskew: bool,
+    prefix_with_index: bool,
+    mosaic: Option<MosaicOptions>,
+    template_on_top: bool,
+    /// How the photo is scaled into the template's photo slot
+    fit_mode: FitMode,
+    /// Only used when `fit_mode` is `contain`
+    gap_fill: GapFill,
+    /// Templates used instead of the run's template for landscape and portrait sources,
+    /// square sources and unset orientations keep the run's template
+    landscape_template_id: Option<i32>,
+    portrait_template_id: Option<i32>,
+    /// RGBA color used by `gap_fill: "color"`, white by default
+    gap_fill_color: Option<[u8; 4]>,
+    /// Minimum delay between two progress events
+    progress_interval_ms: Option<u64>,
+    /// Minimum progress increase between two progress events
+    progress_step_percent: Option<f32>,
+    /// Overrides the template's default watermark for this run
+    watermark: Option<WatermarkOptions>,
+    /// Only process files whose name matches one of these patterns
+    include_patterns: Vec<String>,
+    /// Skip files whose name matches one of these patterns, applied after `include_patterns`
+    exclude_patterns: Vec<String>,
+    /// Also store the untouched source files in the archive under `originals/`
+    include_originals: bool,
+    /// Extra folders or ZIP files processed together with the main image folder
+    sources: Vec<String>,
+    /// Opacity (0..1) of the photo inside its slot, fully opaque when unset
+    photo_opacity: Option<f32>,
+    /// Also pick up photos in subfolders, outputs keep the same subfolders in the archive
+    recursive: bool,
+    /// File extensions (without the dot) picked up instead of the default list, e.g. only `png`
+    extensions: Option<Vec<String>>,
+    /// Additional file extensions (without the dot) to pick up for this run
+    extra_extensions: Vec<String>,
+    /// Center each output on a fixed-size print canvas
+    print_canvas: Option<PrintCanvasOptions>,
+    /// Barcode of the photo number drawn alongside the text number
+    barcode: Option<BarcodeOptions>,
+    /// Caption drawn from each source file name
+    filename_caption: Option<FilenameCaptionOptions>,
+    /// What number a file whose name has no digits gets
+    no_number_behavior: NoNumberBehavior,
+    /// Tilt of the photo inside its slot in degrees, clockwise
+    photo_rotation_degrees: f32,
+    /// Paint rendered text flat or with `text_gradient`
+    text_fill: TextFill,
+    text_gradient: TextGradient,
+    /// Lay the number out on a straight line or along `text_arc`
+    text_path: TextPath,
+    text_arc: TextArc,
+    /// Also push every output to S3 or SFTP, the local archive is still created
+    remote_destination: Option<RemoteDestination>,
+    /// Part of the source kept when it is cover-cropped, centered by default
+    crop_focus: CropFocus,
+    /// Also write each output scaled down to fit this many pixels to `previews/`, e.g. for low-res
+    /// proofs watermarked with the `previews` target
+    preview_max_dimension: Option<u32>,
+    /// Leave the outputs as loose files in the run directory instead of zipping them,
+    /// `include_originals`, `chunk_size` and `archive_compression` then have no effect
+    loose_files: bool,
+    /// Compression of `generated_images.zip`
+    archive_compression: ArchiveCompression,
+    /// Split the outputs into `generated_images_part1.zip`, `generated_images_part2.zip`, ...
+    /// of at most this many outputs each, in output order
+    chunk_size: Option<NonZeroUsize>,
+    /// What to do when two files would get the same name in the archive
+    duplicate_entries: DuplicateEntryPolicy,
+    /// `.cube` 3D LUT color grading applied to each source photo before compositing
+    lut_path: Option<String>,
+    /// Only draw the number on files whose name matches this pattern, the others get a clean output.
+    /// Pair with `include_originals` to also ship the untouched photos.
+    number_filter: Option<String>,
+    /// Number format and padding for ranges of output positions, e.g. `A{n}` for 1 to 50 and
+    /// `B{n}` for 51 to 100, outputs outside every range use the template's format
+    number_rules: Vec<NumberRule>,
+    /// Overrides the template's `number_padding` for this run
+    number_padding: Option<usize>,
+    /// Folder the run directory is created in, overrides the template's `default_output_folder`
+    output_folder: Option<String>,
+    /// Used for the run directory when the app data directory isn't writable
+    fallback_output_dir: Option<String>,
+    /// Produce byte-identical outputs and archive for identical inputs and settings,
+    /// e.g. by storing a fixed modification time in the archive entries
+    deterministic: bool,
+    /// Write a `hash_manifest.json` linking each source's SHA-256 to its output, so reprocessed
+    /// photos can be detected downstream
+    write_hash_manifest: bool,
+    /// Keep going when a file can't be processed instead of aborting the run
+    skip_failed_files: bool,
+    /// Render with at most this many threads instead of one per core, e.g. to stay within the
+    /// memory of a low-RAM machine, see `estimate_memory`
+    max_threads: Option<NonZeroUsize>,
+    /// Bundle the sources that failed with a `failures.json` into `failures.zip`, implies `skip_failed_files`
+    collect_failures_bundle: bool,
+}
+
+impl GenerationOptions {
+    /// How many source photos go into each output of a run over `image_count` photos.
+    fn photos_per_output(&self, image_count: usize) -> usize {
+        match self.mosaic {
+            Some(mosaic) => mosaic.photos_per_output().unwrap_or(image_count).max(1),
+            None => 1,
+        }
+    }
+
+    /// Gradient to paint rendered text with, `None` for flat colors.
+    fn text_gradient(&self) -> Option<&TextGradient> {
+        (self.text_fill == TextFill::Gradient).then_some(&self.text_gradient)
+    }
+
+    /// Arc the number follows, `None` for straight text.
+    fn text_arc(&self) -> Option<&TextArc> {
+        (self.text_path == TextPath::Arc).then_some(&self.text_arc)
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct GenerationResult {
+    /// First archive of the run, or the run directory with `loose_files`
+    archive_path: String,
+    /// Every archive of the run, several when `chunk_size` splits the outputs, none with `loose_files`
+    archive_paths: Vec<String>,
+    failures_bundle_path: Option<String>,
+    /// `None` when the run has no output or its thumbnail couldn't be written
+    thumbnail_path: Option<String>,
+}
+
+/// Outputs and failures of one `generate_images` run.
+struct GenerationRun {
+    images: Vec<GeneratedImage>,
+    failures: Vec<FailedImage>,
+}
+
+struct FailedImage {
+    sources: Vec<PathBuf>,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct FailureRecord {
+    source: String,
+    reason: String,
+}
+
+/// One output written by `generate_images`.
+struct GeneratedImage {
+    /// Source photos composited into this output, several in mosaic mode
+    sources: Vec<PathBuf>,
+    output: PathBuf,
+    /// Scaled-down copy of the output, written when `preview_max_dimension` is set
+    preview: Option<PathBuf>,
+    /// Number extracted from the first source, before any filtering of the drawn number
+    number: String,
+}
+
+/// Entry of `hash_manifest.json`, one per source photo.
+#[derive(Serialize)]
+struct HashManifestRecord {
+    source: String,
+    sha256: String,
+    /// File name of the output inside the run directory and archive
+    output: String,
+    number: String,
+}
+
+/// Coalesces progress updates so large batches don't flood the event bridge.
+struct ProgressThrottle {
+    interval: Option<std::time::Duration>,
+    step_percent: Option<f32>,
+    last_emit: Option<(std::time::Instant, f32)>,
+}
+
+impl ProgressThrottle {
+    fn new(options: &GenerationOptions) -> Self {
+        ProgressThrottle {
+            interval: options.progress_interval_ms.map(std::time::Duration::from_millis),
+            step_percent: options.progress_step_percent,
+            last_emit: None,
+        }
+    }
+
+    fn should_emit(&mut self, percent: f32) -> bool {
+        let now = std::time::Instant::now();
+        let emit = match self.last_emit {
+            // The first and the final update always go through
+            None => true,
+            Some(_) if percent >= 100.0 => true,
+            Some((last_time, last_percent)) => match (self.interval, self.step_percent) {
+                (None, None) => true,
+                (interval, step) => {
+                    interval.is_some_and(|interval| now.duration_since(last_time) >= interval)
+                        || step.is_some_and(|step| percent - last_percent >= step)
+                }
+            },
+        };
+
+        if emit {
+            self.last_emit = Some((now, percent));
+        }
+        emit
+    }
+}
+
+/// Point of the source (0..1 on each axis) kept in view when cover-cropping cuts part of it off.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+struct CropFocus {
+    x: f32,
+    y: f32,
+}
+
+impl Default for CropFocus {
+    fn default() -> Self {
+        CropFocus { x: 0.5, y: 0.5 }
+    }
+}
+
+/// How a photo is scaled into its slot when their aspect ratios differ.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FitMode {
+    /// Fit the whole photo inside the slot, leaving gaps on two sides
+    #[default]
+    Contain,
+    /// Fill the slot, cropping the overflow around `crop_focus`
+    Cover,
+    /// Fill the slot exactly, distorting the photo
+    Stretch,
+}
+
+/// How the parts of the photo slot left empty by a contained photo are filled.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum GapFill {
+    /// Let the template show through
+    #[default]
+    None,
+    Color,
+    /// Blurred, zoomed copy of the same photo
+    Blur,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum DuplicateEntryPolicy {
+    /// Add a `_2`, `_3`, ... suffix to later entries
+    #[default]
+    Rename,
+    /// Fail the archive
+    Error,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum NoNumberBehavior {
+    /// Use the file's position in the run
+    #[default]
+    Sequence,
+    /// Render no number for that file
+    Blank,
+    /// Fail the file, skipped instead when `skip_failed_files` is set
+    Error,
+}
+
+/// Fixed-size page each output is centered on, e.g. a 4x6 print at 300 DPI is 1800x1200.
+#[derive(Deserialize, Clone, Copy)]
+struct PrintCanvasOptions {
+    width_px: u32,
+    height_px: u32,
+    #[serde(default)]
+    margin_px: u32,
+    /// White when unset
+    #[serde(default)]
+    bg_color: Option<[u8; 4]>,
+}
+
+/// Tile several source photos into the single photo slot of one output.
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(default)]
+struct MosaicOptions {
+    columns: Option<u32>,
+    rows: Option<u32>,
+}
+
+impl MosaicOptions {
+    /// Number of photos per output, `None` meaning every photo goes into one output.
+    fn photos_per_output(&self) -> Option<usize> {
+        match (self.columns, self.rows) {
+            (Some(columns), Some(rows)) => Some((columns.max(1) * rows.max(1)) as usize),
+            _ => None,
+        }
+    }
+
+    fn grid_for(&self, count: usize) -> (u32, u32) {
+        let count = count.max(1) as u32;
+        match (self.columns, self.rows) {
+            (Some(columns), Some(rows)) => (columns.max(1), rows.max(1)),
+            (Some(columns), None) => (columns.max(1), count.div_ceil(columns.max(1))),
+            (None, Some(rows)) => (count.div_ceil(rows.max(1)), rows.max(1)),
+            (None, None) => {
+                let columns = (count as f32).sqrt().ceil() as u32;
+                (columns, count.div_ceil(columns))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OutputFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Tiff,
+    /// Lossless, 8-bit only
+    #[serde(rename = "webp")]
+    WebP,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Png => "png",
+            OutputFormat::Tiff => "tiff",
+            OutputFormat::WebP => "webp",
+        }
+    }
+}
+
+/// JPEG encoder quality, from 1 (smallest files) to 100 (fewest artifacts).
+#[derive(Deserialize, Clone, Copy)]
+#[serde(try_from = "u8")]
+struct JpegQuality(u8);
+
+impl Default for JpegQuality {
+    fn default() -> Self {
+        JpegQuality(75)
+    }
+}
+
+impl TryFrom<u8> for JpegQuality {
+    type Error = String;
+
+    fn try_from(quality: u8) -> Result<Self, String> {
+        if !(1..=100).contains(&quality) {
+            return Err(format!("Invalid JPEG quality {}, expected 1 to 100", quality));
+        }
+        Ok(JpegQuality(quality))
+    }
+}
+
+/// How the entries of an archive are compressed.
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+struct ArchiveCompression {
+    method: ArchiveCompressionMethod,
+    /// Deflate level, the zip library's default when unset
+    level: Option<DeflateLevel>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ArchiveCompressionMethod {
+    /// No compression, much faster for JPEG/PNG/WebP outputs which barely shrink anyway
+    Stored,
+    #[default]
+    Deflated,
+}
+
+/// Deflate level from 0 (fastest) to 9 (smallest archive).
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(try_from = "u8")]
+struct DeflateLevel(u8);
+
+impl TryFrom<u8> for DeflateLevel {
+    type Error = String;
+
+    fn try_from(level: u8) -> Result<Self, String> {
+        if level > 9 {
+            return Err(format!("Invalid compression level {}, expected 0 to 9", level));
+        }
+        Ok(DeflateLevel(level))
+    }
+}
+
+/// Chroma subsampling of JPEG outputs, 4:4:4 keeps colored edges of text overlays sharp.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+enum JpegSubsampling {
+    #[serde(rename = "4:4:4")]
+    Chroma444,
+    #[serde(rename = "4:2:2")]
+    Chroma422,
+    /// Smallest files
+    #[default]
+    #[serde(rename = "4:2:0")]
+    Chroma420,
+}
+
+impl JpegSubsampling {
+    fn sampling_factor(self) -> jpeg_encoder::SamplingFactor {
+        match self {
+            JpegSubsampling::Chroma444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            JpegSubsampling::Chroma422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            JpegSubsampling::Chroma420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// Pixel layout of the saved output.
+///
+/// JPEG is always written as `rgb8` and WebP as the 8-bit variant. The 16-bit types keep the source's tonal range
+/// through compositing for PNG/TIFF, but the pixels of the number, caption and watermark text, `auto_enhance`
+/// and mosaic tiles are processed in 8-bit. Transparent areas are flattened onto white when the layout has no alpha.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OutputColorType {
+    /// `rgba8` when the output has transparent areas, e.g. a template with rounded frame corners, `rgb8` otherwise
+    #[default]
+    Auto,
+    Rgb8,
+    Rgba8,
+    Rgb16,
+    Rgba16,
+}
+
+impl OutputColorType {
+    fn is_16_bit(&self) -> bool {
+        matches!(self, OutputColorType::Rgb16 | OutputColorType::Rgba16)
+    }
+}
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -33,416 +709,4017 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn add_photo_template(
     name: String,
     crop_photo: String,
     crop_number: String,
     template_img: String,
+    default_watermark: Option<String>,
+    font_size: Option<f32>,
+    text_color: Option<String>,
+    number_format: Option<String>,
+    number_padding: Option<i32>,
+    default_output_folder: Option<String>,
+    tags: Option<String>,
+    font_id: Option<String>,
 ) -> Result<PhotoTemplate, String> {
-    let mut connection = establish_connection();
+    let mut connection = establish_connection()?;
+    let (image_width, image_height) = template_image_dimensions(&template_img)?;
+    let default_watermark = default_watermark.unwrap_or_default();
+    parse_default_watermark(&default_watermark)?;
+    let font_size = validate_font_size(font_size.unwrap_or(0.0))?;
+    let text_color = text_color.unwrap_or_else(|| DEFAULT_TEXT_COLOR.to_string());
+    parse_hex_color(&text_color)?;
+    let number_format = number_format.unwrap_or_else(|| DEFAULT_NUMBER_FORMAT.to_string());
+    validate_number_format(&number_format)?;
+    let number_padding = validate_number_padding(number_padding.unwrap_or(0))?;
+    let default_output_folder = default_output_folder.unwrap_or_default();
+    let tags = normalize_template_tags(&tags.unwrap_or_default())?;
+    let font_id = validate_font_id(font_id.unwrap_or_default())?;
     
+    let now = chrono::Utc::now().naive_utc();
     let new_template = NewPhotoTemplate {
         name,
         crop_photo,
         crop_number,
         template_img,
+        image_width,
+        image_height,
+        default_watermark,
+        font_size,
+        text_color,
+        number_format,
+        number_padding,
+        default_output_folder,
+        tags,
+        font_id,
+        created_at: now,
+        updated_at: now,
     };
     
-    // Insert the new template
+    // Insert the new template, getting the stored row back from the same statement
     diesel::insert_into(photo_templates::table)
         .values(&new_template)
-        .execute(&mut connection)
-        .map_err(|e| format!("Error inserting photo template: {}", e))?;
-    
-    // Get the last inserted record
-    use diesel::sql_types::Integer;
-    let last_insert_id: i32 = diesel::select(diesel::dsl::sql::<Integer>("last_insert_rowid()"))
+        .returning(PhotoTemplate::as_returning())
         .get_result(&mut connection)
-        .map_err(|e| format!("Error getting last insert ID: {}", e))?;
-    
-    // Fetch and return the inserted record
-    photo_templates::table
-        .find(last_insert_id)
-        .first(&mut connection)
-        .map_err(|e| format!("Error fetching inserted photo template: {}", e))
+        .map_err(|e| format!("Error inserting photo template: {}", e))
 }
 
 #[tauri::command]
 fn get_photo_templates() -> Result<Vec<PhotoTemplate>, String> {
-    let mut connection = establish_connection();
+    let mut connection = establish_connection()?;
     
     photo_templates::table
         .load::<PhotoTemplate>(&mut connection)
         .map_err(|e| format!("Error loading photo templates: {}", e))
 }
 
+#[derive(Serialize)]
+struct TemplateTag {
+    tag: String,
+    /// Number of templates carrying the tag
+    count: usize,
+}
+
+/// Distinct tags of the library sorted alphabetically, for the template filter.
+#[tauri::command]
+fn list_template_tags() -> Result<Vec<TemplateTag>, String> {
+    let mut connection = establish_connection()?;
+    let template_tags: Vec<String> = photo_templates::table
+        .select(photo_templates::tags)
+        .load(&mut connection)
+        .map_err(|e| format!("Error loading photo templates: {}", e))?;
+
+    let mut counts: std::collections::BTreeMap<String, usize> = std::collections::BTreeMap::new();
+    for tags in template_tags {
+        for tag in parse_template_tags(&tags)? {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    Ok(counts.into_iter().map(|(tag, count)| TemplateTag { tag, count }).collect())
+}
+
+/// Relative aspect ratio difference above which pixel crops no longer line up.
+const ASPECT_RATIO_TOLERANCE: f32 = 0.01;
+
+#[derive(Serialize)]
+struct TemplateUpdate {
+    #[serde(flatten)]
+    template: PhotoTemplate,
+    /// The new image has another aspect ratio, the crops should be remapped
+    crops_may_be_misaligned: bool,
+}
+
+/// Whether two image sizes have different shapes, unknown (zero) sizes never differ.
+fn aspect_ratio_changed((old_width, old_height): (i32, i32), (new_width, new_height): (i32, i32)) -> bool {
+    if old_width <= 0 || old_height <= 0 || new_width <= 0 || new_height <= 0 {
+        return false;
+    }
+    let old_ratio = old_width as f32 / old_height as f32;
+    let new_ratio = new_width as f32 / new_height as f32;
+    (new_ratio - old_ratio).abs() / old_ratio > ASPECT_RATIO_TOLERANCE
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn update_photo_template(
+    app_handle: AppHandle,
     id: i32,
     name: String,
     crop_photo: String,
     crop_number: String,
     template_img: String,
-) -> Result<PhotoTemplate, String> {
-    let mut connection = establish_connection();
-    
-    diesel::update(photo_templates::table.find(id))
-        .set((
-            photo_templates::name.eq(name),
-            photo_templates::crop_photo.eq(crop_photo),
-            photo_templates::crop_number.eq(crop_number),
-            photo_templates::template_img.eq(template_img),
-        ))
-        .execute(&mut connection)
-        .map_err(|e| format!("Error updating photo template: {}", e))?;
-    
-    // Return the updated record
-    photo_templates::table
-        .find(id)
-        .first(&mut connection)
-        .map_err(|e| format!("Error fetching updated photo template: {}", e))
+    default_watermark: Option<String>,
+    font_size: Option<f32>,
+    text_color: Option<String>,
+    number_format: Option<String>,
+    number_padding: Option<i32>,
+    default_output_folder: Option<String>,
+    tags: Option<String>,
+    font_id: Option<String>,
+) -> Result<TemplateUpdate, String> {
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    update_template(
+        &mut connection,
+        &images_dir,
+        id,
+        name,
+        crop_photo,
+        crop_number,
+        template_img,
+        default_watermark,
+        font_size,
+        text_color,
+        number_format,
+        number_padding,
+        default_output_folder,
+        tags,
+        font_id,
+    )
 }
 
-#[tauri::command]
-fn delete_photo_template(id: i32) -> Result<String, String> {
-    let mut connection = establish_connection();
-    
-    let deleted_count = diesel::delete(photo_templates::table.find(id))
-        .execute(&mut connection)
-        .map_err(|e| format!("Error deleting photo template: {}", e))?;
-    
-    if deleted_count > 0 {
-        Ok(format!("Photo template with ID {} deleted successfully", id))
+/// Fields left to `None` keep their current value, a swapped image replaces the old file when nothing else uses it.
+#[allow(clippy::too_many_arguments)]
+fn update_template(
+    connection: &mut SqliteConnection,
+    images_dir: &Path,
+    id: i32,
+    name: String,
+    crop_photo: String,
+    crop_number: String,
+    template_img: String,
+    default_watermark: Option<String>,
+    font_size: Option<f32>,
+    text_color: Option<String>,
+    number_format: Option<String>,
+    number_padding: Option<i32>,
+    default_output_folder: Option<String>,
+    tags: Option<String>,
+    font_id: Option<String>,
+) -> Result<TemplateUpdate, String> {
+    let existing: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    // A swapped image is copied into managed storage unless it already lives there
+    let image_changed = template_img != existing.template_img;
+    let template_img = if image_changed {
+        import_template_image(images_dir, template_img)?
     } else {
-        Err("Photo template not found".to_string())
+        template_img
+    };
+    let (image_width, image_height) = template_image_dimensions(&template_img)?;
+    // Leave the default watermark untouched when the caller doesn't send one
+    let default_watermark = default_watermark.unwrap_or_else(|| existing.default_watermark.clone());
+    parse_default_watermark(&default_watermark)?;
+    let font_size = validate_font_size(font_size.unwrap_or(existing.font_size))?;
+    let text_color = text_color.unwrap_or_else(|| existing.text_color.clone());
+    parse_hex_color(&text_color)?;
+    let number_format = number_format.unwrap_or_else(|| existing.number_format.clone());
+    validate_number_format(&number_format)?;
+    let number_padding = validate_number_padding(number_padding.unwrap_or(existing.number_padding))?;
+    let default_output_folder = default_output_folder.unwrap_or_else(|| existing.default_output_folder.clone());
+    let tags = normalize_template_tags(&tags.unwrap_or_else(|| existing.tags.clone()))?;
+    let font_id = validate_font_id(font_id.unwrap_or_else(|| existing.font_id.clone()))?;
+
+    // History entry and update are stored together, or neither is
+    let result = run_in_transaction(connection, |connection| {
+        let unused_images = record_template_edit(connection, &existing)?;
+
+        diesel::update(photo_templates::table.find(id))
+            .set((
+                photo_templates::name.eq(name),
+                photo_templates::crop_photo.eq(crop_photo),
+                photo_templates::crop_number.eq(crop_number),
+                photo_templates::template_img.eq(&template_img),
+                photo_templates::image_width.eq(image_width),
+                photo_templates::image_height.eq(image_height),
+                photo_templates::default_watermark.eq(default_watermark),
+                photo_templates::font_size.eq(font_size),
+                photo_templates::text_color.eq(text_color),
+                photo_templates::number_format.eq(number_format),
+                photo_templates::number_padding.eq(number_padding),
+                photo_templates::default_output_folder.eq(default_output_folder),
+                photo_templates::tags.eq(tags),
+                photo_templates::font_id.eq(font_id),
+                photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(connection)
+            .map_err(|e| format!("Error updating photo template: {}", e))?;
+
+        let template: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching updated photo template: {}", e))?;
+        Ok((template, unused_images))
+    });
+
+    let (template, mut unused_images) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // Nothing references the freshly imported copy once the transaction rolled back
+            if image_changed {
+                remove_unused_template_images(connection, vec![template_img], images_dir);
+            }
+            return Err(e);
+        }
+    };
+    if image_changed {
+        unused_images.push(existing.template_img.clone());
     }
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(TemplateUpdate {
+        template,
+        crops_may_be_misaligned: aspect_ratio_changed(
+            (existing.image_width, existing.image_height),
+            (image_width, image_height),
+        ),
+    })
 }
 
 #[tauri::command]
-async fn save_template_image(app_handle: AppHandle, file_data: Vec<u8>, filename: String) -> Result<String, String> {
-    // Get app data directory
-    let app_data_dir = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Error getting app data directory: {}", e))?;
-    
-    // Create images directory if it doesn't exist
-    let images_dir = app_data_dir.join("template_images");
-    fs::create_dir_all(&images_dir)
-        .map_err(|e| format!("Error creating images directory: {}", e))?;
-    
-    // Generate unique filename with timestamp
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    
-    let file_extension = Path::new(&filename)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .unwrap_or("jpg");
-    
-    let unique_filename = format!("{}_{}.{}", timestamp, filename.replace('.', "_"), file_extension);
-    let file_path = images_dir.join(&unique_filename);
-    
-    // Save file
-    fs::write(&file_path, file_data)
-        .map_err(|e| format!("Error saving file: {}", e))?;
-    
-    // Return the file path as string
-    Ok(file_path.to_string_lossy().to_string())
+fn patch_photo_template(
+    app_handle: AppHandle,
+    id: i32,
+    fields: PhotoTemplatePatch,
+) -> Result<PhotoTemplate, String> {
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    patch_template(&mut connection, &images_dir, id, fields)
 }
 
-#[tauri::command]
-async fn select_image_folder(app_handle: AppHandle) -> Result<String, String> {
-    use tauri_plugin_dialog::{DialogExt};
-    use std::sync::{Arc, Mutex};
-    use tokio::sync::oneshot;
-    
-    let (tx, rx) = oneshot::channel();
-    let tx = Arc::new(Mutex::new(Some(tx)));
-    
-    app_handle.dialog()
-        .file()
-        .pick_folder(move |folder_path| {
-            if let Ok(mut sender) = tx.lock() {
-                if let Some(tx) = sender.take() {
+/// Update only the fields set in `fields`, validated like in `update_photo_template`.
+fn patch_template(
+    connection: &mut SqliteConnection,
+    images_dir: &Path,
+    id: i32,
+    fields: PhotoTemplatePatch,
+) -> Result<PhotoTemplate, String> {
+    let existing: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let mut changes = fields;
+    if let Some(default_watermark) = &changes.default_watermark {
+        parse_default_watermark(default_watermark)?;
+    }
+    if let Some(font_size) = changes.font_size {
+        validate_font_size(font_size)?;
+    }
+    if let Some(text_color) = &changes.text_color {
+        parse_hex_color(text_color)?;
+    }
+    if let Some(number_format) = &changes.number_format {
+        validate_number_format(number_format)?;
+    }
+    if let Some(number_padding) = changes.number_padding {
+        validate_number_padding(number_padding)?;
+    }
+    if let Some(tags) = &changes.tags {
+        changes.tags = Some(normalize_template_tags(tags)?);
+    }
+    if let Some(font_id) = changes.font_id.take() {
+        changes.font_id = Some(validate_font_id(font_id)?);
+    }
+
+    // Same image handling as update_photo_template, only when an image is actually sent
+    let image_changed = changes.template_img.as_ref().is_some_and(|img| *img != existing.template_img);
+    if image_changed {
+        let template_img = import_template_image(images_dir, changes.template_img.take().unwrap_or_default())?;
+        let (image_width, image_height) = template_image_dimensions(&template_img)?;
+        changes.template_img = Some(template_img);
+        changes.image_width = Some(image_width);
+        changes.image_height = Some(image_height);
+    }
+
+    // History entry and update are stored together, or neither is
+    let result = run_in_transaction(connection, |connection| {
+        let mut unused_images = Vec::new();
+        if !changes.is_empty() {
+            unused_images = record_template_edit(connection, &existing)?;
+            diesel::update(photo_templates::table.find(id))
+                .set((&changes, photo_templates::updated_at.eq(chrono::Utc::now().naive_utc())))
+                .execute(connection)
+                .map_err(|e| format!("Error updating photo template: {}", e))?;
+        }
+
+        let template: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching updated photo template: {}", e))?;
+        Ok((template, unused_images))
+    });
+
+    let (template, mut unused_images) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // Nothing references the freshly imported copy once the transaction rolled back
+            if let Some(template_img) = changes.template_img.filter(|_| image_changed) {
+                remove_unused_template_images(connection, vec![template_img], images_dir);
+            }
+            return Err(e);
+        }
+    };
+    if image_changed {
+        unused_images.push(existing.template_img);
+    }
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(template)
+}
+
+/// Swap the background image of several templates at once, e.g. after a rebrand.
+///
+/// The image is stored once and shared by all of them; replaced images that neither a
+/// template nor the edit history still references are deleted afterwards.
+#[tauri::command]
+fn bulk_set_template_image(
+    app_handle: AppHandle,
+    ids: Vec<i32>,
+    new_image_path: String,
+) -> Result<Vec<PhotoTemplate>, String> {
+    let mut connection = establish_connection()?;
+    let images_dir = template_images_dir(&app_handle)?;
+    set_templates_image(&mut connection, &images_dir, &ids, new_image_path)
+}
+
+fn set_templates_image(
+    connection: &mut SqliteConnection,
+    images_dir: &Path,
+    ids: &[i32],
+    new_image_path: String,
+) -> Result<Vec<PhotoTemplate>, String> {
+    let (image_width, image_height) = template_image_dimensions(&new_image_path)?;
+    let template_img = import_template_image(images_dir, new_image_path)?;
+
+    let result = run_in_transaction(connection, |connection| {
+        let mut unused_images = Vec::new();
+        let mut updated = Vec::new();
+        for id in ids {
+            let existing: PhotoTemplate = photo_templates::table
+                .find(id)
+                .first(connection)
+                .map_err(|e| format!("Error loading template {}: {}", id, e))?;
+            unused_images.extend(record_template_edit(connection, &existing)?);
+
+            diesel::update(photo_templates::table.find(id))
+                .set((
+                    photo_templates::template_img.eq(&template_img),
+                    photo_templates::image_width.eq(image_width),
+                    photo_templates::image_height.eq(image_height),
+                    photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+                ))
+                .execute(connection)
+                .map_err(|e| format!("Error updating photo template: {}", e))?;
+            updated.push(
+                photo_templates::table
+                    .find(id)
+                    .first(connection)
+                    .map_err(|e| format!("Error fetching updated photo template: {}", e))?,
+            );
+            unused_images.push(existing.template_img);
+        }
+        Ok((updated, unused_images))
+    });
+
+    let (updated, unused_images) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // Nothing references the freshly imported copy once the transaction rolled back
+            remove_unused_template_image(connection, &template_img, images_dir)?;
+            return Err(e);
+        }
+    };
+
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(updated)
+}
+
+/// Restore the state a template had before its most recent edit.
+#[tauri::command]
+fn revert_template(app_handle: AppHandle, id: i32) -> Result<PhotoTemplate, String> {
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    revert_last_edit(&mut connection, id, &images_dir)
+}
+
+/// Replace a template with its latest history entry, which is removed from the history.
+fn revert_last_edit(connection: &mut SqliteConnection, id: i32, images_dir: &Path) -> Result<PhotoTemplate, String> {
+    let (current, reverted) = run_in_transaction(connection, |connection| {
+        let current: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error loading template: {}", e))?;
+        let edit: TemplateEdit = template_edits::table
+            .filter(template_edits::template_id.eq(id))
+            .order(template_edits::id.desc())
+            .first(connection)
+            .optional()
+            .map_err(|e| format!("Error loading template edit history: {}", e))?
+            .ok_or(format!("No edit to revert for template {}", id))?;
+
+        diesel::update(photo_templates::table.find(id))
+            .set((
+                photo_templates::name.eq(&edit.name),
+                photo_templates::crop_photo.eq(&edit.crop_photo),
+                photo_templates::crop_number.eq(&edit.crop_number),
+                photo_templates::template_img.eq(&edit.template_img),
+                photo_templates::image_width.eq(edit.image_width),
+                photo_templates::image_height.eq(edit.image_height),
+                photo_templates::default_watermark.eq(&edit.default_watermark),
+                photo_templates::font_size.eq(edit.font_size),
+                photo_templates::text_color.eq(&edit.text_color),
+                photo_templates::number_format.eq(&edit.number_format),
+                photo_templates::number_padding.eq(edit.number_padding),
+                photo_templates::default_output_folder.eq(&edit.default_output_folder),
+                photo_templates::tags.eq(&edit.tags),
+                photo_templates::font_id.eq(&edit.font_id),
+                photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(connection)
+            .map_err(|e| format!("Error updating photo template: {}", e))?;
+        diesel::delete(template_edits::table.find(edit.id))
+            .execute(connection)
+            .map_err(|e| format!("Error deleting template edit: {}", e))?;
+
+        let reverted: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching reverted photo template: {}", e))?;
+        Ok((current, reverted))
+    })?;
+
+    if current.template_img != reverted.template_img {
+        remove_unused_template_images(connection, vec![current.template_img], images_dir);
+    }
+
+    Ok(reverted)
+}
+
+/// Create a copy of a template named "{original} (copy)", with its own copy of the template image
+/// so deleting either template never breaks the other.
+#[tauri::command]
+fn duplicate_photo_template(app_handle: AppHandle, id: i32) -> Result<PhotoTemplate, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let image_data = fs::read(&template.template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template.template_img, e))?;
+    let image_filename = Path::new(&template.template_img)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "template.jpg".to_string());
+    let images_dir = template_images_dir(&app_handle)?;
+    let template_img = store_template_image(&images_dir, &image_data, &image_filename)?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let new_template = NewPhotoTemplate {
+        name: format!("{} (copy)", template.name),
+        crop_photo: template.crop_photo,
+        crop_number: template.crop_number,
+        template_img: template_img.to_string_lossy().to_string(),
+        image_width: template.image_width,
+        image_height: template.image_height,
+        default_watermark: template.default_watermark,
+        font_size: template.font_size,
+        text_color: template.text_color,
+        number_format: template.number_format,
+        number_padding: template.number_padding,
+        default_output_folder: template.default_output_folder,
+        tags: template.tags,
+        font_id: template.font_id,
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(photo_templates::table)
+        .values(&new_template)
+        .returning(PhotoTemplate::as_returning())
+        .get_result(&mut connection)
+        .map_err(|e| {
+            // Don't leave the copied image behind when the row can't be stored
+            let _ = fs::remove_file(&template_img);
+            format!("Error inserting photo template: {}", e)
+        })
+}
+
+/// Delete a template with its edit history, and its image file when no other template uses it.
+#[tauri::command]
+fn delete_photo_template(app_handle: AppHandle, id: i32) -> Result<String, String> {
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    delete_template(&mut connection, id, &images_dir)?;
+    Ok(format!("Photo template with ID {} deleted successfully", id))
+}
+
+/// Template and edit history are deleted together, so a failure never leaves one without the other.
+fn delete_template(connection: &mut SqliteConnection, id: i32, images_dir: &Path) -> Result<(), String> {
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(connection)
+        .map_err(|_| "Photo template not found".to_string())?;
+
+    run_in_transaction(connection, |connection| {
+        diesel::delete(template_edits::table.filter(template_edits::template_id.eq(id)))
+            .execute(connection)
+            .map_err(|e| format!("Error deleting template edit history: {}", e))?;
+
+        let deleted_count = diesel::delete(photo_templates::table.find(id))
+            .execute(connection)
+            .map_err(|e| format!("Error deleting photo template: {}", e))?;
+        if deleted_count == 0 {
+            return Err("Photo template not found".to_string());
+        }
+        Ok(())
+    })?;
+
+    remove_unused_template_image(connection, &template.template_img, images_dir)
+}
+
+#[tauri::command]
+fn export_template_svg(id: i32) -> Result<String, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let (crop_photo, crop_number) = parse_template_crops(&template)?;
+    let (width, height) = image::image_dimensions(&template.template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template.template_img, e))?;
+
+    Ok(svg::template_to_svg(
+        &template.template_img,
+        width,
+        height,
+        Some(&crop_photo),
+        crop_number.as_ref(),
+    ))
+}
+
+#[tauri::command]
+fn import_template_svg(app_handle: AppHandle, id: i32, svg_content: String) -> Result<PhotoTemplate, String> {
+    let (crop_photo, crop_number) = svg::parse_crop_rects(&svg_content)?;
+    let crop_photo = crop_photo
+        .ok_or(format!("SVG has no <rect id=\"{}\">", svg::PHOTO_RECT_ID))?;
+
+    let crop_photo_json = serde_json::to_string(&crop_photo)
+        .map_err(|e| format!("Error serializing crop coordinates: {}", e))?;
+    let crop_number_json = match crop_number {
+        Some(crop) => serde_json::to_string(&crop)
+            .map_err(|e| format!("Error serializing crop_number coordinates: {}", e))?,
+        None => String::new(),
+    };
+
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    let existing: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+    let (template, unused_images) = run_in_transaction(&mut connection, |connection| {
+        let unused_images = record_template_edit(connection, &existing)?;
+
+        diesel::update(photo_templates::table.find(id))
+            .set((
+                photo_templates::crop_photo.eq(crop_photo_json),
+                photo_templates::crop_number.eq(crop_number_json),
+                photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(connection)
+            .map_err(|e| format!("Error updating photo template: {}", e))?;
+
+        let template: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching updated photo template: {}", e))?;
+        Ok((template, unused_images))
+    })?;
+    remove_unused_template_images(&mut connection, unused_images, &images_dir);
+    Ok(template)
+}
+
+#[tauri::command]
+fn scale_crops_to(template_id: i32, preview_width: u32, preview_height: u32) -> Result<TemplateCrops, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(template_id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let (crop_photo, crop_number) = parse_template_crops(&template)?;
+    let (width, height) = image::image_dimensions(&template.template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template.template_img, e))?;
+    if width == 0 || height == 0 {
+        return Err("Template image has no pixels".to_string());
+    }
+
+    let scale_x = preview_width as f32 / width as f32;
+    let scale_y = preview_height as f32 / height as f32;
+
+    Ok(TemplateCrops {
+        crop_photo: crop_photo.scaled(scale_x, scale_y),
+        crop_number: crop_number.map(|crop| crop.scaled(scale_x, scale_y)),
+    })
+}
+
+#[tauri::command]
+fn remap_crops(app_handle: AppHandle, id: i32, new_width: u32, new_height: u32) -> Result<PhotoTemplate, String> {
+    let mut connection = establish_connection()?;
+    remap_template_crops(&mut connection, id, new_width, new_height, &template_images_dir(&app_handle)?)
+}
+
+/// Scale the crops of a template from its stored image dimensions to `new_width` x `new_height`.
+///
+/// The stored dimensions become the new ones while the image itself is left as it is: they
+/// describe the image the crops are drawn for, which is then swapped for the resized version
+/// with `update_photo_template` (that reads the dimensions of the new file).
+fn remap_template_crops(
+    connection: &mut SqliteConnection,
+    id: i32,
+    new_width: u32,
+    new_height: u32,
+    images_dir: &Path,
+) -> Result<PhotoTemplate, String> {
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    // Templates created before dimensions were stored fall back to the current image size
+    let (old_width, old_height) = if template.image_width > 0 && template.image_height > 0 {
+        (template.image_width, template.image_height)
+    } else {
+        template_image_dimensions(&template.template_img)?
+    };
+    if new_width == 0 || new_height == 0 {
+        return Err("New dimensions must be greater than zero".to_string());
+    }
+
+    let scale_x = new_width as f32 / old_width as f32;
+    let scale_y = new_height as f32 / old_height as f32;
+    let (crop_photo, crop_number) = parse_template_crops(&template)?;
+
+    let crop_photo = serde_json::to_string(&crop_photo.scaled(scale_x, scale_y))
+        .map_err(|e| format!("Error serializing crop coordinates: {}", e))?;
+    let crop_number = match crop_number {
+        Some(crop) => serde_json::to_string(&crop.scaled(scale_x, scale_y))
+            .map_err(|e| format!("Error serializing crop_number coordinates: {}", e))?,
+        None => String::new(),
+    };
+
+    let (template, unused_images) = run_in_transaction(connection, |connection| {
+        let unused_images = record_template_edit(connection, &template)?;
+        diesel::update(photo_templates::table.find(id))
+            .set((
+                photo_templates::crop_photo.eq(crop_photo),
+                photo_templates::crop_number.eq(crop_number),
+                photo_templates::image_width.eq(new_width as i32),
+                photo_templates::image_height.eq(new_height as i32),
+                photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(connection)
+            .map_err(|e| format!("Error updating photo template: {}", e))?;
+
+        let template: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching updated photo template: {}", e))?;
+        Ok((template, unused_images))
+    })?;
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(template)
+}
+
+#[tauri::command]
+fn check_template_images() -> Result<Vec<TemplateImageReport>, String> {
+    let mut connection = establish_connection()?;
+    let templates = photo_templates::table
+        .load::<PhotoTemplate>(&mut connection)
+        .map_err(|e| format!("Error loading photo templates: {}", e))?;
+
+    let reports = templates
+        .into_iter()
+        .map(|template| {
+            let (status, error) = if !Path::new(&template.template_img).is_file() {
+                (TemplateImageStatus::Missing, None)
+            } else {
+                match image::open(&template.template_img) {
+                    Ok(_) => (TemplateImageStatus::Ok, None),
+                    Err(e) => (TemplateImageStatus::Corrupt, Some(e.to_string())),
+                }
+            };
+
+            TemplateImageReport {
+                id: template.id,
+                name: template.name,
+                template_img: template.template_img,
+                status,
+                error,
+            }
+        })
+        .collect();
+
+    Ok(reports)
+}
+
+#[tauri::command]
+fn sync_templates_from_config(app_handle: AppHandle, path: String) -> Result<TemplateSyncReport, String> {
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Error reading config file {}: {}", path, e))?;
+    let config: TemplatesConfig = serde_json::from_str(&content)
+        .map_err(|e| format!("Error parsing config file {}: {}", path, e))?;
+
+    let images_dir = template_images_dir(&app_handle)?;
+    let mut connection = establish_connection()?;
+    sync_templates(&mut connection, config, &images_dir)
+}
+
+/// Upsert the templates of `config` by name, all of them or none when one fails.
+fn sync_templates(
+    connection: &mut SqliteConnection,
+    config: TemplatesConfig,
+    images_dir: &Path,
+) -> Result<TemplateSyncReport, String> {
+    // Config images are copied into managed storage, like the ones picked in the editor
+    let mut imported_images = Vec::new();
+    let result = run_in_transaction(connection, |connection| {
+        let mut report = TemplateSyncReport::default();
+        let mut unused_images = Vec::new();
+
+        for entry in config.templates {
+            let crop_photo = serde_json::to_string(&entry.crop_photo)
+                .map_err(|e| format!("Error serializing crop coordinates: {}", e))?;
+            let crop_number = match &entry.crop_number {
+                Some(crop) => serde_json::to_string(crop)
+                    .map_err(|e| format!("Error serializing crop_number coordinates: {}", e))?,
+                None => String::new(),
+            };
+
+            let existing: Option<PhotoTemplate> = photo_templates::table
+                .filter(photo_templates::name.eq(&entry.name))
+                .first(connection)
+                .optional()
+                .map_err(|e| format!("Error loading template {}: {}", entry.name, e))?;
+
+            match existing {
+                Some(template) => {
+                    // A config still pointing at the source of the current image doesn't import it again
+                    let template_img = match entry.template_img {
+                        Some(template_img) if !same_file_content(&template_img, &template.template_img) => {
+                            import_synced_image(images_dir, template_img, &mut imported_images)?
+                        }
+                        _ => template.template_img.clone(),
+                    };
+                    if template.crop_photo == crop_photo
+                        && template.crop_number == crop_number
+                        && template.template_img == template_img
+                    {
+                        report.unchanged.push(entry.name);
+                        continue;
+                    }
+
+                    let (image_width, image_height) = template_image_dimensions(&template_img)?;
+                    unused_images.extend(record_template_edit(connection, &template)?);
+                    if template_img != template.template_img {
+                        unused_images.push(template.template_img.clone());
+                    }
+                    diesel::update(photo_templates::table.find(template.id))
+                        .set((
+                            photo_templates::crop_photo.eq(crop_photo),
+                            photo_templates::crop_number.eq(crop_number),
+                            photo_templates::template_img.eq(template_img),
+                            photo_templates::image_width.eq(image_width),
+                            photo_templates::image_height.eq(image_height),
+                            photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+                        ))
+                        .execute(connection)
+                        .map_err(|e| format!("Error updating template {}: {}", entry.name, e))?;
+                    report.updated.push(entry.name);
+                }
+                None => {
+                    let template_img = entry.template_img
+                        .ok_or(format!("Template {} is new and needs a template_img", entry.name))?;
+                    let template_img = import_synced_image(images_dir, template_img, &mut imported_images)?;
+                    let (image_width, image_height) = template_image_dimensions(&template_img)?;
+
+                    diesel::insert_into(photo_templates::table)
+                        .values(&NewPhotoTemplate {
+                            name: entry.name.clone(),
+                            crop_photo,
+                            crop_number,
+                            template_img,
+                            image_width,
+                            image_height,
+                            default_watermark: String::new(),
+                            font_size: 0.0,
+                            text_color: DEFAULT_TEXT_COLOR.to_string(),
+                            number_format: DEFAULT_NUMBER_FORMAT.to_string(),
+                            number_padding: 0,
+                            default_output_folder: String::new(),
+                            tags: "[]".to_string(),
+                            font_id: String::new(),
+                            created_at: chrono::Utc::now().naive_utc(),
+                            updated_at: chrono::Utc::now().naive_utc(),
+                        })
+                        .execute(connection)
+                        .map_err(|e| format!("Error inserting template {}: {}", entry.name, e))?;
+                    report.inserted.push(entry.name);
+                }
+            }
+        }
+
+        Ok((report, unused_images))
+    });
+
+    let (report, unused_images) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // Nothing references the copies once the transaction rolled back
+            remove_unused_template_images(connection, imported_images, images_dir);
+            return Err(e);
+        }
+    };
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(report)
+}
+
+/// Import a config image, keeping track of the copies made so a failed sync can remove them.
+fn import_synced_image(images_dir: &Path, template_img: String, imported_images: &mut Vec<String>) -> Result<String, String> {
+    let stored = import_template_image(images_dir, template_img.clone())?;
+    if stored != template_img {
+        imported_images.push(stored.clone());
+    }
+    Ok(stored)
+}
+
+/// Whether two paths name the same file or files with identical bytes.
+fn same_file_content(a: &str, b: &str) -> bool {
+    a == b || fs::read(a).is_ok_and(|a| fs::read(b).is_ok_and(|b| a == b))
+}
+
+/// Hex SHA-256 over every template field and the template image content,
+/// changes whenever anything that affects rendering changes.
+#[tauri::command]
+fn template_config_hash(id: i32) -> Result<String, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let image_bytes = fs::read(&template.template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template.template_img, e))?;
+
+    let mut hasher = Sha256::new();
+    for field in [
+        &template.name,
+        &template.crop_photo,
+        &template.crop_number,
+        &template.template_img,
+        &template.default_watermark,
+        &template.text_color,
+        &template.number_format,
+        &template.font_id,
+    ] {
+        // Length-prefixed so moving characters between fields changes the hash
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.update(template.font_size.to_le_bytes());
+    hasher.update(template.number_padding.to_le_bytes());
+    hasher.update(Sha256::digest(&image_bytes));
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Canonical `CropCoordinates` JSON for crop data coming from the frontend, ready to be stored.
+#[tauri::command]
+fn normalize_crop_json(raw: String) -> Result<String, String> {
+    let crop = parse_crop_lenient(&raw)?;
+    serde_json::to_string(&crop).map_err(|e| format!("Error serializing crop coordinates: {}", e))
+}
+
+#[tauri::command]
+fn reset_template_crops(app_handle: AppHandle, id: i32) -> Result<PhotoTemplate, String> {
+    let mut connection = establish_connection()?;
+    reset_crops_to_full_image(&mut connection, id, &template_images_dir(&app_handle)?)
+}
+
+/// Make the photo crop cover the whole template image and drop the number crop.
+fn reset_crops_to_full_image(connection: &mut SqliteConnection, id: i32, images_dir: &Path) -> Result<PhotoTemplate, String> {
+    let template: PhotoTemplate = photo_templates::table
+        .find(id)
+        .first(connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let (width, height) = image::image_dimensions(&template.template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template.template_img, e))?;
+    let full_image = CropCoordinates {
+        x: 0.0,
+        y: 0.0,
+        width: width as f32,
+        height: height as f32,
+    };
+    let crop_photo = serde_json::to_string(&full_image)
+        .map_err(|e| format!("Error serializing crop coordinates: {}", e))?;
+
+    let (template, unused_images) = run_in_transaction(connection, |connection| {
+        let unused_images = record_template_edit(connection, &template)?;
+        diesel::update(photo_templates::table.find(id))
+            .set((
+                photo_templates::crop_photo.eq(crop_photo),
+                photo_templates::crop_number.eq(""),
+                photo_templates::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(connection)
+            .map_err(|e| format!("Error updating photo template: {}", e))?;
+
+        let template: PhotoTemplate = photo_templates::table
+            .find(id)
+            .first(connection)
+            .map_err(|e| format!("Error fetching updated photo template: {}", e))?;
+        Ok((template, unused_images))
+    })?;
+    remove_unused_template_images(connection, unused_images, images_dir);
+    Ok(template)
+}
+
+/// Run image work on the blocking thread pool, so a long render doesn't hold up the async
+/// runtime the other commands are served from.
+async fn run_blocking<T: Send + 'static>(task: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<T, String> {
+    tauri::async_runtime::spawn_blocking(task)
+        .await
+        .unwrap_or_else(|e| Err(format!("Background task failed: {}", e)))
+}
+
+#[tauri::command]
+async fn list_source_thumbnails(folder: String, max_dimension: u32) -> Result<Vec<SourceThumbnail>, String> {
+    run_blocking(move || {
+        let max_dimension = max_dimension.clamp(1, MAX_THUMBNAIL_DIMENSION);
+        let image_files = find_image_files(&folder, &GenerationOptions::default())?;
+
+        image_files
+            .iter()
+            .enumerate()
+            .map(|(index, image_file)| {
+                let thumbnail = load_image(&image_file.to_string_lossy())?
+                    .thumbnail(max_dimension, max_dimension);
+                let stem = image_file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+
+                Ok(SourceThumbnail {
+                    filename: image_file.file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    number: extract_number_from_filename(stem, index + 1, NoNumberBehavior::Sequence)?,
+                    data_url: encode_data_url(&thumbnail, image::ImageOutputFormat::Jpeg(70))?,
+                })
+            })
+            .collect()
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct PageEstimate {
+    image_count: usize,
+    photos_per_page: usize,
+    /// Including a partially filled last page
+    pages: usize,
+}
+
+/// Number of outputs a run over `folder` would produce, without rendering anything.
+#[tauri::command]
+fn estimate_pages(template_id: i32, folder: String, options: Option<GenerationOptions>) -> Result<PageEstimate, String> {
+    let options = options.unwrap_or_default();
+    let mut connection = establish_connection()?;
+    photo_templates::table
+        .find(template_id)
+        .first::<PhotoTemplate>(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+
+    let image_count = find_image_files(&folder, &options)?.len();
+    // Templates have a single photo slot, a mosaic grid packs several photos into it
+    let photos_per_page = options.photos_per_output(image_count);
+
+    Ok(PageEstimate {
+        image_count,
+        photos_per_page,
+        pages: image_count.div_ceil(photos_per_page),
+    })
+}
+
+#[derive(Serialize)]
+struct MemoryEstimate {
+    threads: usize,
+    /// Approximate peak memory of the run, in bytes
+    estimated_bytes: u64,
+    /// Memory the system can still hand out, in bytes
+    available_bytes: u64,
+    /// Set when the estimate doesn't fit in the available memory
+    warning: Option<String>,
+}
+
+/// Bytes per decoded pixel, images are handled as 8-bit RGBA during compositing
+const DECODED_BYTES_PER_PIXEL: u64 = 4;
+
+/// Approximate peak memory of a run over `folder` with `max_threads` render threads (the run option
+/// of the same name, one per core when absent), compared to the memory currently available so the
+/// frontend can warn before running out of it.
+#[tauri::command]
+fn estimate_memory(template_id: i32, folder: String, max_threads: Option<usize>) -> Result<MemoryEstimate, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(template_id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+    let template_dimensions = image::image_dimensions(&template.template_img)
+        .map_err(|e| format!("Error reading template image dimensions: {}", e))?;
+
+    // Unreadable files fail during the run anyway, they don't change the estimate
+    let largest_source = find_image_files(&folder, &GenerationOptions::default())?
+        .iter()
+        .filter_map(|path| image::image_dimensions(path).ok())
+        .max_by_key(|&(width, height)| width as u64 * height as u64)
+        .unwrap_or((0, 0));
+
+    let threads = max_threads.unwrap_or_else(rayon::current_num_threads).max(1);
+    let estimated_bytes = estimate_peak_memory(template_dimensions, largest_source, threads);
+
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    let available_bytes = system.available_memory();
+
+    let warning = (estimated_bytes > available_bytes).then(|| {
+        format!(
+            "This run may need about {} MB but only {} MB are available, lower max_threads in the generation options",
+            estimated_bytes / 1_000_000,
+            available_bytes / 1_000_000
+        )
+    });
+
+    Ok(MemoryEstimate { threads, estimated_bytes, available_bytes, warning })
+}
+
+/// Every render thread holds its own decoded copy of the template, plus the largest decoded source.
+fn estimate_peak_memory(template_dimensions: (u32, u32), largest_source: (u32, u32), threads: usize) -> u64 {
+    let decoded_size = |(width, height): (u32, u32)| width as u64 * height as u64 * DECODED_BYTES_PER_PIXEL;
+    decoded_size(template_dimensions) * threads as u64 + decoded_size(largest_source)
+}
+
+#[tauri::command]
+fn sample_region_color(template_id: i32, region: CropCoordinates) -> Result<RegionColor, String> {
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(template_id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+    let template_image = load_image(&template.template_img)?.to_rgba8();
+
+    // Clamp the region to the image so partially outside rectangles still sample something
+    let x_start = (region.x.max(0.0) as u32).min(template_image.width());
+    let y_start = (region.y.max(0.0) as u32).min(template_image.height());
+    let x_end = ((region.x + region.width).max(0.0) as u32).min(template_image.width());
+    let y_end = ((region.y + region.height).max(0.0) as u32).min(template_image.height());
+    if x_end <= x_start || y_end <= y_start {
+        return Err("Region does not overlap the template image".to_string());
+    }
+
+    let mut sums = [0u64; 4];
+    for y in y_start..y_end {
+        for x in x_start..x_end {
+            let pixel = template_image.get_pixel(x, y);
+            for (sum, channel) in sums.iter_mut().zip(pixel.0) {
+                *sum += channel as u64;
+            }
+        }
+    }
+    let count = ((x_end - x_start) * (y_end - y_start)) as u64;
+    let color = sums.map(|sum| (sum / count) as u8);
+
+    let luminance = 0.2126 * color[0] as f32 + 0.7152 * color[1] as f32 + 0.0722 * color[2] as f32;
+    let suggested_text_color = if luminance > 128.0 { "#000000" } else { "#FFFFFF" };
+
+    Ok(RegionColor {
+        color,
+        suggested_text_color: suggested_text_color.to_string(),
+    })
+}
+
+/// Composite a single sample photo into a template, for instant feedback while adjusting its crops.
+///
+/// Nothing is written to disk and no folder is scanned. The sample is rendered as a preview, so
+/// a watermark limited to previews shows on it and one limited to full-res outputs doesn't.
+#[tauri::command]
+async fn preview_template(
+    template_id: i32,
+    sample_image_path: String,
+    options: Option<GenerationOptions>,
+) -> Result<String, String> {
+    run_blocking(move || {
+        let options = options.unwrap_or_default();
+        let mut connection = establish_connection()?;
+        let template: PhotoTemplate = photo_templates::table
+            .find(template_id)
+            .first(&mut connection)
+            .map_err(|e| format!("Error loading template: {}", e))?;
+
+        let preview = render_sample_preview(&template, Path::new(&sample_image_path), &options)?;
+        encode_data_url(&preview, image::ImageOutputFormat::Png)
+    })
+    .await
+}
+
+fn render_sample_preview(template: &PhotoTemplate, sample_path: &Path, options: &GenerationOptions) -> Result<DynamicImage, String> {
+    let prepared = prepare_template(template, options)?;
+    let (preview, _) = render_output(&prepared, &[sample_path.to_path_buf()], 0, options, true)?;
+    Ok(preview)
+}
+
+/// Composite a sample photo into one photo slot of a template with the given fit settings and
+/// return that slot as a PNG data URL, to compare fit modes without running a generation.
+#[tauri::command]
+async fn preview_fit(
+    template_id: i32,
+    slot_index: usize,
+    source_path: String,
+    fit: FitMode,
+    anchor: Option<CropFocus>,
+    crop_focus: Option<CropFocus>,
+) -> Result<String, String> {
+    run_blocking(move || {
+        let mut connection = establish_connection()?;
+        let template: PhotoTemplate = photo_templates::table
+            .find(template_id)
+            .first(&mut connection)
+            .map_err(|e| format!("Error loading template: {}", e))?;
+
+        // Templates have a single photo slot
+        if slot_index != 0 {
+            return Err(format!("Template {} has no photo slot {}", template_id, slot_index));
+        }
+
+        let options = GenerationOptions::default();
+        let prepared = prepare_template(&template, &options)?;
+        let crop = prepared.crop_photo;
+        let (slot_width, slot_height) = (crop.width as u32, crop.height as u32);
+        if slot_width == 0 || slot_height == 0 {
+            return Err("Photo slot is empty".to_string());
+        }
+
+        let source_image = load_source_image(Path::new(&source_path), &options, None)?;
+        let slot_image = fit_to_slot(
+            &source_image,
+            slot_width,
+            slot_height,
+            fit,
+            anchor.unwrap_or_default(),
+            crop_focus.unwrap_or_default(),
+        );
+        let composite = composite_images(&prepared.image, &slot_image, &crop, &options)?;
+        let slot = composite.crop_imm(crop.x.max(0.0) as u32, crop.y.max(0.0) as u32, slot_width, slot_height);
+        encode_data_url(&slot, image::ImageOutputFormat::Png)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn render_before_after(template_id: i32, source_path: String) -> Result<String, String> {
+    run_blocking(move || {
+        let mut connection = establish_connection()?;
+        let template: PhotoTemplate = photo_templates::table
+            .find(template_id)
+            .first(&mut connection)
+            .map_err(|e| format!("Error loading template: {}", e))?;
+
+        let options = GenerationOptions::default();
+        let prepared = prepare_template(&template, &options)?;
+        let (after, _) = render_output(&prepared, &[PathBuf::from(&source_path)], 0, &options, false)?;
+
+        // The raw photo is fit into a panel the size of the result, centered on white
+        let (panel_width, panel_height) = (after.width(), after.height());
+        let before = resize_image(load_image(&source_path)?, panel_width, panel_height, true);
+        let divider_width = (panel_width / 100).max(4);
+
+        let mut comparison = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+            panel_width * 2 + divider_width,
+            panel_height,
+            Rgba([255, 255, 255, 255]),
+        ));
+        image::imageops::overlay(
+            &mut comparison,
+            &before,
+            ((panel_width - before.width()) / 2) as i64,
+            ((panel_height - before.height()) / 2) as i64,
+        );
+        let divider = ImageBuffer::from_pixel(divider_width, panel_height, Rgba([64u8, 64, 64, 255]));
+        image::imageops::overlay(&mut comparison, &DynamicImage::ImageRgba8(divider), panel_width as i64, 0);
+        image::imageops::overlay(&mut comparison, &after, (panel_width + divider_width) as i64, 0);
+
+        encode_data_url(&comparison, image::ImageOutputFormat::Png)
+    })
+    .await
+}
+
+#[derive(Serialize)]
+struct TemplateRegion {
+    data_url: String,
+    /// Output pixels per template pixel, 1 when the region already fits `out_max_dimension`
+    scale: f32,
+}
+
+/// Crop the template image to a rectangle, downscaled so its longest side fits `out_max_dimension`.
+#[tauri::command]
+async fn render_template_region(
+    template_id: i32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    out_max_dimension: u32,
+) -> Result<TemplateRegion, String> {
+    run_blocking(move || {
+        let mut connection = establish_connection()?;
+        let template: PhotoTemplate = photo_templates::table
+            .find(template_id)
+            .first(&mut connection)
+            .map_err(|e| format!("Error loading template: {}", e))?;
+
+        let template_image = load_image(&template.template_img)?;
+        let out_of_bounds = x.checked_add(width).is_none_or(|right| right > template_image.width())
+            || y.checked_add(height).is_none_or(|bottom| bottom > template_image.height());
+        if width == 0 || height == 0 || out_of_bounds {
+            return Err(format!(
+                "Region {}x{} at ({}, {}) is outside the {}x{} template",
+                width, height, x, y, template_image.width(), template_image.height()
+            ));
+        }
+
+        let region = template_image.crop_imm(x, y, width, height);
+        let out_max_dimension = out_max_dimension.max(1);
+        let region = if width.max(height) > out_max_dimension {
+            region.resize(out_max_dimension, out_max_dimension, image::imageops::FilterType::Lanczos3)
+        } else {
+            region
+        };
+
+        Ok(TemplateRegion {
+            scale: region.width() as f32 / width as f32,
+            data_url: encode_data_url(&region, image::ImageOutputFormat::Png)?,
+        })
+    })
+    .await
+}
+
+/// Largest side of the thumbnails embedded in proof sheets, enough for a 4-column print
+const PROOF_THUMBNAIL_DIMENSION: u32 = 600;
+const MAX_PROOF_GRID: u32 = 10;
+/// Directory of the app data dir the proof sheets are written to
+const PROOFS_DIR: &str = "proofs";
+
+/// Lay the photos of `folder` out as numbered thumbnails on A4 pages for client selection.
+///
+/// With a template the photos are composited first, otherwise the raw photos are used.
+/// Returns the path of the PDF, written under the app data directory.
+#[tauri::command]
+async fn make_proof_pdf(
+    app_handle: AppHandle,
+    template_id: Option<i32>,
+    folder: String,
+    columns: u32,
+    rows: u32,
+) -> Result<String, String> {
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    let pdf_path = run_blocking(move || write_proof_pdf(template_id, &folder, columns, rows, &app_data_dir.join(PROOFS_DIR))).await?;
+    Ok(pdf_path.to_string_lossy().to_string())
+}
+
+fn write_proof_pdf(
+    template_id: Option<i32>,
+    folder: &str,
+    columns: u32,
+    rows: u32,
+    proofs_dir: &Path,
+) -> Result<PathBuf, String> {
+    if !(1..=MAX_PROOF_GRID).contains(&columns) || !(1..=MAX_PROOF_GRID).contains(&rows) {
+        return Err(format!("Proof grid must be 1 to {} columns and rows", MAX_PROOF_GRID));
+    }
+
+    let options = GenerationOptions::default();
+    let prepared = match template_id {
+        Some(template_id) => {
+            let mut connection = establish_connection()?;
+            let template: PhotoTemplate = photo_templates::table
+                .find(template_id)
+                .first(&mut connection)
+                .map_err(|e| format!("Error loading template: {}", e))?;
+            Some(prepare_template(&template, &options)?)
+        }
+        None => None,
+    };
+
+    let image_files = find_image_files(folder, &options)?;
+    if image_files.is_empty() {
+        return Err("No image files found in the selected folder".to_string());
+    }
+
+    let cells = image_files
+        .par_iter()
+        .enumerate()
+        .map(|(index, image_file)| {
+            let (image, number) = match &prepared {
+                Some(prepared) => render_output(prepared, std::slice::from_ref(image_file), index, &options, true)?,
+                None => {
+                    let stem = image_file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                    (
+                        load_source_image(image_file, &options, None)?,
+                        extract_number_from_filename(stem, index + 1, NoNumberBehavior::Sequence)?,
+                    )
+                }
+            };
+            let thumbnail = DynamicImage::ImageRgb8(
+                flatten_alpha(&image.thumbnail(PROOF_THUMBNAIL_DIMENSION, PROOF_THUMBNAIL_DIMENSION)).to_rgb8(),
+            );
+            let mut jpeg = std::io::Cursor::new(Vec::new());
+            thumbnail
+                .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(80))
+                .map_err(|e| format!("Error encoding proof thumbnail: {}", e))?;
+
+            let number_format = prepared.as_ref().map_or(DEFAULT_NUMBER_FORMAT, |prepared| &prepared.number_style.format);
+            let filename = image_file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            Ok(pdf::ProofCell {
+                jpeg: jpeg.into_inner(),
+                width: thumbnail.width(),
+                height: thumbnail.height(),
+                label_lines: vec![number_format.replace("{n}", &number), filename],
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    fs::create_dir_all(proofs_dir)
+        .map_err(|e| format!("Error creating proofs directory: {}", e))?;
+    let pdf_path = proofs_dir.join(format!("proof_{}.pdf", chrono::Local::now().format("%Y%m%d_%H%M%S_%3f")));
+    fs::write(&pdf_path, pdf::proof_sheet(&cells, columns, rows))
+        .map_err(|e| format!("Error writing proof PDF: {}", e))?;
+
+    Ok(pdf_path)
+}
+
+#[derive(Serialize)]
+struct FontInfo {
+    id: String,
+    name: String,
+}
+
+/// Fonts that can be referenced by id wherever a font path is accepted.
+#[tauri::command]
+fn list_available_fonts() -> Vec<FontInfo> {
+    text::BUNDLED_FONTS
+        .iter()
+        .map(|font| FontInfo {
+            id: font.id.to_string(),
+            name: font.name.to_string(),
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn measure_text(text: String, font_path: Option<String>, font_size: f32) -> Result<TextSize, String> {
+    if font_size <= 0.0 {
+        return Err("Font size must be greater than zero".to_string());
+    }
+
+    let font = text::load_font(font_path.as_deref())?;
+    let (width, height) = text::measure_text(&font, font_size, &text);
+    Ok(TextSize { width, height })
+}
+
+#[tauri::command]
+async fn save_template_image(app_handle: AppHandle, file_data: Vec<u8>, filename: String) -> Result<String, String> {
+    let images_dir = template_images_dir(&app_handle)?;
+    let file_path = store_template_image(&images_dir, &file_data, &filename)?;
+    
+    // Return the file path as string
+    Ok(file_path.to_string_lossy().to_string())
+}
+
+/// Directory of the app data dir holding the images of all templates
+const TEMPLATE_IMAGES_DIR: &str = "template_images";
+
+fn template_images_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    // Get app data directory
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    Ok(app_data_dir.join(TEMPLATE_IMAGES_DIR))
+}
+
+fn store_template_image(images_dir: &Path, file_data: &[u8], filename: &str) -> Result<PathBuf, String> {
+    // Create images directory if it doesn't exist
+    fs::create_dir_all(images_dir)
+        .map_err(|e| format!("Error creating images directory: {}", e))?;
+    
+    // Generate unique filename with timestamp
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    
+    let file_extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("jpg");
+    
+    let stem = format!("{}_{}", timestamp, filename.replace('.', "_"));
+    let mut file_path = images_dir.join(format!("{}.{}", stem, file_extension));
+    // Two images stored within the same second under the same name must not share a file
+    let mut counter = 1;
+    while file_path.exists() {
+        file_path = images_dir.join(format!("{}_{}.{}", stem, counter, file_extension));
+        counter += 1;
+    }
+    
+    // Save file
+    fs::write(&file_path, file_data)
+        .map_err(|e| format!("Error saving file: {}", e))?;
+    
+    Ok(file_path)
+}
+
+/// Copy a newly chosen template image into managed storage unless it already lives there.
+fn import_template_image(images_dir: &Path, template_img: String) -> Result<String, String> {
+    if Path::new(&template_img).starts_with(images_dir) {
+        return Ok(template_img);
+    }
+
+    let file_data = fs::read(&template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template_img, e))?;
+    let filename = Path::new(&template_img)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "template.jpg".to_string());
+    let stored_path = store_template_image(images_dir, &file_data, &filename)?;
+
+    Ok(stored_path.to_string_lossy().to_string())
+}
+
+#[derive(Serialize)]
+struct TemplateReference {
+    id: i32,
+    name: String,
+}
+
+/// Templates whose image is the file at `path`, however the path is spelled.
+#[tauri::command]
+fn templates_using_image(path: String) -> Result<Vec<TemplateReference>, String> {
+    let mut connection = establish_connection()?;
+    let templates: Vec<PhotoTemplate> = photo_templates::table
+        .order(photo_templates::id.asc())
+        .load(&mut connection)
+        .map_err(|e| format!("Error loading templates: {}", e))?;
+
+    // Compare resolved paths so `./a/../b.png` and symlinks match too, the raw path otherwise
+    let resolve = |path: &str| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let target = resolve(&path);
+    Ok(templates
+        .into_iter()
+        .filter(|template| resolve(&template.template_img) == target)
+        .map(|template| TemplateReference {
+            id: template.id,
+            name: template.name,
+        })
+        .collect())
+}
+
+/// Number of prior states kept per template for `revert_template`.
+const MAX_TEMPLATE_EDITS: i64 = 20;
+
+/// Save the current state of a template before it is edited, dropping the oldest
+/// entries beyond `MAX_TEMPLATE_EDITS`.
+///
+/// Returns the images of the dropped entries, for `remove_unused_template_images` once the
+/// edit is committed.
+fn record_template_edit(connection: &mut SqliteConnection, template: &PhotoTemplate) -> Result<Vec<String>, String> {
+    diesel::insert_into(template_edits::table)
+        .values(NewTemplateEdit::from(template))
+        .execute(connection)
+        .map_err(|e| format!("Error recording template edit: {}", e))?;
+
+    let expired: Vec<TemplateEdit> = template_edits::table
+        .filter(template_edits::template_id.eq(template.id))
+        .order(template_edits::id.desc())
+        .offset(MAX_TEMPLATE_EDITS)
+        .load(connection)
+        .map_err(|e| format!("Error loading template edit history: {}", e))?;
+
+    let mut expired_images = Vec::new();
+    for edit in expired {
+        diesel::delete(template_edits::table.find(edit.id))
+            .execute(connection)
+            .map_err(|e| format!("Error pruning template edit history: {}", e))?;
+        // Images only kept alive by the history can go with it
+        expired_images.push(edit.template_img);
+    }
+
+    Ok(expired_images)
+}
+
+/// Delete the managed images among `template_imgs` that nothing references anymore.
+///
+/// Called once the change that dropped them is committed, so a rolled back change never points
+/// at a deleted file. A file that can't be removed is left behind rather than reported, the
+/// change itself went through.
+fn remove_unused_template_images(connection: &mut SqliteConnection, mut template_imgs: Vec<String>, images_dir: &Path) {
+    template_imgs.sort();
+    template_imgs.dedup();
+    for template_img in template_imgs {
+        let _ = remove_unused_template_image(connection, &template_img, images_dir);
+    }
+}
+
+/// Delete a managed template image once no template references it anymore.
+fn remove_unused_template_image(
+    connection: &mut SqliteConnection,
+    template_img: &str,
+    images_dir: &Path,
+) -> Result<(), String> {
+    // Never touch files outside the managed directory, the path may have been edited by hand
+    let path = Path::new(template_img);
+    let escapes_directory = path.components().any(|component| component == std::path::Component::ParentDir);
+    if !path.starts_with(images_dir) || escapes_directory {
+        return Ok(());
+    }
+
+    let references: i64 = photo_templates::table
+        .filter(photo_templates::template_img.eq(template_img))
+        .count()
+        .get_result(connection)
+        .map_err(|e| format!("Error counting template image references: {}", e))?;
+    // The edit history still needs the image to revert to it
+    let history_references: i64 = template_edits::table
+        .filter(template_edits::template_img.eq(template_img))
+        .count()
+        .get_result(connection)
+        .map_err(|e| format!("Error counting template image references: {}", e))?;
+    if references + history_references > 0 {
+        return Ok(());
+    }
+
+    match fs::remove_file(template_img) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Error removing template image {}: {}", template_img, e)),
+    }
+}
+
+#[tauri::command]
+async fn select_image_folder(app_handle: AppHandle) -> Result<String, String> {
+    use tauri_plugin_dialog::{DialogExt};
+    use std::sync::{Arc, Mutex};
+    use tokio::sync::oneshot;
+    
+    let (tx, rx) = oneshot::channel();
+    let tx = Arc::new(Mutex::new(Some(tx)));
+    
+    app_handle.dialog()
+        .file()
+        .pick_folder(move |folder_path| {
+            if let Ok(mut sender) = tx.lock() {
+                if let Some(tx) = sender.take() {
                     let _ = tx.send(folder_path);
                 }
             }
-        });
-    
-    match rx.await {
-        Ok(Some(path)) => Ok(path.to_string()),
-        Ok(None) => Err("No folder selected".to_string()),
-        Err(_) => Err("Dialog was cancelled".to_string()),
+        });
+    
+    match rx.await {
+        Ok(Some(path)) => Ok(path.to_string()),
+        Ok(None) => Err("No folder selected".to_string()),
+        Err(_) => Err("Dialog was cancelled".to_string()),
+    }
+}
+
+/// Directory of the app data dir holding one workspace per imported folder
+const IMPORTED_SOURCES_DIR: &str = "imported_sources";
+
+/// Sent as `import-progress` after each file copied by `import_source_folder`.
+#[derive(Serialize, Clone)]
+struct ImportProgress {
+    filename: String,
+    copied: usize,
+    total: usize,
+}
+
+/// Copy the images of `folder` into a new workspace under app data and return its path,
+/// so generation doesn't depend on removable media staying plugged in.
+#[tauri::command]
+async fn import_source_folder(app_handle: AppHandle, folder: String) -> Result<String, String> {
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    let workspace_name = format!("import_{}", chrono::Local::now().format("%Y%m%d_%H%M%S_%3f"));
+    let workspace = app_data_dir.join(IMPORTED_SOURCES_DIR).join(workspace_name);
+
+    let image_files = find_image_files(&folder, &GenerationOptions::default())?;
+    if image_files.is_empty() {
+        return Err("No image files found in the selected folder".to_string());
+    }
+    copy_source_files(&image_files, &workspace, |progress| {
+        app_handle.emit("import-progress", progress).unwrap_or(());
+    })?;
+
+    Ok(workspace.to_string_lossy().to_string())
+}
+
+fn copy_source_files(
+    image_files: &[PathBuf],
+    workspace: &Path,
+    mut on_copy: impl FnMut(ImportProgress),
+) -> Result<(), String> {
+    fs::create_dir_all(workspace)
+        .map_err(|e| format!("Error creating import workspace: {}", e))?;
+
+    for (index, image_file) in image_files.iter().enumerate() {
+        let filename = image_file.file_name()
+            .ok_or_else(|| format!("Invalid source path {:?}", image_file))?;
+        fs::copy(image_file, workspace.join(filename))
+            .map_err(|e| format!("Error copying {:?} into the workspace: {}", image_file, e))?;
+        on_copy(ImportProgress {
+            filename: filename.to_string_lossy().to_string(),
+            copied: index + 1,
+            total: image_files.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Run a generation and wait for its result, queued behind any job already running.
+///
+/// With `archive` false the outputs are left as loose files and the run directory is returned.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn generate_images_with_template(
+    app_handle: AppHandle,
+    generation_state: State<'_, GenerationState>,
+    template_id: i32,
+    image_folder_path: String,
+    options: Option<serde_json::Value>,
+    preset_name: Option<String>,
+    output_dir: Option<String>,
+    archive: Option<bool>,
+) -> Result<GenerationResult, String> {
+    let options = generation_request_options(preset_name.as_deref(), options, output_dir, archive)?;
+    let job = generation_state.enqueue(template_id, &image_folder_path);
+    run_generation_job(&app_handle, &generation_state, job.id, &image_folder_path, options).await
+}
+
+/// Queue a generation and return its job id right away, progress is reported through `generation-job` events.
+///
+/// Takes the same `output_dir` and `archive` settings as `generate_images_with_template`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn enqueue_generation(
+    app_handle: AppHandle,
+    generation_state: State<'_, GenerationState>,
+    template_id: i32,
+    image_folder_path: String,
+    options: Option<serde_json::Value>,
+    preset_name: Option<String>,
+    output_dir: Option<String>,
+    archive: Option<bool>,
+) -> Result<u64, String> {
+    let options = generation_request_options(preset_name.as_deref(), options, output_dir, archive)?;
+    let job = generation_state.enqueue(template_id, &image_folder_path);
+    app_handle.emit("generation-job", &job).unwrap_or(());
+
+    let job_id = job.id;
+    tauri::async_runtime::spawn(async move {
+        let generation_state = app_handle.state::<GenerationState>();
+        // The outcome is stored on the job and emitted, nobody awaits it here
+        let _ = run_generation_job(&app_handle, &generation_state, job_id, &image_folder_path, options).await;
+    });
+
+    Ok(job_id)
+}
+
+/// Options of a generation command, `output_dir` and `archive` overriding the preset and options.
+fn generation_request_options(
+    preset_name: Option<&str>,
+    options: Option<serde_json::Value>,
+    output_dir: Option<String>,
+    archive: Option<bool>,
+) -> Result<GenerationOptions, String> {
+    let mut options = resolve_generation_options(preset_name, options)?;
+    if archive == Some(false) {
+        options.loose_files = true;
+    }
+    if let Some(output_dir) = output_dir.filter(|dir| !dir.trim().is_empty()) {
+        // Checked before queueing so an unusable pick is reported instead of silently replaced
+        check_existing_output_dir(Path::new(&output_dir))?;
+        options.output_folder = Some(output_dir);
+    }
+    Ok(options)
+}
+
+/// Build the options of a run from a saved preset, overridden by the options sent with the call.
+///
+/// Overrides replace whole top-level settings, e.g. sending `watermark` replaces the preset's watermark entirely.
+fn resolve_generation_options(
+    preset_name: Option<&str>,
+    overrides: Option<serde_json::Value>,
+) -> Result<GenerationOptions, String> {
+    let mut settings = match preset_name {
+        Some(name) => {
+            let mut connection = establish_connection()?;
+            let preset: GenerationPreset = generation_presets::table
+                .filter(generation_presets::name.eq(name))
+                .first(&mut connection)
+                .map_err(|e| format!("Error loading preset \"{}\": {}", name, e))?;
+            serde_json::from_str(&preset.settings)
+                .map_err(|e| format!("Error parsing preset \"{}\": {}", name, e))?
+        }
+        None => serde_json::Map::new(),
+    };
+
+    match overrides {
+        Some(serde_json::Value::Object(overrides)) => settings.extend(overrides),
+        Some(serde_json::Value::Null) | None => {}
+        Some(_) => return Err("Generation options must be a JSON object".to_string()),
+    }
+
+    serde_json::from_value(serde_json::Value::Object(settings))
+        .map_err(|e| format!("Invalid generation options: {}", e))
+}
+
+/// Save generation settings under `name`, replacing any preset with the same name.
+#[tauri::command]
+fn save_generation_preset(name: String, settings: serde_json::Value) -> Result<GenerationPreset, String> {
+    if name.trim().is_empty() {
+        return Err("Preset name must not be empty".to_string());
+    }
+    if !settings.is_object() {
+        return Err("Preset settings must be a JSON object".to_string());
+    }
+    // Reject settings that would only fail once a generation uses them
+    serde_json::from_value::<GenerationOptions>(settings.clone())
+        .map_err(|e| format!("Invalid preset settings: {}", e))?;
+
+    let settings = settings.to_string();
+    let mut connection = establish_connection()?;
+    diesel::insert_into(generation_presets::table)
+        .values(NewGenerationPreset {
+            name: name.clone(),
+            settings: settings.clone(),
+        })
+        .on_conflict(generation_presets::name)
+        .do_update()
+        .set(generation_presets::settings.eq(&settings))
+        .execute(&mut connection)
+        .map_err(|e| format!("Error saving preset: {}", e))?;
+
+    generation_presets::table
+        .filter(generation_presets::name.eq(&name))
+        .first(&mut connection)
+        .map_err(|e| format!("Error fetching saved preset: {}", e))
+}
+
+#[tauri::command]
+fn list_generation_presets() -> Result<Vec<GenerationPreset>, String> {
+    let mut connection = establish_connection()?;
+    generation_presets::table
+        .order(generation_presets::name.asc())
+        .load(&mut connection)
+        .map_err(|e| format!("Error loading presets: {}", e))
+}
+
+#[tauri::command]
+fn get_job_status(generation_state: State<'_, GenerationState>, id: u64) -> Result<GenerationJob, String> {
+    generation_state.job(id).ok_or(format!("Generation job {} not found", id))
+}
+
+#[tauri::command]
+fn list_jobs(generation_state: State<'_, GenerationState>) -> Vec<GenerationJob> {
+    generation_state.all_jobs()
+}
+
+/// Queued behind the previous jobs, see `GenerationState::run_job`.
+async fn run_generation_job(
+    app_handle: &AppHandle,
+    generation_state: &GenerationState,
+    job_id: u64,
+    image_folder_path: &str,
+    options: GenerationOptions,
+) -> Result<GenerationResult, String> {
+    let emit_job = |job: &GenerationJob| app_handle.emit("generation-job", job).unwrap_or(());
+    generation_state
+        .run_job(job_id, emit_job, |job| {
+            // Rendering blocks for the whole run, keep it off the async runtime's worker threads
+            let task_handle = app_handle.clone();
+            let image_folder_path = image_folder_path.to_string();
+            async move {
+                tauri::async_runtime::spawn_blocking(move || {
+                    let generation_state = task_handle.state::<GenerationState>();
+                    run_generation(&task_handle, &generation_state.cancelled, job.template_id, &image_folder_path, &options)
+                })
+                .await
+                .unwrap_or_else(|e| Err(format!("Generation task failed: {}", e)))
+            }
+        })
+        .await
+}
+
+fn run_generation(
+    app_handle: &AppHandle,
+    cancelled: &AtomicBool,
+    template_id: i32,
+    image_folder_path: &str,
+    options: &GenerationOptions,
+) -> Result<GenerationResult, String> {
+    // 1. Get PhotoTemplate from database
+    let mut connection = establish_connection()?;
+    let template: PhotoTemplate = photo_templates::table
+        .find(template_id)
+        .first(&mut connection)
+        .map_err(|e| format!("Error loading template: {}", e))?;
+    let mut load_template = |id: i32| {
+        photo_templates::table
+            .find(id)
+            .first::<PhotoTemplate>(&mut connection)
+            .map_err(|e| format!("Error loading template {}: {}", id, e))
+    };
+    let orientation_templates = OrientationTemplates {
+        landscape: options.landscape_template_id.map(&mut load_template).transpose()?,
+        portrait: options.portrait_template_id.map(&mut load_template).transpose()?,
+    };
+
+    // 2. Create output directory for processed images
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    // Each run gets its own directory so earlier exports stay listed in the recent runs
+    let run_name = format!("run_{}", chrono::Local::now().format("%Y%m%d_%H%M%S_%3f"));
+    // Checked before rendering anything so read-only profiles fail with a clear error
+    let output_dir = run_output_dir(&app_data_dir, &template, &run_name, options)?;
+
+    // 3. Composite every image, forwarding progress to the UI
+    let run = generate_images(
+        &template,
+        &orientation_templates,
+        image_folder_path,
+        &output_dir,
+        options,
+        cancelled,
+        &mut AppEvents(app_handle),
+    )?;
+
+    // 4. Archive the outputs
+    package_run(&run, &output_dir, options, cancelled)
+}
+
+/// Create the ZIP archives and extra files of a finished run, then illustrate it with a thumbnail.
+fn package_run(
+    run: &GenerationRun,
+    output_dir: &Path,
+    options: &GenerationOptions,
+    cancelled: &AtomicBool,
+) -> Result<GenerationResult, String> {
+    let manifest_path = if options.write_hash_manifest {
+        Some(write_hash_manifest(&run.images, output_dir)?)
+    } else {
+        None
+    };
+    let chunks = if options.loose_files { Vec::new() } else { archive_chunks(&run.images, options.chunk_size) };
+    let archive_result = chunks
+        .into_iter()
+        .map(|(archive_name, images)| {
+            let mut entries = Vec::new();
+            for image in images {
+                entries.push((image.output.clone(), output_entry_name(&image.output, output_dir)));
+                if let Some(preview) = &image.preview {
+                    entries.push((preview.clone(), output_entry_name(preview, output_dir)));
+                }
+                // Originals go to a subfolder so they can't collide with outputs
+                if options.include_originals {
+                    for source in &image.sources {
+                        entries.push((source.clone(), format!("originals/{}", archive_entry_name(source))));
+                    }
+                }
+            }
+            // Each part carries the manifest of the whole run
+            if let Some(manifest_path) = &manifest_path {
+                entries.push((manifest_path.clone(), "hash_manifest.json".to_string()));
+            }
+            create_archive(
+                entries,
+                &output_dir.join(archive_name),
+                options.duplicate_entries,
+                options.deterministic,
+                options.archive_compression,
+                cancelled,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|archive_paths| {
+            let failures_bundle_path = if options.collect_failures_bundle && !run.failures.is_empty() {
+                Some(create_failures_bundle(&run.failures, output_dir, options.deterministic, cancelled)?)
+            } else {
+                None
+            };
+            let archive_path = match archive_paths.first() {
+                Some(archive_path) => archive_path.clone(),
+                None => output_dir.to_string_lossy().to_string(),
+            };
+            Ok(GenerationResult { archive_path, archive_paths, failures_bundle_path, thumbnail_path: None })
+        });
+
+    // Images extracted from ZIP sources are only needed until they are archived
+    let _ = fs::remove_dir_all(output_dir.join(EXTRACTED_SOURCES_DIR));
+
+    archive_result.map(|result| GenerationResult {
+        // Only used to illustrate the run in the UI, not worth failing the generation for
+        thumbnail_path: write_run_thumbnail(&run.images, output_dir)
+            .ok()
+            .flatten()
+            .map(|path| path.to_string_lossy().to_string()),
+        ..result
+    })
+}
+
+/// Archive names of a run and the outputs each one holds, a single `generated_images.zip`
+/// unless `chunk_size` splits them into numbered parts.
+fn archive_chunks(images: &[GeneratedImage], chunk_size: Option<NonZeroUsize>) -> Vec<(String, &[GeneratedImage])> {
+    match chunk_size {
+        Some(chunk_size) if images.len() > chunk_size.get() => images
+            .chunks(chunk_size.get())
+            .enumerate()
+            .map(|(index, chunk)| (format!("generated_images_part{}.zip", index + 1), chunk))
+            .collect(),
+        _ => vec![("generated_images.zip".to_string(), images)],
+    }
+}
+
+/// Create the directory `run_name` is written to, under the output folder of the run or the
+/// template, or under the app data directory when neither sets one.
+fn run_output_dir(
+    app_data_dir: &Path,
+    template: &PhotoTemplate,
+    run_name: &str,
+    options: &GenerationOptions,
+) -> Result<PathBuf, String> {
+    // A folder picked for this run wins over the template's default delivery folder
+    let output_folder = options
+        .output_folder
+        .as_deref()
+        .or(Some(template.default_output_folder.as_str()))
+        .filter(|folder| !folder.trim().is_empty());
+    match output_folder {
+        // Never swapped for another folder, the outputs are expected there
+        Some(folder) => prepare_output_dir(&Path::new(folder).join(run_name)),
+        None => match prepare_output_dir(&app_data_dir.join(GENERATED_IMAGES_DIR).join(run_name)) {
+            Ok(output_dir) => Ok(output_dir),
+            Err(e) => match &options.fallback_output_dir {
+                Some(fallback) => prepare_output_dir(&Path::new(fallback).join(run_name)),
+                None => Err(e),
+            },
+        },
+    }
+}
+
+/// Check that a directory picked by the user exists and can be written to, without creating it.
+fn check_existing_output_dir(output_dir: &Path) -> Result<(), String> {
+    if !output_dir.is_dir() {
+        return Err(format!("Output directory {:?} does not exist", output_dir));
+    }
+    prepare_output_dir(output_dir).map(|_| ())
+}
+
+/// Create `output_dir` and make sure files can be written to it.
+fn prepare_output_dir(output_dir: &Path) -> Result<PathBuf, String> {
+    let not_writable = |e: std::io::Error| {
+        format!(
+            "Output directory {:?} is not writable ({}). Check its permissions or set a fallback output directory",
+            output_dir, e
+        )
+    };
+    fs::create_dir_all(output_dir).map_err(not_writable)?;
+
+    let probe_path = output_dir.join(".write_test");
+    fs::write(&probe_path, b"").map_err(not_writable)?;
+    fs::remove_file(&probe_path).map_err(not_writable)?;
+    Ok(output_dir.to_path_buf())
+}
+
+/// Receives the events of a generation run, every method defaults to ignoring them.
+trait GenerationObserver {
+    /// Periodic counts while source folders are listed
+    fn on_scan(&mut self, _scan: ScanProgress) {}
+    /// Called as outputs complete, throttled per the generation options
+    fn on_progress(&mut self, _progress: GenerationProgress) {}
+    /// Called after each output reaches the remote destination
+    fn on_upload(&mut self, _upload: UploadProgress) {}
+    /// The run was cancelled before every output was rendered
+    fn on_cancelled(&mut self, _cancelled: GenerationCancelled) {}
+    /// A source was left out of the run before rendering, e.g. an empty file
+    fn on_skipped(&mut self, _skipped: SkippedSource) {}
+}
+
+/// Sent as `generation-progress`, e.g. to show "Processing 12/300: IMG_4521.jpg".
+#[derive(Serialize, Clone)]
+struct GenerationProgress {
+    /// Outputs finished so far, this one included
+    current: usize,
+    total: usize,
+    /// Source file of the output that just finished
+    filename: String,
+    percent: f32,
+}
+
+/// Sent as `generation-cancelled` when a run stops early, outputs already written are kept.
+#[derive(Serialize, Clone)]
+struct GenerationCancelled {
+    completed: usize,
+    total: usize,
+}
+
+impl GenerationObserver for () {}
+
+/// Forwards generation events to the UI.
+struct AppEvents<'a>(&'a AppHandle);
+
+impl GenerationObserver for AppEvents<'_> {
+    fn on_scan(&mut self, scan: ScanProgress) {
+        self.0.emit("scan-progress", scan).unwrap_or(());
+    }
+
+    fn on_progress(&mut self, progress: GenerationProgress) {
+        self.0.emit("generation-progress", progress).unwrap_or(());
+    }
+
+    fn on_upload(&mut self, upload: UploadProgress) {
+        self.0.emit("upload-progress", upload).unwrap_or(());
+    }
+
+    fn on_cancelled(&mut self, cancelled: GenerationCancelled) {
+        self.0.emit("generation-cancelled", cancelled).unwrap_or(());
+    }
+
+    fn on_skipped(&mut self, skipped: SkippedSource) {
+        self.0.emit("source-skipped", skipped).unwrap_or(());
+    }
+}
+
+/// Templates a run switches to depending on the orientation of each source.
+#[derive(Default)]
+struct OrientationTemplates {
+    landscape: Option<PhotoTemplate>,
+    portrait: Option<PhotoTemplate>,
+}
+
+/// Composite every image of `image_folder_path` with the template into `output_dir`, or with
+/// the landscape/portrait template of `orientation_templates` matching the source.
+///
+/// Scan, render and upload progress are reported to `observer`. Setting `cancelled` stops
+/// rendering further outputs, the ones already written stay in `output_dir`.
+fn generate_images(
+    template: &PhotoTemplate,
+    orientation_templates: &OrientationTemplates,
+    image_folder_path: &str,
+    output_dir: &Path,
+    options: &GenerationOptions,
+    cancelled: &AtomicBool,
+    observer: &mut (impl GenerationObserver + Send),
+) -> Result<GenerationRun, String> {
+    // 1. Parse crops, watermark and load the template images
+    let primary = prepare_template(template, options)?;
+    let landscape = orientation_templates.landscape.as_ref().map(|template| prepare_template(template, options)).transpose()?;
+    let portrait = orientation_templates.portrait.as_ref().map(|template| prepare_template(template, options)).transpose()?;
+    let uploader = options.remote_destination.as_ref().map(remote::connect).transpose()?;
+
+    // 2. Find all image files in the folder and any extra sources
+    let mut empty_files = Vec::new();
+    let (image_files, output_stems) = resolve_sources(
+        image_folder_path,
+        options,
+        &output_dir.join(EXTRACTED_SOURCES_DIR),
+        |scan| observer.on_scan(scan),
+        &mut empty_files,
+    )?;
+    for empty_file in &empty_files {
+        observer.on_skipped(SkippedSource {
+            path: empty_file.to_string_lossy().to_string(),
+            reason: EMPTY_SOURCE_REASON.to_string(),
+        });
+    }
+    if image_files.is_empty() {
+        return Err("No image files found in the selected folder".to_string());
+    }
+
+    // 3. Process each image (or each group of images in mosaic mode) in parallel
+    let group_size = options.photos_per_output(image_files.len());
+    let groups: Vec<&[PathBuf]> = image_files.chunks(group_size).collect();
+    let total_images = groups.len();
+    // Uploads share one connection, events go through one lock so progress never goes backwards
+    let uploader = Mutex::new((uploader, 0usize));
+    let events = Mutex::new((observer, ProgressThrottle::new(options), 0usize));
+
+    let render = || groups
+        .par_iter()
+        .enumerate()
+        .map(|(index, group)| {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let image_file = &group[0];
+            // Only peek at the source when the run has a template to route it to
+            let orientation = if landscape.is_some() || portrait.is_some() {
+                source_orientation(image_file, options)
+            } else {
+                None
+            };
+            let prepared = match orientation {
+                Some(Orientation::Landscape) => landscape.as_ref().unwrap_or(&primary),
+                Some(Orientation::Portrait) => portrait.as_ref().unwrap_or(&primary),
+                None => &primary,
+            };
+
+            // Save result image - preserve original filename, prefixed by its source on collisions
+            let original_filename = match output_stems.get(image_file) {
+                Some(name) => name.clone(),
+                None => match image_file.file_stem().and_then(|s| s.to_str()) {
+                    Some(name) => name.to_string(),
+                    None => format!("image_{}", index + 1),
+                },
+            };
+            let mut output_filename = format!("{}_processed.{}", original_filename, options.output_format.extension());
+            if options.prefix_with_index {
+                // Zero-padded sequence so labs sorting by name keep the processing order
+                let width = total_images.to_string().len().max(4);
+                // On the file name, subfolders of a recursive scan stay as they are
+                output_filename = match output_filename.rsplit_once('/') {
+                    Some((subfolder, name)) => format!("{}/{:0width$}_{}", subfolder, index + 1, name, width = width),
+                    None => format!("{:0width$}_{}", index + 1, output_filename, width = width),
+                };
+            }
+            let output_path = output_dir.join(&output_filename);
+            let preview_path = options
+                .preview_max_dimension
+                .map(|_| output_dir.join(PREVIEWS_DIR).join(&output_filename));
+            let result = [Some(&output_path), preview_path.as_ref()]
+                .into_iter()
+                .flatten()
+                .try_for_each(|path| fs::create_dir_all(path.parent().unwrap_or(output_dir)))
+                .map_err(|e| format!("Error creating output folder for {}: {}", output_filename, e))
+                .and_then(|()| render_composite(prepared, group, index, options))
+                .and_then(|(composite, extracted_number)| {
+                    // Scaled from the finished output so the preview looks the same, watermark aside
+                    if let (Some(preview_path), Some(max_dimension)) = (&preview_path, options.preview_max_dimension) {
+                        let preview = finish_output(composite.clone(), prepared, options, true)?
+                            .thumbnail(max_dimension, max_dimension);
+                        save_output_image(&preview, preview_path, options, &extracted_number)?;
+                    }
+                    let result_image = finish_output(composite, prepared, options, false)?;
+                    save_output_image(&result_image, &output_path, options, &extracted_number)?;
+                    Ok(extracted_number)
+                })
+                .and_then(|extracted_number| {
+                    let (uploader, uploaded) = &mut *uploader.lock().unwrap_or_else(|e| e.into_inner());
+                    let Some(uploader) = uploader.as_mut() else {
+                        return Ok(extracted_number);
+                    };
+                    uploader.upload(&output_path, &output_filename)?;
+                    *uploaded += 1;
+                    events.lock().unwrap_or_else(|e| e.into_inner()).0.on_upload(UploadProgress {
+                        remote_name: output_filename.clone(),
+                        uploaded: *uploaded,
+                        total: total_images,
+                    });
+                    Ok(extracted_number)
+                });
+
+            let outcome = match result {
+                Ok(number) => Ok(Ok(GeneratedImage {
+                    sources: group.to_vec(),
+                    output: output_path,
+                    preview: preview_path,
+                    number,
+                })),
+                Err(reason) if options.skip_failed_files || options.collect_failures_bundle => Ok(Err(FailedImage {
+                    sources: group.to_vec(),
+                    reason,
+                })),
+                Err(reason) => Err(reason),
+            }
+            .map(Some);
+
+            // Report progress, coalesced according to the throttle settings
+            let (observer, throttle, completed) = &mut *events.lock().unwrap_or_else(|e| e.into_inner());
+            *completed += 1;
+            let percent = *completed as f32 / total_images as f32 * 100.0;
+            if throttle.should_emit(percent) {
+                observer.on_progress(GenerationProgress {
+                    current: *completed,
+                    total: total_images,
+                    filename: image_file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+                    percent,
+                });
+            }
+            outcome
+        })
+        .collect::<Result<Vec<_>, String>>();
+    // A run limited to fewer threads gets its own pool, the others share the global one
+    let outcomes = match options.max_threads {
+        Some(max_threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(max_threads.get())
+            .build()
+            .map_err(|e| format!("Error creating the render thread pool: {}", e))?
+            .install(render),
+        None => render(),
+    }?;
+
+    let (observer, _, completed) = events.into_inner().unwrap_or_else(|e| e.into_inner());
+    if completed < total_images {
+        observer.on_cancelled(GenerationCancelled { completed, total: total_images });
+        return Err(format!(
+            "Generation cancelled after {} of {} images, the images already generated were kept in {:?}",
+            completed, total_images, output_dir
+        ));
+    }
+
+    // Outcomes are collected in source order, so outputs stay listed in processing order
+    let mut processed_files = Vec::new();
+    let mut failures: Vec<FailedImage> = empty_files
+        .into_iter()
+        .map(|empty_file| FailedImage {
+            sources: vec![empty_file],
+            reason: EMPTY_SOURCE_REASON.to_string(),
+        })
+        .collect();
+    for outcome in outcomes.into_iter().flatten() {
+        match outcome {
+            Ok(image) => processed_files.push(image),
+            Err(failure) => failures.push(failure),
+        }
+    }
+
+    Ok(GenerationRun {
+        images: processed_files,
+        failures,
+    })
+}
+
+enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+/// Orientation of a source as it will be composited, `None` for square or unreadable files.
+///
+/// Only the header is read, the EXIF rotation counts when `auto_enhance` applies it.
+fn source_orientation(source_path: &Path, options: &GenerationOptions) -> Option<Orientation> {
+    let (mut width, mut height) = image::io::Reader::open(source_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    if options.auto_enhance && (5..=8).contains(&enhance::read_exif_orientation(source_path)) {
+        std::mem::swap(&mut width, &mut height);
+    }
+    match width.cmp(&height) {
+        std::cmp::Ordering::Greater => Some(Orientation::Landscape),
+        std::cmp::Ordering::Less => Some(Orientation::Portrait),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+/// Everything about a template that is shared by all outputs of a run.
+struct PreparedTemplate {
+    image: DynamicImage,
+    crop_photo: CropCoordinates,
+    crop_number: Option<CropCoordinates>,
+    watermark: Option<WatermarkOptions>,
+    /// Width extracted numbers are zero-padded to, 0 to keep them as-is
+    number_padding: usize,
+    /// Filename patterns of the outputs that get a number, empty for all of them
+    number_filter: Vec<Regex>,
+    number_style: NumberStyle,
+    /// Color grading applied to every source photo, from `lut_path`
+    lut: Option<lut::Lut3d>,
+}
+
+/// Format and padding of the outputs at positions `from` to `to` (1-based, inclusive) of a run.
+#[derive(Deserialize, Clone, Debug)]
+struct NumberRule {
+    from: usize,
+    to: usize,
+    /// Displayed text, `{n}` being replaced by the number, like the template's `number_format`
+    format: String,
+    /// Replaces the run's number padding in this range
+    #[serde(default)]
+    padding: Option<usize>,
+}
+
+impl NumberRule {
+    fn covers(&self, position: usize) -> bool {
+        (self.from..=self.to).contains(&position)
+    }
+}
+
+fn validate_number_rules(rules: &[NumberRule]) -> Result<(), String> {
+    for rule in rules {
+        if rule.from == 0 || rule.from > rule.to {
+            return Err(format!("Invalid number rule range {} to {}, positions start at 1", rule.from, rule.to));
+        }
+        validate_number_format(&rule.format)?;
+        if let Some(padding) = rule.padding {
+            validate_number_padding(i32::try_from(padding).unwrap_or(i32::MAX))?;
+        }
+    }
+    Ok(())
+}
+
+/// How the number is drawn in the crop_number area.
+struct NumberStyle {
+    /// The template's `font_id`
+    font: rusttype::Font<'static>,
+    /// `None` to fit the crop_number area
+    font_size: Option<f32>,
+    color: Rgba<u8>,
+    /// Displayed text, `{n}` being replaced by the number
+    format: String,
+}
+
+fn prepare_template(template: &PhotoTemplate, options: &GenerationOptions) -> Result<PreparedTemplate, String> {
+    // Parse crop coordinates (crop_number is optional)
+    let (crop_photo, crop_number) = parse_template_crops(template)?;
+
+    // Use the per-run watermark, falling back to the template's default one
+    let watermark = match &options.watermark {
+        Some(watermark) => Some(watermark.clone()),
+        None => parse_default_watermark(&template.default_watermark)?,
+    };
+    validate_number_rules(&options.number_rules)?;
+    if let Some(padding) = options.number_padding {
+        validate_number_padding(i32::try_from(padding).unwrap_or(i32::MAX))?;
+    }
+    if options.write_number_to_metadata && matches!(options.output_format, OutputFormat::Tiff | OutputFormat::WebP) {
+        return Err(format!(
+            "The number can't be written to the metadata of {} outputs, use JPEG or PNG",
+            options.output_format.extension().to_uppercase()
+        ));
+    }
+
+    Ok(PreparedTemplate {
+        image: load_image(&template.template_img)?,
+        crop_photo,
+        crop_number,
+        watermark,
+        number_padding: options.number_padding.unwrap_or(template.number_padding.max(0) as usize),
+        number_filter: compile_filename_patterns(options.number_filter.as_slice())?,
+        number_style: NumberStyle {
+            font: text::load_font(Some(&template.font_id))?,
+            font_size: (template.font_size > 0.0).then_some(template.font_size),
+            color: parse_hex_color(&template.text_color)?,
+            format: template.number_format.clone(),
+        },
+        lut: options.lut_path.as_deref().map(|path| lut::load_cube(Path::new(path))).transpose()?,
+    })
+}
+
+/// Composite one output from its source photo(s), returning it with the number drawn on it.
+///
+/// `preview` renders the low-res proof variant, which a watermark can be limited to.
+fn render_output(
+    prepared: &PreparedTemplate,
+    sources: &[PathBuf],
+    index: usize,
+    options: &GenerationOptions,
+    preview: bool,
+) -> Result<(DynamicImage, String), String> {
+    let (composite, number) = render_composite(prepared, sources, index, options)?;
+    Ok((finish_output(composite, prepared, options, preview)?, number))
+}
+
+/// Everything `render_output` draws before the watermark and print canvas, shared by an output and its preview.
+fn render_composite(
+    prepared: &PreparedTemplate,
+    sources: &[PathBuf],
+    index: usize,
+    options: &GenerationOptions,
+) -> Result<(DynamicImage, String), String> {
+    let image_file = &sources[0];
+    let crop_coords = &prepared.crop_photo;
+
+    // Load and resize source image
+    let source_image = match options.mosaic {
+        Some(mosaic) => build_mosaic(
+            sources,
+            crop_coords.width as u32,
+            crop_coords.height as u32,
+            mosaic,
+            options,
+            prepared.lut.as_ref(),
+        )?,
+        None => prepare_slot_image(
+            load_source_image(image_file, options, prepared.lut.as_ref())?,
+            crop_coords.width as u32,
+            crop_coords.height as u32,
+            options,
+        ),
+    };
+
+    // Extract number from filename for text overlay
+    let filename = image_file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extracted_number = extract_number_from_filename(filename, index + 1, options.no_number_behavior)?;
+    // The first rule covering this output's position replaces the template's format and padding
+    let number_rule = options.number_rules.iter().find(|rule| rule.covers(index + 1));
+    let number_padding = number_rule.and_then(|rule| rule.padding).unwrap_or(prepared.number_padding);
+    let extracted_number = pad_number(&extracted_number, number_padding);
+    let rule_style = number_rule.map(|rule| NumberStyle {
+        font: prepared.number_style.font.clone(),
+        font_size: prepared.number_style.font_size,
+        color: prepared.number_style.color,
+        format: rule.format.clone(),
+    });
+    let number_style = rule_style.as_ref().unwrap_or(&prepared.number_style);
+
+    // Files left out by the number filter still get composited, just without the number
+    let source_name = image_file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let show_number = prepared.number_filter.is_empty()
+        || prepared.number_filter.iter().any(|pattern| pattern.is_match(&source_name));
+    let displayed_number = if show_number { extracted_number.as_str() } else { "" };
+
+    // Composite images with text overlay
+    let mut result_image = composite_images_with_text(&prepared.image, &source_image, crop_coords, prepared.crop_number.as_ref(), displayed_number, number_style, options)?;
+    if let Some(barcode) = options.barcode.as_ref().filter(|_| !displayed_number.is_empty()) {
+        result_image = barcode::apply_barcode(result_image, barcode, displayed_number)?;
+    }
+    if let Some(caption) = &options.filename_caption {
+        result_image = caption::apply_filename_caption(result_image, caption, filename, options.text_gradient());
+    }
+
+    Ok((result_image, extracted_number))
+}
+
+/// Stamp the watermark if it targets this kind of output, then lay the result out on the print canvas.
+fn finish_output(
+    mut result_image: DynamicImage,
+    prepared: &PreparedTemplate,
+    options: &GenerationOptions,
+    preview: bool,
+) -> Result<DynamicImage, String> {
+    if let Some(watermark) = prepared.watermark.as_ref().filter(|watermark| watermark.target.covers(preview)) {
+        result_image = watermark::apply_watermark(result_image, watermark);
+    }
+    if let Some(canvas) = &options.print_canvas {
+        result_image = place_on_print_canvas(result_image, canvas, options)?;
+    }
+    Ok(result_image)
+}
+
+// Utility functions for image processing
+
+/// Center `image` inside the canvas margins, scaling it down when it doesn't fit.
+fn place_on_print_canvas(
+    image: DynamicImage,
+    canvas: &PrintCanvasOptions,
+    options: &GenerationOptions,
+) -> Result<DynamicImage, String> {
+    let area_width = canvas.width_px.saturating_sub(canvas.margin_px * 2);
+    let area_height = canvas.height_px.saturating_sub(canvas.margin_px * 2);
+    if area_width == 0 || area_height == 0 {
+        return Err(format!(
+            "Print canvas {}x{} leaves no room inside a {}px margin",
+            canvas.width_px, canvas.height_px, canvas.margin_px
+        ));
+    }
+
+    let image = if image.width() > area_width || image.height() > area_height {
+        image.resize(area_width, area_height, image::imageops::FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let background = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+        canvas.width_px,
+        canvas.height_px,
+        Rgba(canvas.bg_color.unwrap_or([255, 255, 255, 255])),
+    ));
+    let x = ((canvas.width_px - image.width()) / 2) as i64;
+    let y = ((canvas.height_px - image.height()) / 2) as i64;
+    let layers = [(&background, 0, 0), (&image, x, y)];
+
+    Ok(if options.color_type.is_16_bit() {
+        DynamicImage::ImageRgba16(stack_layers(canvas.width_px, canvas.height_px, &layers, DynamicImage::to_rgba16))
+    } else {
+        DynamicImage::ImageRgba8(stack_layers(canvas.width_px, canvas.height_px, &layers, DynamicImage::to_rgba8))
+    })
+}
+
+fn template_image_dimensions(template_img: &str) -> Result<(i32, i32), String> {
+    let (width, height) = image::image_dimensions(template_img)
+        .map_err(|e| format!("Error reading template image {}: {}", template_img, e))?;
+    Ok((width as i32, height as i32))
+}
+
+const DEFAULT_TEXT_COLOR: &str = "#000000";
+const DEFAULT_NUMBER_FORMAT: &str = "N° {n}";
+
+/// Number formats must show the number somewhere, e.g. `#{n}` or `Photo {n}`.
+fn validate_number_format(number_format: &str) -> Result<(), String> {
+    if !number_format.contains("{n}") {
+        return Err(format!("Number format \"{}\" must contain {{n}}", number_format));
+    }
+    Ok(())
+}
+
+/// Parse `#RGB`, `#RRGGBB` or `#RRGGBBAA` into a color, an empty string being the default black.
+fn parse_hex_color(hex: &str) -> Result<Rgba<u8>, String> {
+    let trimmed = hex.trim();
+    if trimmed.is_empty() {
+        return parse_hex_color(DEFAULT_TEXT_COLOR);
+    }
+
+    let invalid = || format!("Invalid color \"{}\", expected #RGB, #RRGGBB or #RRGGBBAA", hex);
+    let digits = trimmed.strip_prefix('#').ok_or_else(invalid)?;
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+
+    let channel = |index: usize, len: usize| {
+        let value = u8::from_str_radix(&digits[index * len..(index + 1) * len], 16).unwrap_or(0);
+        // #RGB digits are doubled, F meaning FF
+        if len == 1 { value * 17 } else { value }
+    };
+    match digits.len() {
+        3 => Ok(Rgba([channel(0, 1), channel(1, 1), channel(2, 1), 255])),
+        6 => Ok(Rgba([channel(0, 2), channel(1, 2), channel(2, 2), 255])),
+        8 => Ok(Rgba([channel(0, 2), channel(1, 2), channel(2, 2), channel(3, 2)])),
+        _ => Err(invalid()),
+    }
+}
+
+const MAX_NUMBER_PADDING: i32 = 20;
+
+fn validate_number_padding(number_padding: i32) -> Result<i32, String> {
+    if !(0..=MAX_NUMBER_PADDING).contains(&number_padding) {
+        return Err(format!("Invalid number padding {}, expected 0 to {}", number_padding, MAX_NUMBER_PADDING));
+    }
+    Ok(number_padding)
+}
+
+/// Font sizes are in pixels, 0 meaning auto-fit to the crop_number area.
+fn validate_font_size(font_size: f32) -> Result<f32, String> {
+    if !font_size.is_finite() || font_size < 0.0 {
+        return Err(format!("Invalid font size {}, expected 0 (auto) or a positive size", font_size));
+    }
+    Ok(font_size)
+}
+
+/// Check that a template's font can be loaded, so a typo fails when saving rather than when generating.
+fn validate_font_id(font_id: String) -> Result<String, String> {
+    let font_id = font_id.trim().to_string();
+    text::load_font(Some(&font_id))?;
+    Ok(font_id)
+}
+
+/// Parse a template's `tags` JSON array, an empty string meaning no tags.
+fn parse_template_tags(tags: &str) -> Result<Vec<String>, String> {
+    if tags.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(tags).map_err(|e| format!("Error parsing template tags: {}", e))
+}
+
+/// Trim tags and drop empty and repeated ones, returning the JSON array to store.
+fn normalize_template_tags(tags: &str) -> Result<String, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in parse_template_tags(tags)? {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|existing| existing == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    serde_json::to_string(&normalized).map_err(|e| format!("Error serializing template tags: {}", e))
+}
+
+fn parse_default_watermark(default_watermark: &str) -> Result<Option<WatermarkOptions>, String> {
+    if default_watermark.is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(default_watermark)
+        .map(Some)
+        .map_err(|e| format!("Error parsing default watermark: {}", e))
+}
+
+fn parse_template_crops(template: &PhotoTemplate) -> Result<(CropCoordinates, Option<CropCoordinates>), String> {
+    let crop_photo: CropCoordinates = serde_json::from_str(&template.crop_photo)
+        .map_err(|e| format!("Error parsing crop coordinates: {}", e))?;
+
+    let crop_number = if !template.crop_number.is_empty() {
+        Some(serde_json::from_str(&template.crop_number)
+            .map_err(|e| format!("Error parsing crop_number coordinates: {}", e))?)
+    } else {
+        None
+    };
+
+    Ok((crop_photo, crop_number))
+}
+
+/// Parse crop coordinates the frontend may send loosely: any key casing, numbers as strings, extra fields.
+fn parse_crop_lenient(raw: &str) -> Result<CropCoordinates, String> {
+    let value: serde_json::Value = serde_json::from_str(raw)
+        .map_err(|e| format!("Error parsing crop coordinates: {}", e))?;
+    let serde_json::Value::Object(fields) = value else {
+        return Err("Crop coordinates must be a JSON object".to_string());
+    };
+
+    let field = |name: &str| -> Result<f32, String> {
+        let value = fields
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+            .ok_or(format!("Crop coordinates are missing \"{}\"", name))?;
+        let number = match value {
+            serde_json::Value::Number(number) => number.as_f64(),
+            serde_json::Value::String(text) => text.trim().parse::<f64>().ok(),
+            _ => None,
+        };
+        match number {
+            Some(number) if number.is_finite() => Ok(number as f32),
+            _ => Err(format!("Crop coordinate \"{}\" is not a number: {}", name, value)),
+        }
+    };
+
+    let crop = CropCoordinates {
+        x: field("x")?,
+        y: field("y")?,
+        width: field("width")?,
+        height: field("height")?,
+    };
+    if crop.width < 0.0 || crop.height < 0.0 {
+        return Err(format!("Crop dimensions must not be negative, got {}x{}", crop.width, crop.height));
+    }
+
+    Ok(crop)
+}
+
+fn load_image(image_path: &str) -> Result<DynamicImage, String> {
+    if heic::is_heic(Path::new(image_path)) {
+        return heic::decode(Path::new(image_path));
+    }
+    image::open(image_path)
+        .map_err(|e| format!("Error loading image {}: {}", image_path, e))
+}
+
+/// Source extensions picked up when a run doesn't set `extensions`.
+const DEFAULT_IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "bmp", "gif", "tiff"];
+
+/// `.PNG` and `png` both become `png`.
+fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+/// Decides which files are picked up as source images.
+struct ImageFileFilter {
+    extensions: Vec<String>,
+    include_patterns: Vec<Regex>,
+    exclude_patterns: Vec<Regex>,
+}
+
+impl ImageFileFilter {
+    fn new(options: &GenerationOptions) -> Result<Self, String> {
+        let base_extensions: Vec<String> = match &options.extensions {
+            Some(extensions) => extensions.iter().map(|ext| normalize_extension(ext)).collect(),
+            // HEIC files are listed even without HEIC support so they fail with a clear error
+            None => DEFAULT_IMAGE_EXTENSIONS
+                .iter()
+                .chain(heic::HEIC_EXTENSIONS)
+                .map(|ext| ext.to_string())
+                .collect(),
+        };
+        Ok(ImageFileFilter {
+            extensions: base_extensions
+                .into_iter()
+                .chain(options.extra_extensions.iter().map(|ext| normalize_extension(ext)))
+                .collect(),
+            include_patterns: compile_filename_patterns(&options.include_patterns)?,
+            exclude_patterns: compile_filename_patterns(&options.exclude_patterns)?,
+        })
+    }
+
+    fn accepts(&self, path: &Path) -> bool {
+        let Some(ext_str) = path.extension().and_then(|ext| ext.to_str()) else {
+            return false;
+        };
+        if !self.extensions.contains(&ext_str.to_lowercase()) {
+            return false;
+        }
+
+        let filename = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let included = self.include_patterns.is_empty()
+            || self.include_patterns.iter().any(|pattern| pattern.is_match(&filename));
+        let excluded = self.exclude_patterns.iter().any(|pattern| pattern.is_match(&filename));
+        included && !excluded
+    }
+}
+
+fn find_image_files(folder_path: &str, options: &GenerationOptions) -> Result<Vec<PathBuf>, String> {
+    scan_image_files(folder_path, options, |_| {}, &mut Vec::new())
+}
+
+/// Sent as `scan-progress` while a source folder is being listed.
+#[derive(Serialize, Clone)]
+struct ScanProgress {
+    folder: String,
+    /// Directory entries looked at so far
+    scanned: usize,
+    /// Image files accepted so far
+    found: usize,
+    done: bool,
+}
+
+/// Sent as `source-skipped` for a source left out of a run, it is also listed in the run's failures.
+#[derive(Serialize, Clone)]
+struct SkippedSource {
+    path: String,
+    reason: String,
+}
+
+/// Why a zero-byte source is skipped, interrupted downloads leave such files behind
+const EMPTY_SOURCE_REASON: &str = "File is empty (0 bytes), it may be an interrupted download";
+
+/// Directory entries between two `scan-progress` updates
+const SCAN_PROGRESS_EVERY: usize = 500;
+
+/// `find_image_files` reporting periodic counts to `on_scan`, for folders large enough to take a while.
+///
+/// Empty files can never decode, they are added to `empty_files` instead of the returned list.
+fn scan_image_files(
+    folder_path: &str,
+    options: &GenerationOptions,
+    mut on_scan: impl FnMut(ScanProgress),
+    empty_files: &mut Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, String> {
+    let mut image_files = Vec::new();
+    let filter = ImageFileFilter::new(options)?;
+    let mut scanned = 0;
+    
+    let mut walker = WalkDir::new(folder_path);
+    if !options.recursive {
+        walker = walker.max_depth(1);
+    }
+    for entry in walker {
+        let entry = entry.map_err(|e| format!("Error walking directory: {}", e))?;
+        // The folder itself is not one of its entries
+        if entry.depth() == 0 && entry.file_type().is_dir() {
+            continue;
+        }
+        scanned += 1;
+        if scanned % SCAN_PROGRESS_EVERY == 0 {
+            on_scan(ScanProgress {
+                folder: folder_path.to_string(),
+                scanned,
+                found: image_files.len(),
+                done: false,
+            });
+        }
+        
+        if entry.file_type().is_file() && filter.accepts(entry.path()) {
+            if entry.metadata().is_ok_and(|metadata| metadata.len() == 0) {
+                empty_files.push(entry.path().to_path_buf());
+                continue;
+            }
+            image_files.push(entry.path().to_path_buf());
+        }
+    }
+
+    on_scan(ScanProgress {
+        folder: folder_path.to_string(),
+        scanned,
+        found: image_files.len(),
+        done: true,
+    });
+    
+    image_files.sort();
+    Ok(image_files)
+}
+
+/// Scratch folder, inside the output directory, receiving images extracted from ZIP sources
+const EXTRACTED_SOURCES_DIR: &str = "extracted_sources";
+/// Folder, inside the output directory, receiving the scaled-down copies of `preview_max_dimension`
+const PREVIEWS_DIR: &str = "previews";
+
+/// Gather the images of the main folder and of every extra source (folder or ZIP).
+///
+/// Images are merged into one list sorted by filename, ties keeping the source order.
+/// Also returns output stems for files whose name exists in several sources,
+/// prefixed with their source's name so outputs can't overwrite each other.
+/// Empty files are left out and added to `empty_files`.
+fn resolve_sources(
+    image_folder_path: &str,
+    options: &GenerationOptions,
+    extract_dir: &Path,
+    mut on_scan: impl FnMut(ScanProgress),
+    empty_files: &mut Vec<PathBuf>,
+) -> Result<(Vec<PathBuf>, HashMap<PathBuf, String>), String> {
+    // Leftovers of an interrupted run would otherwise be picked up again
+    let _ = fs::remove_dir_all(extract_dir);
+
+    let sources: Vec<&str> = std::iter::once(image_folder_path)
+        .chain(options.sources.iter().map(String::as_str))
+        .collect();
+
+    let mut files: Vec<(String, String, PathBuf)> = Vec::new();
+    for (source_index, source) in sources.iter().enumerate() {
+        let source_path = Path::new(source);
+        let source_name = source_path
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("source{}", source_index + 1));
+
+        let is_zip = source_path.is_file()
+            && source_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+        let found = if is_zip {
+            let target_dir = extract_dir.join(format!("{}_{}", source_index + 1, source_name));
+            extract_zip_images(source_path, &target_dir, options, empty_files)?
+        } else {
+            scan_image_files(source, options, &mut on_scan, empty_files)?
+        };
+
+        files.extend(found.into_iter().map(|file| (source_name.clone(), relative_stem(source_path, &file), file)));
+    }
+
+    if sources.len() > 1 {
+        files.sort_by(|(_, _, a), (_, _, b)| a.file_name().cmp(&b.file_name()));
+    }
+
+    let mut stem_counts: HashMap<String, usize> = HashMap::new();
+    for (_, stem, _) in &files {
+        *stem_counts.entry(stem.clone()).or_default() += 1;
+    }
+
+    let mut output_stems = HashMap::new();
+    for (source_name, stem, file) in &files {
+        if stem_counts.get(stem).copied().unwrap_or(0) > 1 {
+            output_stems.insert(file.clone(), format!("{}_{}", source_name, stem));
+        } else if stem.contains('/') {
+            output_stems.insert(file.clone(), stem.clone());
+        }
+    }
+
+    Ok((files.into_iter().map(|(_, _, file)| file).collect(), output_stems))
+}
+
+/// Stem of `file` with the subfolders between `source` and it, e.g. `2024/a` for `source/2024/a.jpg`.
+fn relative_stem(source: &Path, file: &Path) -> String {
+    let stem = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let subfolders: Vec<String> = file
+        .parent()
+        .and_then(|parent| parent.strip_prefix(source).ok())
+        .map(|subfolder| {
+            subfolder
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    if subfolders.is_empty() {
+        stem
+    } else {
+        format!("{}/{}", subfolders.join("/"), stem)
+    }
+}
+
+/// Extract the supported images of a ZIP into `target_dir`, flattening its folders.
+///
+/// Empty entries are not extracted, they are added to `empty_files` as `<zip path>/<entry name>`.
+fn extract_zip_images(
+    zip_path: &Path,
+    target_dir: &Path,
+    options: &GenerationOptions,
+    empty_files: &mut Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, String> {
+    let filter = ImageFileFilter::new(options)?;
+    let file = fs::File::open(zip_path)
+        .map_err(|e| format!("Error opening ZIP source {:?}: {}", zip_path, e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("Error reading ZIP source {:?}: {}", zip_path, e))?;
+
+    fs::create_dir_all(target_dir)
+        .map_err(|e| format!("Error creating extraction directory: {}", e))?;
+
+    let mut extracted = Vec::new();
+    for entry_index in 0..archive.len() {
+        let mut entry = archive.by_index(entry_index)
+            .map_err(|e| format!("Error reading ZIP entry: {}", e))?;
+        // Only keep the file name, entry paths can't be trusted to stay inside target_dir
+        let Some(filename) = entry.enclosed_name().and_then(|name| name.file_name()).map(|name| name.to_owned()) else {
+            continue;
+        };
+        if !entry.is_file() || !filter.accepts(Path::new(&filename)) {
+            continue;
+        }
+        if entry.size() == 0 {
+            empty_files.push(zip_path.join(&filename));
+            continue;
+        }
+
+        let mut target_path = target_dir.join(&filename);
+        if target_path.exists() {
+            target_path = target_dir.join(format!("{}_{}", entry_index, filename.to_string_lossy()));
+        }
+
+        let mut output = fs::File::create(&target_path)
+            .map_err(|e| format!("Error extracting {:?}: {}", filename, e))?;
+        std::io::copy(&mut entry, &mut output)
+            .map_err(|e| format!("Error extracting {:?}: {}", filename, e))?;
+        extracted.push(target_path);
+    }
+
+    extracted.sort();
+    Ok(extracted)
+}
+
+/// Turn filename patterns into case-insensitive regexes.
+///
+/// Patterns support `*` and `?` wildcards and must match the whole filename;
+/// a pattern without wildcards matches any filename containing it.
+fn compile_filename_patterns(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns
+        .iter()
+        .filter(|pattern| !pattern.trim().is_empty())
+        .map(|pattern| {
+            let escaped = regex::escape(pattern.trim())
+                .replace(r"\*", ".*")
+                .replace(r"\?", ".");
+            let expression = if pattern.contains(['*', '?']) {
+                format!("(?i)^{}$", escaped)
+            } else {
+                format!("(?i){}", escaped)
+            };
+            Regex::new(&expression).map_err(|e| format!("Invalid filename pattern {}: {}", pattern, e))
+        })
+        .collect()
+}
+
+/// Decode a source photo, then apply the optional auto-enhance and color grading `lut`.
+fn load_source_image(
+    source_path: &Path,
+    options: &GenerationOptions,
+    lut: Option<&lut::Lut3d>,
+) -> Result<DynamicImage, String> {
+    let img = if heic::is_heic(source_path) {
+        heic::decode(source_path)?
+    } else {
+        // Detect the format from the content so files with extra extensions (e.g. `.jfif`) still decode
+        image::io::Reader::open(source_path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|e| format!("Error loading image {:?}: {}", source_path, e))?
+            .decode()
+            .map_err(|e| match e {
+                image::ImageError::IoError(io_error) if io_error.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    format!("Error loading image {:?}: file appears truncated ({})", source_path, io_error)
+                }
+                e => format!("Error loading image {:?}: {}", source_path, e),
+            })?
+    };
+
+    let img = if options.auto_enhance {
+        enhance::auto_enhance(img, source_path)
+    } else {
+        img
+    };
+    let img = if options.deskew { deskew::deskew(img) } else { img };
+    Ok(match lut {
+        Some(lut) => lut.apply(img),
+        None => img,
+    })
+}
+
+fn resize_image(
+    img: DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    preserve_ratio: bool,
+) -> DynamicImage {
+
+    if preserve_ratio {
+        // Calculate the scaling factor to fit within target dimensions while preserving aspect ratio
+        let (orig_width, orig_height) = (img.width(), img.height());
+        let width_ratio = target_width as f32 / orig_width as f32;
+        let height_ratio = target_height as f32 / orig_height as f32;
+        let scale_ratio = width_ratio.min(height_ratio);
+        
+        let new_width = (orig_width as f32 * scale_ratio) as u32;
+        let new_height = (orig_height as f32 * scale_ratio) as u32;
+        
+        img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3)
+    } else {
+        img.resize_exact(target_width, target_height, image::imageops::FilterType::Lanczos3)
+    }
+}
+
+/// Scale `img` to cover `target_width` x `target_height`, then crop the overflow keeping the part around `focus`.
+fn cover_crop(
+    img: &DynamicImage,
+    target_width: u32,
+    target_height: u32,
+    focus: CropFocus,
+    filter: image::imageops::FilterType,
+) -> DynamicImage {
+    let scale = (target_width as f32 / img.width() as f32).max(target_height as f32 / img.height() as f32);
+    let scaled_width = ((img.width() as f32 * scale).ceil() as u32).max(target_width);
+    let scaled_height = ((img.height() as f32 * scale).ceil() as u32).max(target_height);
+    let scaled = img.resize_exact(scaled_width, scaled_height, filter);
+
+    let x = ((scaled_width - target_width) as f32 * focus.x.clamp(0.0, 1.0)).round() as u32;
+    let y = ((scaled_height - target_height) as f32 * focus.y.clamp(0.0, 1.0)).round() as u32;
+    scaled.crop_imm(x, y, target_width, target_height)
+}
+
+/// Scale `img` into a `slot_width` x `slot_height` image with `fit`.
+///
+/// A contained photo is placed at `anchor` (0..1 on each axis, e.g. 0.5 to center it) of the space
+/// it leaves free, the rest of the slot stays transparent.
+fn fit_to_slot(
+    img: &DynamicImage,
+    slot_width: u32,
+    slot_height: u32,
+    fit: FitMode,
+    anchor: CropFocus,
+    crop_focus: CropFocus,
+) -> DynamicImage {
+    match fit {
+        FitMode::Cover => cover_crop(img, slot_width, slot_height, crop_focus, image::imageops::FilterType::Lanczos3),
+        FitMode::Stretch => resize_image(img.clone(), slot_width, slot_height, false),
+        FitMode::Contain => {
+            let contained = resize_image(img.clone(), slot_width, slot_height, true);
+            let x = ((slot_width - contained.width()) as f32 * anchor.x.clamp(0.0, 1.0)).round();
+            let y = ((slot_height - contained.height()) as f32 * anchor.y.clamp(0.0, 1.0)).round();
+            let mut slot = DynamicImage::new_rgba8(slot_width, slot_height);
+            image::imageops::overlay(&mut slot, &contained, x as i64, y as i64);
+            slot
+        }
+    }
+}
+
+/// Resize a source photo for its slot according to `fit_mode`, tilt it by `photo_rotation_degrees`
+/// and fill the leftover space according to `gap_fill`.
+fn prepare_slot_image(
+    source_image: DynamicImage,
+    slot_width: u32,
+    slot_height: u32,
+    options: &GenerationOptions,
+) -> DynamicImage {
+    let rotation = options.photo_rotation_degrees % 360.0;
+    let contained = match options.fit_mode {
+        FitMode::Contain if options.gap_fill == GapFill::None && rotation == 0.0 => {
+            return resize_image(source_image, slot_width, slot_height, true);
+        }
+        FitMode::Contain => resize_image(source_image.clone(), slot_width, slot_height, true),
+        // Cover and stretch fill the whole slot, only a rotation can leave gaps
+        fit => fit_to_slot(&source_image, slot_width, slot_height, fit, CropFocus::default(), options.crop_focus),
+    };
+    if rotation == 0.0 && contained.width() >= slot_width && contained.height() >= slot_height {
+        return contained;
+    }
+
+    let mut slot = match options.gap_fill {
+        GapFill::Blur => {
+            // Cover-scale the photo so it fills the slot, then blur it into a soft backdrop
+            let backdrop = cover_crop(&source_image, slot_width, slot_height, options.crop_focus, image::imageops::FilterType::Triangle);
+            let sigma = slot_width.max(slot_height) as f32 / 40.0;
+            DynamicImage::ImageRgba8(backdrop.blur(sigma).to_rgba8())
+        }
+        GapFill::Color => DynamicImage::ImageRgba8(ImageBuffer::from_pixel(
+            slot_width,
+            slot_height,
+            Rgba(options.gap_fill_color.unwrap_or([255, 255, 255, 255])),
+        )),
+        GapFill::None => DynamicImage::ImageRgba8(ImageBuffer::new(slot_width, slot_height)),
+    };
+
+    let x = (slot_width - contained.width()) / 2;
+    let y = (slot_height - contained.height()) / 2;
+    if rotation == 0.0 {
+        image::imageops::overlay(&mut slot, &contained, x as i64, y as i64);
+    } else {
+        // Rotate on a slot-sized transparent canvas so the corners turning out of the slot are clipped
+        let mut canvas = image::RgbaImage::new(slot_width, slot_height);
+        image::imageops::overlay(&mut canvas, &contained.to_rgba8(), x as i64, y as i64);
+        let rotated = imageproc::geometric_transformations::rotate_about_center(
+            &canvas,
+            rotation.to_radians(),
+            imageproc::geometric_transformations::Interpolation::Bilinear,
+            Rgba([0, 0, 0, 0]),
+        );
+        image::imageops::overlay(&mut slot, &rotated, 0, 0);
+    }
+    slot
+}
+
+fn build_mosaic(
+    source_paths: &[PathBuf],
+    slot_width: u32,
+    slot_height: u32,
+    mosaic: MosaicOptions,
+    options: &GenerationOptions,
+    lut: Option<&lut::Lut3d>,
+) -> Result<DynamicImage, String> {
+    let (columns, rows) = mosaic.grid_for(source_paths.len());
+    let cell_width = slot_width / columns;
+    let cell_height = slot_height / rows;
+    if cell_width == 0 || cell_height == 0 {
+        return Err(format!("Photo slot is too small for a {}x{} mosaic", columns, rows));
+    }
+
+    let mut canvas = DynamicImage::new_rgba8(slot_width, slot_height);
+
+    for (cell, source_path) in source_paths.iter().enumerate() {
+        let cell = cell as u32;
+        let tile = resize_image(load_source_image(source_path, options, lut)?, cell_width, cell_height, true);
+
+        // Center each photo inside its grid cell
+        let x = (cell % columns) * cell_width + (cell_width - tile.width()) / 2;
+        let y = (cell / columns) * cell_height + (cell_height - tile.height()) / 2;
+        image::imageops::overlay(&mut canvas, &tile, x as i64, y as i64);
+    }
+
+    Ok(canvas)
+}
+
+fn extract_number_from_filename(
+    filename: &str,
+    fallback_id: usize,
+    no_number_behavior: NoNumberBehavior,
+) -> Result<String, String> {
+    let re = Regex::new(r"([0-9]+)").unwrap();
+    if let Some(captures) = re.captures(filename) {
+        if let Some(number_match) = captures.get(1) {
+            return Ok(number_match.as_str().to_string());
+        }
+    }
+
+    match no_number_behavior {
+        NoNumberBehavior::Sequence => Ok(fallback_id.to_string()),
+        NoNumberBehavior::Blank => Ok(String::new()),
+        NoNumberBehavior::Error => Err(format!("No number found in file name \"{}\"", filename)),
+    }
+}
+
+/// Left-pad a number with zeros to `width` digits, so `7` becomes `007` for a width of 3.
+///
+/// Blank numbers stay blank and longer numbers are never truncated.
+fn pad_number(number: &str, width: usize) -> String {
+    if number.is_empty() {
+        return String::new();
+    }
+    format!("{:0>width$}", number, width = width)
+}
+
+fn composite_images_with_text(
+    template_image: &DynamicImage,
+    source_image: &DynamicImage,
+    crop_coords: &CropCoordinates,
+    crop_number_coords: Option<&CropCoordinates>,
+    number: &str,
+    number_style: &NumberStyle,
+    options: &GenerationOptions,
+) -> Result<DynamicImage, String> {
+    // First, composite the images normally
+    let mut result = composite_images(template_image, source_image, crop_coords, options)?;
+    
+    // Add text overlay if crop_number coordinates are available and there is a number to show
+    if let Some(txt_crop) = crop_number_coords.filter(|_| !number.is_empty()) {
+        // Always add text overlay - removed format detection that was causing the error
+        // The original PHP logic for PNG detection is not critical for functionality
+        result = add_text_overlay(result, txt_crop, number, number_style, options)?;
+    }
+    
+    Ok(result)
+}
+
+fn add_text_overlay(
+    image: DynamicImage,
+    txt_crop: &CropCoordinates,
+    number: &str,
+    number_style: &NumberStyle,
+    options: &GenerationOptions,
+) -> Result<DynamicImage, String> {
+    // Create the text to display
+    let text = number_style.format.replace("{n}", number);
+    
+    // Draw the glyphs with the template's font, centered in the crop_number area and fitted to it
+    // unless the template sets a font size
+    Ok(text::draw_keeping_depth(image, |rgba_image| {
+        text::draw_text_in_box(
+            rgba_image,
+            &number_style.font,
+            &text,
+            (txt_crop.x, txt_crop.y, txt_crop.width, txt_crop.height),
+            number_style.font_size,
+            number_style.color,
+            options.text_gradient(),
+            options.text_arc(),
+        )
+    }))
+}
+
+fn composite_images(
+    template_image: &DynamicImage,
+    source_image: &DynamicImage,
+    crop_coords: &CropCoordinates,
+    options: &GenerationOptions,
+) -> Result<DynamicImage, String> {
+    // Get the actual dimensions of the resized source image
+    let source_width = source_image.width();
+    let source_height = source_image.height();
+    
+    // Calculate the available space in the crop area
+    let crop_width = crop_coords.width as u32;
+    let crop_height = crop_coords.height as u32;
+    
+    // Calculate centering offsets
+    let offset_x = if crop_width > source_width {
+        (crop_width - source_width) / 2
+    } else {
+        0
+    };
+    
+    let offset_y = if crop_height > source_height {
+        (crop_height - source_height) / 2
+    } else {
+        0
+    };
+    
+    // Calculate final centered position
+    let centered_x = (crop_coords.x as u32 + offset_x) as i64;
+    let centered_y = (crop_coords.y as u32 + offset_y) as i64;
+    
+    // A faded photo lets the template underneath show through
+    let faded_source;
+    let source_image = match options.photo_opacity {
+        Some(opacity) if opacity < 1.0 => {
+            faded_source = with_opacity(source_image, opacity, options.color_type.is_16_bit());
+            &faded_source
+        }
+        _ => source_image,
+    };
+
+    // Overlay the source image onto the template at the centered coordinates.
+    // Frame-style templates are drawn over the photo so it shows through their transparent cutout.
+    let layers = if options.template_on_top {
+        vec![(source_image, centered_x, centered_y), (template_image, 0, 0)]
+    } else {
+        vec![(template_image, 0, 0), (source_image, centered_x, centered_y)]
+    };
+
+    // Work in RGBA so transparent areas of the template survive the overlay
+    let (width, height) = (template_image.width(), template_image.height());
+    let result = if options.color_type.is_16_bit() {
+        DynamicImage::ImageRgba16(stack_layers(width, height, &layers, DynamicImage::to_rgba16))
+    } else {
+        DynamicImage::ImageRgba8(stack_layers(width, height, &layers, DynamicImage::to_rgba8))
+    };
+    
+    Ok(result)
+}
+
+/// Scale the alpha channel of an image by `opacity` (clamped to 0..1).
+fn with_opacity(image: &DynamicImage, opacity: f32, sixteen_bit: bool) -> DynamicImage {
+    let opacity = opacity.clamp(0.0, 1.0);
+    if sixteen_bit {
+        let mut rgba = image.to_rgba16();
+        for pixel in rgba.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u16;
+        }
+        DynamicImage::ImageRgba16(rgba)
+    } else {
+        let mut rgba = image.to_rgba8();
+        for pixel in rgba.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+        }
+        DynamicImage::ImageRgba8(rgba)
+    }
+}
+
+fn stack_layers<P: Pixel>(
+    width: u32,
+    height: u32,
+    layers: &[(&DynamicImage, i64, i64)],
+    convert: impl Fn(&DynamicImage) -> ImageBuffer<P, Vec<P::Subpixel>>,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::new(width, height);
+    for (index, (layer, x, y)) in layers.iter().enumerate() {
+        let layer = convert(layer);
+        if index == 0 && *x == 0 && *y == 0 && layer.dimensions() == (width, height) {
+            // Start from the bottom layer as-is instead of blending it onto an empty canvas
+            canvas = layer;
+        } else {
+            image::imageops::overlay(&mut canvas, &layer, *x, *y);
+        }
+    }
+    canvas
+}
+
+fn save_output_image(
+    image: &DynamicImage,
+    output_path: &Path,
+    options: &GenerationOptions,
+    number: &str,
+) -> Result<(), String> {
+    // Only the alpha color types keep the template's transparent areas, JPEG is always flattened
+    let color_type = match (options.output_format, options.color_type) {
+        (OutputFormat::Jpeg, _) => OutputColorType::Rgb8,
+        (_, OutputColorType::Auto) if has_transparency(image) => OutputColorType::Rgba8,
+        (_, OutputColorType::Auto) => OutputColorType::Rgb8,
+        (OutputFormat::Png | OutputFormat::Tiff, color_type) => color_type,
+        // The WebP encoder only takes 8-bit buffers
+        (OutputFormat::WebP, OutputColorType::Rgb8 | OutputColorType::Rgb16) => OutputColorType::Rgb8,
+        (OutputFormat::WebP, _) => OutputColorType::Rgba8,
+    };
+    // Dropping the alpha channel as-is would reveal whatever color the transparent pixels hold, often black
+    let flattened;
+    let image = match color_type {
+        OutputColorType::Rgb8 | OutputColorType::Rgb16 if has_transparency(image) => {
+            flattened = flatten_alpha(image);
+            &flattened
+        }
+        _ => image,
+    };
+    let image = match color_type {
+        // Auto is resolved above
+        OutputColorType::Auto | OutputColorType::Rgb8 => DynamicImage::ImageRgb8(image.to_rgb8()),
+        OutputColorType::Rgba8 => DynamicImage::ImageRgba8(image.to_rgba8()),
+        OutputColorType::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+        OutputColorType::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+    };
+
+    let output_format = match options.output_format {
+        // The image crate's encoder always writes 4:4:4
+        OutputFormat::Jpeg => None,
+        OutputFormat::Png => Some(image::ImageOutputFormat::Png),
+        OutputFormat::Tiff => Some(image::ImageOutputFormat::Tiff),
+        OutputFormat::WebP => Some(image::ImageOutputFormat::WebP),
+    };
+    let mut bytes = match output_format {
+        Some(output_format) => {
+            let mut encoded = std::io::Cursor::new(Vec::new());
+            image.write_to(&mut encoded, output_format)
+                .map_err(|e| format!("Error encoding image: {}", e))?;
+            encoded.into_inner()
+        }
+        None => encode_jpeg(&image.to_rgb8(), options.jpeg_quality, options.jpeg_subsampling)?,
+    };
+
+    // Store the print number in XMP so ordering systems can read it without the overlay
+    if options.write_number_to_metadata && !number.is_empty() {
+        let xmp = metadata::build_xmp_packet(options.metadata_field, number);
+        bytes = match options.output_format {
+            OutputFormat::Jpeg => metadata::embed_xmp_in_jpeg(&bytes, &xmp)?,
+            OutputFormat::Png => metadata::embed_xmp_in_png(&bytes, &xmp)?,
+            // TIFF stores XMP in an IFD tag and WebP in a RIFF chunk, neither encoder exposes them,
+            // `prepare_template` refuses these runs
+            OutputFormat::Tiff | OutputFormat::WebP => bytes,
+        };
+    }
+
+    fs::write(output_path, bytes)
+        .map_err(|e| format!("Error saving image: {}", e))
+}
+
+/// Whether any pixel of `image` is not fully opaque.
+fn has_transparency(image: &DynamicImage) -> bool {
+    match image {
+        DynamicImage::ImageRgba8(buffer) => buffer.pixels().any(|pixel| pixel[3] < u8::MAX),
+        DynamicImage::ImageRgba16(buffer) => buffer.pixels().any(|pixel| pixel[3] < u16::MAX),
+        _ if image.color().has_alpha() => image.to_rgba16().pixels().any(|pixel| pixel[3] < u16::MAX),
+        _ => false,
+    }
+}
+
+/// Blend `image` onto an opaque white background, keeping 16-bit images in 16-bit.
+fn flatten_alpha(image: &DynamicImage) -> DynamicImage {
+    let (width, height) = (image.width(), image.height());
+    let background = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(width, height, Rgba([255, 255, 255, 255])));
+    let layers = [(&background, 0, 0), (image, 0, 0)];
+    if matches!(image, DynamicImage::ImageRgba16(_) | DynamicImage::ImageLumaA16(_)) {
+        DynamicImage::ImageRgba16(stack_layers(width, height, &layers, DynamicImage::to_rgba16))
+    } else {
+        DynamicImage::ImageRgba8(stack_layers(width, height, &layers, DynamicImage::to_rgba8))
+    }
+}
+
+fn encode_jpeg(image: &image::RgbImage, quality: JpegQuality, subsampling: JpegSubsampling) -> Result<Vec<u8>, String> {
+    let (width, height) = (
+        u16::try_from(image.width()).map_err(|_| "Image is too wide for JPEG".to_string())?,
+        u16::try_from(image.height()).map_err(|_| "Image is too tall for JPEG".to_string())?,
+    );
+    let mut bytes = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut bytes, quality.0);
+    encoder.set_sampling_factor(subsampling.sampling_factor());
+    encoder
+        .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+        .map_err(|e| format!("Error encoding image: {}", e))?;
+    Ok(bytes)
+}
+
+fn encode_data_url(image: &DynamicImage, format: image::ImageOutputFormat) -> Result<String, String> {
+    use base64::Engine;
+
+    let (mime, image) = match format {
+        image::ImageOutputFormat::Jpeg(_) => ("image/jpeg", DynamicImage::ImageRgb8(flatten_alpha(image).to_rgb8())),
+        _ => ("image/png", DynamicImage::ImageRgba8(image.to_rgba8())),
+    };
+
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)
+        .map_err(|e| format!("Error encoding image: {}", e))?;
+
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(encoded.into_inner())
+    ))
+}
+
+/// Entry name of an output in the archive, keeping the subfolders it was written to inside `output_dir`.
+fn output_entry_name(output: &Path, output_dir: &Path) -> String {
+    match output.strip_prefix(output_dir) {
+        Ok(relative) if relative.components().count() > 1 => relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        _ => archive_entry_name(output),
+    }
+}
+
+fn archive_entry_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("processed_image.jpg")
+        .to_string()
+}
+
+#[derive(Serialize)]
+struct SelfTestStep {
+    name: String,
+    passed: bool,
+    duration_ms: u128,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SelfTestReport {
+    passed: bool,
+    steps: Vec<SelfTestStep>,
+}
+
+/// Time `step` and record its outcome, returning its value when it passed.
+fn run_self_test_step<T>(
+    steps: &mut Vec<SelfTestStep>,
+    name: &str,
+    step: impl FnOnce() -> Result<T, String>,
+) -> Option<T> {
+    let started = std::time::Instant::now();
+    let result = step();
+    steps.push(SelfTestStep {
+        name: name.to_string(),
+        passed: result.is_ok(),
+        duration_ms: started.elapsed().as_millis(),
+        error: result.as_ref().err().cloned(),
+    });
+    result.ok()
+}
+
+/// Run the whole pipeline (composite, overlay, encode, zip) on a synthetic template and photo
+/// in a temporary directory, without touching the database or user files.
+#[tauri::command]
+fn run_self_test() -> SelfTestReport {
+    let work_dir = std::env::temp_dir().join(format!(
+        "photo_template_self_test_{}_{}",
+        std::process::id(),
+        chrono::Local::now().format("%Y%m%d%H%M%S%3f")
+    ));
+    let photos_dir = work_dir.join("photos");
+    let output_dir = work_dir.join("output");
+    let mut steps = Vec::new();
+
+    let template = run_self_test_step(&mut steps, "prepare inputs", || {
+        fs::create_dir_all(&photos_dir).map_err(|e| format!("Error creating self-test directory: {}", e))?;
+        fs::create_dir_all(&output_dir).map_err(|e| format!("Error creating self-test directory: {}", e))?;
+
+        let template_path = work_dir.join("template.png");
+        ImageBuffer::from_pixel(400, 300, Rgba([240u8, 240, 240, 255]))
+            .save(&template_path)
+            .map_err(|e| format!("Error writing self-test template: {}", e))?;
+        image::RgbImage::from_fn(320, 240, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]))
+            .save(photos_dir.join("photo_0042.png"))
+            .map_err(|e| format!("Error writing self-test photo: {}", e))?;
+
+        let crop = |x: f32, y: f32, width: f32, height: f32| {
+            serde_json::to_string(&CropCoordinates { x, y, width, height })
+                .map_err(|e| format!("Error serializing crop coordinates: {}", e))
+        };
+        Ok(PhotoTemplate {
+            id: 0,
+            name: "Self-test".to_string(),
+            crop_photo: crop(20.0, 20.0, 360.0, 200.0)?,
+            crop_number: crop(20.0, 240.0, 360.0, 40.0)?,
+            template_img: template_path.to_string_lossy().to_string(),
+            image_width: 400,
+            image_height: 300,
+            default_watermark: String::new(),
+            font_size: 0.0,
+            text_color: DEFAULT_TEXT_COLOR.to_string(),
+            number_format: DEFAULT_NUMBER_FORMAT.to_string(),
+            number_padding: 0,
+            default_output_folder: String::new(),
+            tags: "[]".to_string(),
+            font_id: String::new(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: chrono::Utc::now().naive_utc(),
+        })
+    });
+
+    let options = GenerationOptions::default();
+    let generated = template.and_then(|template| {
+        run_self_test_step(&mut steps, "generate", || {
+            let run = generate_images(
+                &template,
+                &OrientationTemplates::default(),
+                &photos_dir.to_string_lossy(),
+                &output_dir,
+                &options,
+                &AtomicBool::new(false),
+                &mut (),
+            )?;
+            if run.images.len() != 1 {
+                return Err(format!("Expected 1 output, got {}", run.images.len()));
+            }
+            Ok(run.images)
+        })
+    });
+
+    let decoded = generated.as_ref().and_then(|images| {
+        run_self_test_step(&mut steps, "decode outputs", || {
+            for generated in images {
+                let output = image::open(&generated.output)
+                    .map_err(|e| format!("Error decoding {:?}: {}", generated.output, e))?;
+                if (output.width(), output.height()) != (400, 300) {
+                    return Err(format!("Output is {}x{} instead of 400x300", output.width(), output.height()));
+                }
+            }
+            Ok(())
+        })
+    });
+
+    if let (Some(images), Some(())) = (&generated, decoded) {
+        run_self_test_step(&mut steps, "archive", || {
+            let entries = images
+                .iter()
+                .map(|image| (image.output.clone(), archive_entry_name(&image.output)))
+                .collect();
+            let archive_path = create_archive(
+                entries,
+                &output_dir.join("generated_images.zip"),
+                DuplicateEntryPolicy::Rename,
+                false,
+                ArchiveCompression::default(),
+                &AtomicBool::new(false),
+            )?;
+            let archive = fs::File::open(&archive_path)
+                .map_err(|e| format!("Error opening archive: {}", e))
+                .and_then(|file| zip::ZipArchive::new(file).map_err(|e| format!("Error reading archive: {}", e)))?;
+            if archive.len() != images.len() {
+                return Err(format!("Archive holds {} entries instead of {}", archive.len(), images.len()));
+            }
+            Ok(())
+        });
+    }
+
+    let _ = fs::remove_dir_all(&work_dir);
+
+    SelfTestReport {
+        // A step that didn't run because an earlier one failed counts as a failure too
+        passed: steps.len() == 4 && steps.iter().all(|step| step.passed),
+        steps,
+    }
+}
+
+#[derive(Serialize)]
+struct ArchiveEntryError {
+    name: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct ArchiveVerification {
+    valid: bool,
+    entry_count: usize,
+    bad_entries: Vec<ArchiveEntryError>,
+}
+
+/// Decompress every entry of a ZIP so its CRC is checked, and optionally decode the image entries.
+#[tauri::command]
+fn verify_archive(path: String, check_images: Option<bool>) -> Result<ArchiveVerification, String> {
+    let file = fs::File::open(&path)
+        .map_err(|e| format!("Error opening archive {}: {}", path, e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| format!("Error reading archive {}: {}", path, e))?;
+    let image_extensions = ["jpg", "jpeg", "png", "tiff", "tif", "bmp", "gif", "webp"];
+
+    let mut bad_entries = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = match archive.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                bad_entries.push(ArchiveEntryError {
+                    name: format!("#{}", index),
+                    error: format!("Error reading entry: {}", e),
+                });
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+
+        // The zip reader checks the CRC once the entry has been read to the end
+        let mut data = Vec::new();
+        if let Err(e) = std::io::Read::read_to_end(&mut entry, &mut data) {
+            bad_entries.push(ArchiveEntryError {
+                name,
+                error: format!("Error decompressing entry: {}", e),
+            });
+            continue;
+        }
+
+        let is_image = Path::new(&name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| image_extensions.contains(&ext.to_lowercase().as_str()));
+        if check_images.unwrap_or(false) && is_image {
+            if let Err(e) = image::load_from_memory(&data) {
+                bad_entries.push(ArchiveEntryError {
+                    name,
+                    error: format!("Error decoding image: {}", e),
+                });
+            }
+        }
     }
+
+    Ok(ArchiveVerification {
+        valid: bad_entries.is_empty(),
+        entry_count: archive.len(),
+        bad_entries,
+    })
 }
 
-#[tauri::command]
-async fn generate_images_with_template(
-    app_handle: AppHandle,
-    template_id: i32,
-    image_folder_path: String,
-) -> Result<String, String> {
-    // 1. Get PhotoTemplate from database
-    let mut connection = establish_connection();
-    let template: PhotoTemplate = photo_templates::table
-        .find(template_id)
-        .first(&mut connection)
-        .map_err(|e| format!("Error loading template: {}", e))?;
+/// Directory of the app data dir holding one subdirectory per generation run
+const GENERATED_IMAGES_DIR: &str = "generated_images";
+const RUN_THUMBNAIL_FILENAME: &str = "run_thumbnail.jpg";
+const RUN_THUMBNAIL_SIZE: u32 = 256;
 
-    // 2. Parse crop coordinates
-    let crop_coords: CropCoordinates = serde_json::from_str(&template.crop_photo)
-        .map_err(|e| format!("Error parsing crop coordinates: {}", e))?;
-    
-    // 2.1. Parse crop_number coordinates if available
-    let crop_number_coords: Option<CropCoordinates> = if !template.crop_number.is_empty() {
-        Some(serde_json::from_str(&template.crop_number)
-            .map_err(|e| format!("Error parsing crop_number coordinates: {}", e))?)
+/// Write a small JPEG of the first output, or a 2x2 grid of the first four, into the run directory.
+///
+/// Returns its path, `None` when the run has no output.
+fn write_run_thumbnail(images: &[GeneratedImage], output_dir: &Path) -> Result<Option<PathBuf>, String> {
+    let outputs: Vec<&GeneratedImage> = images.iter().take(4).collect();
+    if outputs.is_empty() {
+        return Ok(None);
+    }
+
+    let (columns, cell_size) = if outputs.len() == 1 {
+        (1, RUN_THUMBNAIL_SIZE)
     } else {
-        None
+        (2, RUN_THUMBNAIL_SIZE / 2)
     };
+    let rows = (outputs.len() as u32).div_ceil(columns);
 
-    // 3. Load template image
-    let template_image = load_image(&template.template_img)?;
-
-    // 4. Find all image files in the folder
-    let image_files = find_image_files(&image_folder_path)?;
-    if image_files.is_empty() {
-        return Err("No image files found in the selected folder".to_string());
+    let mut thumbnail = ImageBuffer::from_pixel(columns * cell_size, rows * cell_size, Rgba([255u8, 255, 255, 255]));
+    for (index, generated) in outputs.iter().enumerate() {
+        let cell = image::open(&generated.output)
+            .map_err(|e| format!("Error loading {:?} for the run thumbnail: {}", generated.output, e))?
+            .thumbnail(cell_size, cell_size)
+            .to_rgba8();
+        let x = (index as u32 % columns) * cell_size + (cell_size - cell.width()) / 2;
+        let y = (index as u32 / columns) * cell_size + (cell_size - cell.height()) / 2;
+        image::imageops::overlay(&mut thumbnail, &cell, x as i64, y as i64);
     }
 
-    // 5. Create output directory for processed images
-    let app_data_dir = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Error getting app data directory: {}", e))?;
-    let output_dir = app_data_dir.join("generated_images");
-    fs::create_dir_all(&output_dir)
-        .map_err(|e| format!("Error creating output directory: {}", e))?;
-
-    // 6. Process each image
-    let mut processed_files = Vec::new();
-    let total_images = image_files.len();
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgba8(thumbnail)
+        .to_rgb8()
+        .write_to(&mut encoded, image::ImageOutputFormat::Jpeg(75))
+        .map_err(|e| format!("Error encoding run thumbnail: {}", e))?;
+    let thumbnail_path = output_dir.join(RUN_THUMBNAIL_FILENAME);
+    fs::write(&thumbnail_path, encoded.into_inner())
+        .map_err(|e| format!("Error writing run thumbnail: {}", e))?;
+    Ok(Some(thumbnail_path))
+}
 
-    for (index, image_file) in image_files.iter().enumerate() {
-        // Load and resize source image
-        let source_image = load_and_resize_image(
-            image_file,
-            crop_coords.width as u32,
-            crop_coords.height as u32,
-            true,
-        )?;
+#[derive(Serialize)]
+struct GeneratedArchive {
+    archive_path: String,
+    thumbnail_path: Option<String>,
+    /// Seconds since the Unix epoch
+    created_at: u64,
+}
 
-        // Extract number from filename for text overlay
-        let filename = image_file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-        let extracted_number = extract_number_from_filename(filename, index + 1);
-        
-        // Composite images with text overlay
-        let result_image = composite_images_with_text(&template_image, &source_image, &crop_coords, crop_number_coords.as_ref(), &extracted_number)?;
+/// Archives of previous runs, newest first.
+#[tauri::command]
+fn list_generated_archives(app_handle: AppHandle) -> Result<Vec<GeneratedArchive>, String> {
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    let generated_dir = app_data_dir.join(GENERATED_IMAGES_DIR);
+    if !generated_dir.exists() {
+        return Ok(Vec::new());
+    }
 
-        // Save result image - preserve original filename
-        let original_filename = match image_file.file_stem().and_then(|s| s.to_str()) {
-            Some(name) => name.to_string(),
-            None => format!("image_{}", index + 1),
+    let entries = fs::read_dir(&generated_dir)
+        .map_err(|e| format!("Error reading generated images directory: {}", e))?;
+    let mut archives = Vec::new();
+    for entry in entries {
+        let run_dir = entry
+            .map_err(|e| format!("Error reading generated images directory: {}", e))?
+            .path();
+        // Runs split into parts are listed by their first part
+        let archive_path = ["generated_images.zip", "generated_images_part1.zip"]
+            .iter()
+            .map(|name| run_dir.join(name))
+            .find(|path| path.is_file())
+            .unwrap_or_default();
+        let Ok(metadata) = fs::metadata(&archive_path) else {
+            continue;
         };
-        let output_filename = format!("{}_processed.jpg", original_filename);
-        let output_path = output_dir.join(&output_filename);
-        result_image.save(&output_path)
-            .map_err(|e| format!("Error saving image: {}", e))?;
-
-        processed_files.push(output_path);
 
-        // Emit progress event
-        let progress = (index + 1) as f32 / total_images as f32 * 100.0;
-        app_handle.emit("generation-progress", progress).unwrap_or(());
+        let thumbnail_path = run_dir.join(RUN_THUMBNAIL_FILENAME);
+        archives.push(GeneratedArchive {
+            archive_path: archive_path.to_string_lossy().to_string(),
+            thumbnail_path: thumbnail_path.is_file().then(|| thumbnail_path.to_string_lossy().to_string()),
+            created_at: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_secs()),
+        });
     }
 
-    // 7. Create ZIP archive
-    let archive_path = create_archive(processed_files, &output_dir)?;
+    archives.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| b.archive_path.cmp(&a.archive_path)));
+    Ok(archives)
+}
+
+/// Depth of the directories listed by `snapshot_app_data`, e.g. `generated_images/run_...`
+const SNAPSHOT_DEPTH: usize = 2;
 
-    Ok(archive_path)
+#[derive(Serialize)]
+struct AppDataEntry {
+    /// Relative to the app data directory, with `/` separators
+    path: String,
+    is_dir: bool,
+    /// Total size in bytes, including everything below a directory
+    size: u64,
+    /// Files below a directory, 1 for a file
+    file_count: usize,
 }
 
-// Utility functions for image processing
+#[derive(Serialize)]
+struct AppDataSnapshot {
+    root: String,
+    total_size: u64,
+    total_files: usize,
+    entries: Vec<AppDataEntry>,
+    /// Directories and files the app creates that don't exist (yet)
+    missing: Vec<String>,
+}
 
-fn load_image(image_path: &str) -> Result<DynamicImage, String> {
-    image::open(image_path)
-        .map_err(|e| format!("Error loading image {}: {}", image_path, e))
+/// Read-only summary of what the app stores in its data directory, to paste into bug reports.
+#[tauri::command]
+fn snapshot_app_data(app_handle: AppHandle) -> Result<AppDataSnapshot, String> {
+    let app_data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Error getting app data directory: {}", e))?;
+    snapshot_directory(&app_data_dir)
 }
 
-fn find_image_files(folder_path: &str) -> Result<Vec<PathBuf>, String> {
-    let mut image_files = Vec::new();
-    let supported_extensions = ["jpg", "jpeg", "png", "bmp", "gif", "tiff"];
-    
-    for entry in WalkDir::new(folder_path).max_depth(1) {
-        let entry = entry.map_err(|e| format!("Error walking directory: {}", e))?;
-        
-        if entry.file_type().is_file() {
-            if let Some(extension) = entry.path().extension() {
-                if let Some(ext_str) = extension.to_str() {
-                    if supported_extensions.contains(&ext_str.to_lowercase().as_str()) {
-                        image_files.push(entry.path().to_path_buf());
-                    }
+fn snapshot_directory(root: &Path) -> Result<AppDataSnapshot, String> {
+    // Sorted so the report reads like a tree
+    let mut entries: std::collections::BTreeMap<String, AppDataEntry> = std::collections::BTreeMap::new();
+    let mut total_size = 0;
+    let mut total_files = 0;
+
+    if root.exists() {
+        for entry in WalkDir::new(root).min_depth(1) {
+            let entry = entry.map_err(|e| format!("Error walking app data directory: {}", e))?;
+            let relative: Vec<String> = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or(entry.path())
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect();
+
+            if entry.file_type().is_dir() {
+                if relative.len() <= SNAPSHOT_DEPTH {
+                    let path = relative.join("/");
+                    entries.entry(path.clone()).or_insert(AppDataEntry { path, is_dir: true, size: 0, file_count: 0 });
                 }
+                continue;
+            }
+
+            let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            total_size += size;
+            total_files += 1;
+            // Count the file in each listed directory above it, and list it when it's shallow enough
+            for depth in 1..=relative.len().min(SNAPSHOT_DEPTH) {
+                let path = relative[..depth].join("/");
+                let is_dir = depth < relative.len();
+                let listed = entries.entry(path.clone()).or_insert(AppDataEntry { path, is_dir, size: 0, file_count: 0 });
+                listed.size += size;
+                listed.file_count += 1;
             }
         }
     }
-    
-    image_files.sort();
-    Ok(image_files)
-}
 
-fn load_and_resize_image(
-    source_path: &Path,
-    target_width: u32,
-    target_height: u32,
-    preserve_ratio: bool,
-) -> Result<DynamicImage, String> {
-    let img = image::open(source_path)
-        .map_err(|e| format!("Error loading image {:?}: {}", source_path, e))?;
-    
-    if preserve_ratio {
-        // Calculate the scaling factor to fit within target dimensions while preserving aspect ratio
-        let (orig_width, orig_height) = (img.width(), img.height());
-        let width_ratio = target_width as f32 / orig_width as f32;
-        let height_ratio = target_height as f32 / orig_height as f32;
-        let scale_ratio = width_ratio.min(height_ratio);
-        
-        let new_width = (orig_width as f32 * scale_ratio) as u32;
-        let new_height = (orig_height as f32 * scale_ratio) as u32;
-        
-        Ok(img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3))
-    } else {
-        Ok(img.resize_exact(target_width, target_height, image::imageops::FilterType::Lanczos3))
-    }
+    let missing = [TEMPLATE_IMAGES_DIR, GENERATED_IMAGES_DIR, IMPORTED_SOURCES_DIR, PROOFS_DIR, DATABASE_FILENAME]
+        .iter()
+        .filter(|name| !entries.contains_key(**name))
+        .map(|name| name.to_string())
+        .collect();
+
+    Ok(AppDataSnapshot {
+        root: root.to_string_lossy().to_string(),
+        total_size,
+        total_files,
+        entries: entries.into_values().collect(),
+        missing,
+    })
 }
 
-fn extract_number_from_filename(filename: &str, fallback_id: usize) -> String {
-    let re = Regex::new(r"([0-9]+)").unwrap();
-    if let Some(captures) = re.captures(filename) {
-        if let Some(number_match) = captures.get(1) {
-            return number_match.as_str().to_string();
+/// Write `hash_manifest.json` into the run directory, mapping each source's SHA-256 to its output and number.
+fn write_hash_manifest(images: &[GeneratedImage], output_dir: &Path) -> Result<PathBuf, String> {
+    let mut records = Vec::new();
+    for image in images {
+        for source in &image.sources {
+            let mut file = fs::File::open(source)
+                .map_err(|e| format!("Error reading {:?} for the hash manifest: {}", source, e))?;
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut file, &mut hasher)
+                .map_err(|e| format!("Error hashing {:?}: {}", source, e))?;
+            records.push(HashManifestRecord {
+                source: source.to_string_lossy().to_string(),
+                sha256: format!("{:x}", hasher.finalize()),
+                output: output_entry_name(&image.output, output_dir),
+                number: image.number.clone(),
+            });
         }
     }
-    fallback_id.to_string()
-}
 
-fn composite_images_with_text(
-    template_image: &DynamicImage,
-    source_image: &DynamicImage,
-    crop_coords: &CropCoordinates,
-    crop_number_coords: Option<&CropCoordinates>,
-    number: &str,
-) -> Result<DynamicImage, String> {
-    // First, composite the images normally
-    let mut result = composite_images(template_image, source_image, crop_coords)?;
-    
-    // Add text overlay if crop_number coordinates are available
-    if let Some(txt_crop) = crop_number_coords {
-        // Always add text overlay - removed format detection that was causing the error
-        // The original PHP logic for PNG detection is not critical for functionality
-        result = add_text_overlay(result, txt_crop, number)?;
-    }
-    
-    Ok(result)
+    let manifest_path = output_dir.join("hash_manifest.json");
+    let manifest = serde_json::to_string_pretty(&records)
+        .map_err(|e| format!("Error serializing hash manifest: {}", e))?;
+    fs::write(&manifest_path, manifest)
+        .map_err(|e| format!("Error writing hash manifest: {}", e))?;
+    Ok(manifest_path)
 }
 
-fn add_text_overlay(
-    image: DynamicImage,
-    txt_crop: &CropCoordinates,
-    number: &str,
-) -> Result<DynamicImage, String> {
-    // Create the text to display
-    let text = format!("N° {}", number);
-    
-    // For simplicity, we'll use a basic approach to draw text
-    // Convert to RGBA image for text drawing
-    let mut rgba_image = image.to_rgba8();
-    
-    // Calculate centered position within the crop_number area
-    // Approximate text dimensions (simple estimation)
-    let estimated_text_width = text.len() as f32 * 12.0; // rough estimation
-    let estimated_text_height = 30.0;
-    
-    let text_x = (txt_crop.x + txt_crop.width / 2.0) - (estimated_text_width / 2.0);
-    let text_y = (txt_crop.y + txt_crop.height / 2.0) - (estimated_text_height / 2.0);
-    
-    // For now, use a basic black rectangle overlay to mark the number area
-    // This ensures the function works and marks where text would appear
-    // In production, you'd want to add a proper font file or use system fonts
-    let rect_x = text_x.max(0.0) as u32;
-    let rect_y = text_y.max(0.0) as u32;
-    let rect_width = estimated_text_width as u32;
-    let rect_height = estimated_text_height as u32;
-    
-    // Draw a semi-transparent black rectangle to indicate the number area
-    for x in rect_x..rect_x.saturating_add(rect_width).min(rgba_image.width()) {
-        for y in rect_y..rect_y.saturating_add(rect_height).min(rgba_image.height()) {
-            if x < rgba_image.width() && y < rgba_image.height() {
-                rgba_image.put_pixel(x, y, Rgba([0u8, 0u8, 0u8, 150u8]));
-            }
+/// Zip the sources that failed together with a `failures.json` listing the reasons.
+fn create_failures_bundle(
+    failures: &[FailedImage],
+    output_dir: &Path,
+    deterministic: bool,
+    cancelled: &AtomicBool,
+) -> Result<String, String> {
+    let records: Vec<FailureRecord> = failures
+        .iter()
+        .flat_map(|failure| {
+            failure.sources.iter().map(|source| FailureRecord {
+                source: source.to_string_lossy().to_string(),
+                reason: failure.reason.clone(),
+            })
+        })
+        .collect();
+
+    let report_path = output_dir.join("failures.json");
+    let report = serde_json::to_string_pretty(&records)
+        .map_err(|e| format!("Error serializing failures report: {}", e))?;
+    fs::write(&report_path, report)
+        .map_err(|e| format!("Error writing failures report: {}", e))?;
+
+    let mut entries = vec![(report_path.clone(), "failures.json".to_string())];
+    for (index, failure) in failures.iter().enumerate() {
+        for source in failure.sources.iter().filter(|source| source.is_file()) {
+            // Indexed so files with the same name from different sources stay distinct
+            entries.push((source.clone(), format!("{}_{}", index + 1, archive_entry_name(source))));
         }
     }
-    
-    // TODO: Replace with proper font rendering when font files are available
-    // For now this provides visual confirmation that number extraction is working
-    
-    Ok(DynamicImage::ImageRgba8(rgba_image))
+
+    let bundle_path = create_archive(
+        entries,
+        &output_dir.join("failures.zip"),
+        DuplicateEntryPolicy::Rename,
+        deterministic,
+        ArchiveCompression::default(),
+        cancelled,
+    );
+    let _ = fs::remove_file(&report_path);
+    bundle_path
 }
 
-fn composite_images(
-    template_image: &DynamicImage,
-    source_image: &DynamicImage,
-    crop_coords: &CropCoordinates,
-) -> Result<DynamicImage, String> {
-    let mut result = template_image.clone();
-    
-    // Get the actual dimensions of the resized source image
-    let source_width = source_image.width();
-    let source_height = source_image.height();
-    
-    // Calculate the available space in the crop area
-    let crop_width = crop_coords.width as u32;
-    let crop_height = crop_coords.height as u32;
-    
-    // Calculate centering offsets
-    let offset_x = if crop_width > source_width {
-        (crop_width - source_width) / 2
-    } else {
-        0
-    };
-    
-    let offset_y = if crop_height > source_height {
-        (crop_height - source_height) / 2
-    } else {
-        0
-    };
-    
-    // Calculate final centered position
-    let centered_x = (crop_coords.x as u32 + offset_x) as i64;
-    let centered_y = (crop_coords.y as u32 + offset_y) as i64;
-    
-    // Overlay the source image onto the template at the centered coordinates
-    image::imageops::overlay(&mut result, source_image, centered_x, centered_y);
-    
-    Ok(result)
+/// Zip `entries`, each being a file on disk and its name inside the archive.
+///
+/// Entry names already used in the archive (compared case-insensitively, as most extractors do)
+/// are renamed or rejected according to `duplicates`. `deterministic` archives store a fixed
+/// modification time instead of the current one.
+fn create_archive(
+    entries: Vec<(PathBuf, String)>,
+    archive_path: &Path,
+    duplicates: DuplicateEntryPolicy,
+    deterministic: bool,
+    compression: ArchiveCompression,
+    cancelled: &AtomicBool,
+) -> Result<String, String> {
+    // Write to a temporary file so a cancelled or failed run never leaves a partial archive behind
+    let partial_path = archive_path.with_extension("zip.part");
+    let result = write_archive(&entries, &partial_path, duplicates, deterministic, compression, cancelled)
+        .and_then(|()| {
+            fs::rename(&partial_path, archive_path)
+                .map_err(|e| format!("Error finalizing archive file: {}", e))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&partial_path);
+    }
+    result.map(|()| archive_path.to_string_lossy().to_string())
 }
 
-fn create_archive(images: Vec<PathBuf>, output_dir: &Path) -> Result<String, String> {
-    let archive_path = output_dir.join("generated_images.zip");
-    let file = fs::File::create(&archive_path)
+/// Zip `entries` into `path`, the zip writer being closed when this returns, even on errors.
+fn write_archive(
+    entries: &[(PathBuf, String)],
+    path: &Path,
+    duplicates: DuplicateEntryPolicy,
+    deterministic: bool,
+    compression: ArchiveCompression,
+    cancelled: &AtomicBool,
+) -> Result<(), String> {
+    let file = fs::File::create(path)
         .map_err(|e| format!("Error creating archive file: {}", e))?;
     
     let mut zip = ZipWriter::new(file);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o755);
+    let mut options = match compression.method {
+        ArchiveCompressionMethod::Stored => FileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        ArchiveCompressionMethod::Deflated => FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(compression.level.map(|level| level.0 as i32)),
+    }
+    .unix_permissions(0o755);
+    if deterministic {
+        options = options.last_modified_time(zip::DateTime::default());
+    }
     
-    for image_path in images.iter() {
-        // Use the actual filename from the processed image path
-        let filename = image_path.file_name()
-            .and_then(|name| name.to_str())
-            .unwrap_or("processed_image.jpg");
+    let mut used_names = std::collections::HashSet::new();
+    for (image_path, entry_name) in entries.iter() {
+        if cancelled.load(Ordering::SeqCst) {
+            return Err("Generation cancelled while creating the archive".to_string());
+        }
+
+        let entry_name = if used_names.contains(&entry_name.to_lowercase()) {
+            match duplicates {
+                DuplicateEntryPolicy::Rename => unique_entry_name(entry_name, &used_names),
+                DuplicateEntryPolicy::Error => {
+                    return Err(format!("Duplicate archive entry name: {}", entry_name));
+                }
+            }
+        } else {
+            entry_name.clone()
+        };
+        used_names.insert(entry_name.to_lowercase());
         
-        zip.start_file(filename, options)
+        zip.start_file(entry_name.as_str(), options)
             .map_err(|e| format!("Error starting zip file entry: {}", e))?;
         
         let image_data = fs::read(image_path)
@@ -454,8 +4731,29 @@ fn create_archive(images: Vec<PathBuf>, output_dir: &Path) -> Result<String, Str
     
     zip.finish()
         .map_err(|e| format!("Error finalizing zip: {}", e))?;
-    
-    Ok(archive_path.to_string_lossy().to_string())
+    Ok(())
+}
+
+/// First `name_2.ext`, `name_3.ext`, ... not already taken in the archive.
+fn unique_entry_name(entry_name: &str, used_names: &std::collections::HashSet<String>) -> String {
+    let path = Path::new(entry_name);
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    let parent = match path.parent().map(|parent| parent.to_string_lossy().replace('\\', "/")) {
+        Some(parent) if !parent.is_empty() => format!("{}/", parent),
+        _ => String::new(),
+    };
+
+    (2..)
+        .map(|suffix| format!("{}{}_{}{}", parent, stem, suffix, extension))
+        .find(|candidate| !used_names.contains(&candidate.to_lowercase()))
+        .expect("an unused suffix always exists")
+}
+
+/// Cancel the job currently running, queued jobs still run afterwards.
+#[tauri::command]
+fn cancel_generation(generation_state: State<'_, GenerationState>) {
+    generation_state.cancelled.store(true, Ordering::SeqCst);
 }
 
 #[tauri::command]
@@ -467,48 +4765,355 @@ async fn download_archive(app_handle: AppHandle, archive_path: String) -> Result
         return Err("Archive file does not exist".to_string());
     }
     
-    // Get the parent directory of the archive
-    let parent_dir = path.parent()
-        .ok_or("Cannot get parent directory of archive")?;
+    // Runs without an archive return their output directory, opened as-is
+    let folder = if path.is_dir() {
+        path
+    } else {
+        path.parent().ok_or("Cannot get parent directory of archive")?
+    };
     
     // Open the folder containing the archive
     app_handle.opener()
-        .open_path(parent_dir.to_string_lossy().to_string(), None::<String>)
+        .open_path(folder.to_string_lossy().to_string(), None::<String>)
         .map_err(|e| format!("Error opening folder: {}", e))?;
     
     Ok(())
 }
 
-fn establish_connection() -> SqliteConnection {
-    let database_url = "sqlite://photo_template.db";
-    SqliteConnection::establish(database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+/// Database file name, inside the app data directory once `init_database` ran.
+const DATABASE_FILENAME: &str = "photo_template.db";
+
+type DbPool = r2d2::Pool<r2d2::ConnectionManager<SqliteConnection>>;
+type DbConnection = r2d2::PooledConnection<r2d2::ConnectionManager<SqliteConnection>>;
+
+/// Connections shared by all commands, set up by `init_database` when the app starts.
+static DATABASE_POOL: std::sync::OnceLock<DbPool> = std::sync::OnceLock::new();
+
+/// Whether the database can be used, the app still starts when `init_database` fails so
+/// `repair_database` can be run.
+static DATABASE_STATUS: Mutex<DatabaseStatus> = Mutex::new(DatabaseStatus::Ready);
+
+/// Returned by `database_status`, the UI offers `repair_database` while it `needs_repair`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum DatabaseStatus {
+    Ready,
+    /// Setting up the database failed, other commands refuse to use it until it is repaired
+    NeedsRepair { error: String },
+}
+
+fn set_database_status(status: DatabaseStatus) {
+    *DATABASE_STATUS.lock().unwrap_or_else(|e| e.into_inner()) = status;
+}
+
+/// How long a connection waits for another one to release the database file.
+const DATABASE_BUSY_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Debug)]
+struct SqlitePragmas;
+
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        // Pooled connections can overlap, wait for locks instead of failing right away
+        diesel::sql_query(format!("PRAGMA busy_timeout = {}", DATABASE_BUSY_TIMEOUT_MS))
+            .execute(connection)
+            .map(|_| ())
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+fn build_pool(database_path: &Path) -> Result<DbPool, String> {
+    r2d2::Pool::builder()
+        .connection_customizer(Box::new(SqlitePragmas))
+        .build(r2d2::ConnectionManager::new(database_path.to_string_lossy()))
+        .map_err(|e| format!("Error connecting to the database {:?}: {}", database_path, e))
+}
+
+/// Point the database at the app data directory, moving over a database left in the
+/// working directory by earlier versions, then run pending migrations.
+fn init_database(app_data_dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(app_data_dir)
+        .map_err(|e| format!("Error creating app data directory: {}", e))?;
+
+    let database_path = app_data_dir.join(DATABASE_FILENAME);
+    move_legacy_database(Path::new(DATABASE_FILENAME), &database_path)?;
+    open_database(&database_path)
+}
+
+/// Move the database at `legacy_path` to `database_path`, unless there is already one there.
+fn move_legacy_database(legacy_path: &Path, database_path: &Path) -> Result<(), String> {
+    if database_path.exists() || !legacy_path.is_file() {
+        return Ok(());
+    }
+    move_file(legacy_path, database_path)
+        .map_err(|e| format!("Error moving the database to {:?}: {}", database_path, e))
+}
+
+/// Set up the connection pool of `database_path` and run pending migrations.
+fn open_database(database_path: &Path) -> Result<(), String> {
+    DATABASE_POOL
+        .set(build_pool(database_path)?)
+        .map_err(|_| "Database was already initialized".to_string())?;
+    let mut connection = establish_connection()?;
+    run_migrations(&mut connection)
+}
+
+/// Rename `from` to `to`, copying then removing it when they are on different filesystems.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+/// Borrow a connection from the pool set up by `init_database`.
+fn establish_connection() -> Result<DbConnection, String> {
+    if let DatabaseStatus::NeedsRepair { error } = &*DATABASE_STATUS.lock().unwrap_or_else(|e| e.into_inner()) {
+        return Err(format!("The database needs to be repaired before it can be used: {}", error));
+    }
+    pooled_connection()
+}
+
+/// Connection of the pool whatever the database status, for `repair_database`.
+fn pooled_connection() -> Result<DbConnection, String> {
+    DATABASE_POOL
+        .get()
+        .ok_or("The database is not initialized")?
+        .get()
+        .map_err(|e| format!("Error connecting to the database: {}", e))
+}
+
+/// Error carried out of a diesel transaction closure, keeping the repo's `String` errors.
+struct TransactionError(String);
+
+impl From<diesel::result::Error> for TransactionError {
+    fn from(error: diesel::result::Error) -> Self {
+        TransactionError(format!("Database transaction error: {}", error))
+    }
+}
+
+fn run_in_transaction<T>(
+    connection: &mut SqliteConnection,
+    f: impl FnOnce(&mut SqliteConnection) -> Result<T, String>,
+) -> Result<T, String> {
+    connection
+        .transaction(|connection| f(connection).map_err(TransactionError))
+        .map_err(|TransactionError(message)| message)
+}
+
+/// Whether the database is ready, or failed to be set up at startup and needs `repair_database`.
+#[tauri::command]
+fn database_status() -> DatabaseStatus {
+    DATABASE_STATUS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[derive(Serialize)]
+struct DatabaseRepairReport {
+    /// What was changed, in order
+    actions: Vec<String>,
+    /// Inconsistencies left as they are
+    problems: Vec<String>,
+    /// Result of SQLite's `PRAGMA integrity_check`, `ok` for a healthy file
+    integrity: String,
+    /// Copy of the database taken before the first migration was reverted, none when nothing was
+    backup: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheckRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    integrity_check: String,
+}
+
+#[derive(QueryableByName)]
+struct DatabaseFileRow {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    file: String,
+}
+
+/// Write a consistent copy of the database next to its file, returning the path of the copy.
+fn backup_database(connection: &mut SqliteConnection) -> Result<String, String> {
+    let database_file = diesel::sql_query("SELECT file FROM pragma_database_list WHERE name = 'main'")
+        .get_result::<DatabaseFileRow>(connection)
+        .map_err(|e| format!("Error locating the database file: {}", e))?
+        .file;
+    if database_file.is_empty() {
+        return Err("The database has no file to back up".to_string());
+    }
+
+    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let mut backup_path = format!("{}.backup-{}", database_file, timestamp);
+    let mut counter = 1;
+    while Path::new(&backup_path).exists() {
+        backup_path = format!("{}.backup-{}_{}", database_file, timestamp, counter);
+        counter += 1;
+    }
+    // Unlike a file copy, this also picks up changes still in the write-ahead log
+    diesel::sql_query("VACUUM INTO ?")
+        .bind::<diesel::sql_types::Text, _>(&backup_path)
+        .execute(connection)
+        .map_err(|e| format!("Error backing up the database to {}: {}", backup_path, e))?;
+    Ok(backup_path)
+}
+
+/// Bring the migrations back to a consistent state after an interrupted or failed migration.
+///
+/// The database is ready again once every migration could be applied.
+#[tauri::command]
+fn repair_database() -> Result<DatabaseRepairReport, String> {
+    let mut connection = pooled_connection()?;
+    let report = repair_database_schema(&mut connection)?;
+    if run_migrations(&mut connection).is_ok() {
+        set_database_status(DatabaseStatus::Ready);
+    }
+    Ok(report)
+}
+
+/// Pending migrations are run one by one. One that fails because its changes are already in the
+/// database without being recorded is reverted and run again, once the database is backed up.
+fn repair_database_schema(connection: &mut SqliteConnection) -> Result<DatabaseRepairReport, String> {
+    let mut report = DatabaseRepairReport {
+        actions: Vec::new(),
+        problems: Vec::new(),
+        integrity: String::new(),
+        backup: None,
+    };
+
+    report.integrity = diesel::sql_query("PRAGMA integrity_check")
+        .load::<IntegrityCheckRow>(connection)
+        .map_err(|e| format!("Error checking database integrity: {}", e))?
+        .into_iter()
+        .map(|row| row.integrity_check)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let migrations = MIGRATIONS
+        .migrations()
+        .map_err(|e| format!("Error loading database migrations: {}", e))?;
+    let applied = connection
+        .applied_migrations()
+        .map_err(|e| format!("Error reading applied migrations: {}", e))?;
+    // Written by a newer version of the app, nothing this version can do about them
+    for version in &applied {
+        if !migrations.iter().any(|migration| migration.name().version() == *version) {
+            report.problems.push(format!("Unknown migration {} is recorded as applied", version));
+        }
+    }
+
+    for migration in migrations.iter().filter(|migration| !applied.contains(&migration.name().version())) {
+        let name = migration.name().to_string();
+        let Err(run_error) = connection.run_migration(migration.as_ref()) else {
+            report.actions.push(format!("Applied pending migration {}", name));
+            continue;
+        };
+
+        // Reverting drops tables, keep a copy to recover the data from if re-applying fails too
+        if report.backup.is_none() {
+            match backup_database(connection) {
+                Ok(backup_path) => {
+                    report.actions.push(format!("Backed up the database to {}", backup_path));
+                    report.backup = Some(backup_path);
+                }
+                Err(e) => {
+                    report.problems.push(format!("Migration {} failed: {} (not reverted: {})", name, run_error, e));
+                    break;
+                }
+            }
+        }
+
+        // Each migration runs in a transaction, so either none or all of it is in the database
+        let rerun = connection
+            .revert_migration(migration.as_ref())
+            .and_then(|_| connection.run_migration(migration.as_ref()));
+        match rerun {
+            Ok(_) => report.actions.push(format!("Reverted and re-applied migration {}", name)),
+            Err(e) => {
+                report.problems.push(format!("Migration {} failed: {} (retry after revert: {})", name, run_error, e));
+                // Later migrations build on this one
+                break;
+            }
+        }
+    }
+
+    if report.problems.is_empty() {
+        report.actions.push("Database schema is up to date".to_string());
+    }
+    Ok(report)
 }
 
-fn run_migrations(connection: &mut SqliteConnection) {
-    connection.run_pending_migrations(MIGRATIONS).unwrap();
+fn run_migrations(connection: &mut SqliteConnection) -> Result<(), String> {
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .map(|_| ())
+        .map_err(|e| format!("Error running database migrations: {}", e))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Run database migrations on startup
-    let mut connection = establish_connection();
-    run_migrations(&mut connection);
-    
     tauri::Builder::default()
+        .setup(|app| {
+            // The database lives next to the template images, whatever directory the app starts from
+            let app_data_dir = app.path().app_data_dir()?;
+            // A failed migration must not keep the app from starting, the frontend offers `repair_database`
+            if let Err(error) = init_database(&app_data_dir) {
+                set_database_status(DatabaseStatus::NeedsRepair { error });
+            }
+            Ok(())
+        })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_sql::Builder::default().build())
+        .manage(GenerationState::default())
         .invoke_handler(tauri::generate_handler![
             greet, 
+            database_status,
+            repair_database,
             add_photo_template, 
             get_photo_templates, 
+            list_template_tags,
             update_photo_template, 
+            patch_photo_template,
             delete_photo_template, 
+            duplicate_photo_template,
+            revert_template,
+            bulk_set_template_image,
+            templates_using_image,
+            export_template_svg,
+            import_template_svg,
+            scale_crops_to,
+            remap_crops,
+            check_template_images,
+            sync_templates_from_config,
+            reset_template_crops,
+            normalize_crop_json,
+            template_config_hash,
+            list_source_thumbnails,
+            sample_region_color,
+            estimate_pages,
+            estimate_memory,
+            preview_template,
+            preview_fit,
+            render_before_after,
+            render_template_region,
+            make_proof_pdf,
+            measure_text,
+            list_available_fonts,
             save_template_image,
             select_image_folder,
+            import_source_folder,
             generate_images_with_template,
-            download_archive
+            enqueue_generation,
+            get_job_status,
+            list_jobs,
+            save_generation_preset,
+            list_generation_presets,
+            cancel_generation,
+            download_archive,
+            list_generated_archives,
+            snapshot_app_data,
+            verify_archive,
+            run_self_test
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,190 @@
+use image::DynamicImage;
+use std::fs;
+use std::path::Path;
+
+/// Largest `LUT_3D_SIZE` accepted, common grading LUTs use 17, 33 or 65
+const MAX_LUT_SIZE: usize = 256;
+
+/// A 3D color lookup table read from an Adobe/Resolve `.cube` file.
+pub struct Lut3d {
+    size: usize,
+    /// Output colors, red varying fastest, then green, then blue
+    table: Vec<[f32; 3]>,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+pub fn load_cube(path: &Path) -> Result<Lut3d, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Error reading LUT {:?}: {}", path, e))?;
+    parse_cube(&text).map_err(|e| format!("Invalid LUT {:?}: {}", path, e))
+}
+
+pub fn parse_cube(text: &str) -> Result<Lut3d, String> {
+    let mut size = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut table = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next().unwrap_or_default();
+        let line_number = line_index + 1;
+
+        match keyword {
+            "TITLE" => {}
+            "LUT_1D_SIZE" => return Err("1D LUTs are not supported".to_string()),
+            "LUT_3D_SIZE" => {
+                let value: usize = fields
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or(format!("line {}: invalid LUT_3D_SIZE", line_number))?;
+                if !(2..=MAX_LUT_SIZE).contains(&value) {
+                    return Err(format!("LUT_3D_SIZE {} is outside 2 to {}", value, MAX_LUT_SIZE));
+                }
+                size = Some(value);
+            }
+            "DOMAIN_MIN" => domain_min = parse_triplet(fields, line_number)?,
+            "DOMAIN_MAX" => domain_max = parse_triplet(fields, line_number)?,
+            // Other keywords (e.g. LUT_3D_INPUT_RANGE) don't change how the table is read
+            _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+            _ => table.push(parse_triplet(line.split_whitespace(), line_number)?),
+        }
+    }
+
+    let size = size.ok_or("missing LUT_3D_SIZE")?;
+    if table.len() != size * size * size {
+        return Err(format!("expected {} entries, found {}", size * size * size, table.len()));
+    }
+    if (0..3).any(|channel| domain_max[channel] <= domain_min[channel]) {
+        return Err("DOMAIN_MAX must be greater than DOMAIN_MIN".to_string());
+    }
+
+    Ok(Lut3d { size, table, domain_min, domain_max })
+}
+
+fn parse_triplet<'a>(fields: impl Iterator<Item = &'a str>, line_number: usize) -> Result<[f32; 3], String> {
+    let values: Vec<f32> = fields
+        .map(|value| value.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("line {}: {}", line_number, e))?;
+    match values[..] {
+        [r, g, b] => Ok([r, g, b]),
+        _ => Err(format!("line {}: expected 3 values", line_number)),
+    }
+}
+
+impl Lut3d {
+    /// Look up a color with trilinear interpolation, channels in 0..1.
+    pub fn sample(&self, color: [f32; 3]) -> [f32; 3] {
+        let max_index = (self.size - 1) as f32;
+        let mut lower = [0; 3];
+        let mut fraction = [0.0; 3];
+        for channel in 0..3 {
+            let range = self.domain_max[channel] - self.domain_min[channel];
+            let position = ((color[channel] - self.domain_min[channel]) / range).clamp(0.0, 1.0) * max_index;
+            // The last cell interpolates towards itself so position == max_index stays in bounds
+            lower[channel] = (position.floor() as usize).min(self.size - 2);
+            fraction[channel] = position - lower[channel] as f32;
+        }
+
+        let entry = |r: usize, g: usize, b: usize| self.table[r + g * self.size + b * self.size * self.size];
+        let mut result = [0.0; 3];
+        for corner in 0..8 {
+            let offsets = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight: f32 = (0..3)
+                .map(|channel| if offsets[channel] == 1 { fraction[channel] } else { 1.0 - fraction[channel] })
+                .product();
+            if weight == 0.0 {
+                continue;
+            }
+            let value = entry(lower[0] + offsets[0], lower[1] + offsets[1], lower[2] + offsets[2]);
+            for channel in 0..3 {
+                result[channel] += value[channel] * weight;
+            }
+        }
+        result
+    }
+
+    /// Grade every pixel of `image`, keeping alpha and 16-bit precision.
+    pub fn apply(&self, image: DynamicImage) -> DynamicImage {
+        let is_16_bit = matches!(
+            image,
+            DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_)
+        );
+
+        if is_16_bit {
+            let mut rgba = image.to_rgba16();
+            for pixel in rgba.pixels_mut() {
+                let graded = self.sample([0, 1, 2].map(|channel| pixel[channel] as f32 / 65535.0));
+                for channel in 0..3 {
+                    pixel[channel] = (graded[channel].clamp(0.0, 1.0) * 65535.0).round() as u16;
+                }
+            }
+            DynamicImage::ImageRgba16(rgba)
+        } else {
+            let mut rgba = image.to_rgba8();
+            for pixel in rgba.pixels_mut() {
+                let graded = self.sample([0, 1, 2].map(|channel| pixel[channel] as f32 / 255.0));
+                for channel in 0..3 {
+                    pixel[channel] = (graded[channel].clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    /// Size 2 cube mapping each corner of the color cube through `map`.
+    fn cube(map: impl Fn([f32; 3]) -> [f32; 3]) -> String {
+        let mut text = "TITLE \"test\"\nLUT_3D_SIZE 2\n".to_string();
+        for b in 0..2 {
+            for g in 0..2 {
+                for r in 0..2 {
+                    let [r, g, b] = map([r as f32, g as f32, b as f32]);
+                    text.push_str(&format!("{} {} {}\n", r, g, b));
+                }
+            }
+        }
+        text
+    }
+
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| image::Rgb([(x * 4) as u8, (y * 4) as u8, 100])))
+    }
+
+    #[test]
+    fn identity_lut_leaves_the_image_unchanged() {
+        let identity = parse_cube(&cube(|color| color)).unwrap();
+
+        assert_eq!(identity.apply(gradient()).to_rgb8(), gradient().to_rgb8());
+    }
+
+    #[test]
+    fn lut_shifts_the_graded_channels() {
+        // Halves blue, red and green untouched
+        let cooler = parse_cube(&cube(|[r, g, b]| [r, g, b * 0.5])).unwrap();
+
+        let graded = cooler.apply(gradient()).to_rgb8();
+
+        for (graded, original) in graded.pixels().zip(gradient().to_rgb8().pixels()) {
+            assert_eq!(graded.0, [original[0], original[1], 50]);
+        }
+    }
+
+    #[test]
+    fn malformed_cube_is_rejected() {
+        assert!(parse_cube("LUT_3D_SIZE 2\n0 0 0\n").err().unwrap().contains("expected 8 entries, found 1"));
+        assert!(parse_cube("0 0 0\n").err().unwrap().contains("missing LUT_3D_SIZE"));
+        assert!(parse_cube(&cube(|color| color).replace("1 1 1", "1 1 x")).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use serde::Deserialize;
+
+/// XMP properties the print number can be written to.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataField {
+    /// `dc:identifier`
+    #[default]
+    Identifier,
+    /// `xmp:Label`
+    Label,
+    /// `photoshop:TransmissionReference`
+    TransmissionReference,
+}
+
+impl MetadataField {
+    pub fn property_name(&self) -> &'static str {
+        match self {
+            MetadataField::Identifier => "dc:identifier",
+            MetadataField::Label => "xmp:Label",
+            MetadataField::TransmissionReference => "photoshop:TransmissionReference",
+        }
+    }
+}
+
+const XMP_JPEG_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Build a minimal XMP packet holding a single text property.
+pub fn build_xmp_packet(field: MetadataField, value: &str) -> String {
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\"",
+            " xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+            " xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"",
+            " xmlns:photoshop=\"http://ns.adobe.com/photoshop/1.0/\">",
+            "<{field}>{value}</{field}>",
+            "</rdf:Description>",
+            "</rdf:RDF>",
+            "</x:xmpmeta>",
+            "<?xpacket end=\"w\"?>"
+        ),
+        field = field.property_name(),
+        value = escape_xml(value),
+    )
+}
+
+/// Insert an XMP APP1 segment into an encoded JPEG, after the leading APP0/APP1 segments
+/// (JFIF, EXIF) that readers expect to come first.
+pub fn embed_xmp_in_jpeg(jpeg: &[u8], xmp: &str) -> Result<Vec<u8>, String> {
+    if jpeg.len() < 2 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+        return Err("Encoded data is not a JPEG stream".to_string());
+    }
+
+    let payload_len = XMP_JPEG_HEADER.len() + xmp.len() + 2;
+    if payload_len > u16::MAX as usize {
+        return Err("XMP packet is too large for a JPEG segment".to_string());
+    }
+
+    let mut insert_at = 2;
+    while let [0xFF, 0xE0 | 0xE1, high, low, ..] = jpeg[insert_at..] {
+        let segment_end = insert_at + 2 + u16::from_be_bytes([high, low]) as usize;
+        if segment_end > jpeg.len() {
+            return Err("JPEG stream has a truncated header segment".to_string());
+        }
+        insert_at = segment_end;
+    }
+
+    let mut output = Vec::with_capacity(jpeg.len() + payload_len + 2);
+    output.extend_from_slice(&jpeg[..insert_at]);
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&(payload_len as u16).to_be_bytes());
+    output.extend_from_slice(XMP_JPEG_HEADER);
+    output.extend_from_slice(xmp.as_bytes());
+    output.extend_from_slice(&jpeg[insert_at..]);
+    Ok(output)
+}
+
+/// Insert an XMP `iTXt` chunk right after the IHDR chunk of an encoded PNG.
+pub fn embed_xmp_in_png(png: &[u8], xmp: &str) -> Result<Vec<u8>, String> {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    // Signature (8) + IHDR length (4) + type (4) + data (13) + CRC (4)
+    const IHDR_END: usize = 33;
+
+    if png.len() < IHDR_END || &png[..8] != PNG_SIGNATURE || &png[12..16] != b"IHDR" {
+        return Err("Encoded data is not a PNG stream".to_string());
+    }
+
+    // Keyword, null separator, uncompressed flag, compression method, empty language and translated keyword
+    let mut data = Vec::with_capacity(xmp.len() + 24);
+    data.extend_from_slice(b"XML:com.adobe.xmp\0");
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(xmp.as_bytes());
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(b"iTXt");
+    hasher.update(&data);
+
+    let mut output = Vec::with_capacity(png.len() + data.len() + 12);
+    output.extend_from_slice(&png[..IHDR_END]);
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    output.extend_from_slice(b"iTXt");
+    output.extend_from_slice(&data);
+    output.extend_from_slice(&hasher.finalize().to_be_bytes());
+    output.extend_from_slice(&png[IHDR_END..]);
+    Ok(output)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    #[test]
+    fn xmp_segment_follows_the_jfif_and_exif_segments() {
+        let jfif = segment(0xE0, b"JFIF\0\x01\x02");
+        let exif = segment(0xE1, b"Exif\0\0");
+        let quantization = segment(0xDB, &[0; 4]);
+        let jpeg = [&[0xFF, 0xD8][..], &jfif, &exif, &quantization, &[0xFF, 0xD9]].concat();
+
+        let embedded = embed_xmp_in_jpeg(&jpeg, "<x/>").unwrap();
+
+        let xmp_at = 2 + jfif.len() + exif.len();
+        assert_eq!(embedded[..xmp_at], jpeg[..xmp_at]);
+        assert_eq!(embedded[xmp_at..xmp_at + 2], [0xFF, 0xE1]);
+        let xmp_segment = &embedded[xmp_at + 4..xmp_at + 4 + XMP_JPEG_HEADER.len() + 4];
+        assert_eq!(xmp_segment, [XMP_JPEG_HEADER, b"<x/>"].concat());
+        assert_eq!(embedded[xmp_at + 4 + XMP_JPEG_HEADER.len() + 4..], jpeg[xmp_at..]);
+    }
+
+    #[test]
+    fn non_jpeg_data_is_rejected() {
+        assert!(embed_xmp_in_jpeg(b"\x89PNG", "<x/>").is_err());
+        assert!(embed_xmp_in_jpeg(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x40], "<x/>").is_err());
+    }
+
+    #[test]
+    fn xmp_chunk_follows_the_png_header() {
+        let mut png = Vec::new();
+        image::RgbImage::new(2, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+
+        let embedded = embed_xmp_in_png(&png, "<x/>").unwrap();
+
+        assert_eq!(&embedded[37..41], b"iTXt");
+        assert!(image::load_from_memory(&embedded).is_ok());
+    }
+
+    #[test]
+    fn xmp_packet_escapes_the_value() {
+        let packet = build_xmp_packet(MetadataField::Label, "A&B <7>");
+        assert!(packet.contains("<xmp:Label>A&amp;B &lt;7&gt;</xmp:Label>"));
+    }
+}
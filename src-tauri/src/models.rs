@@ -10,6 +10,24 @@ pub struct PhotoTemplate {
     pub crop_photo: String,
     pub crop_number: String,
     pub template_img: String,
+    pub image_width: i32,
+    pub image_height: i32,
+    pub default_watermark: String,
+    pub font_size: f32,
+    pub text_color: String,
+    pub number_format: String,
+    pub number_padding: i32,
+    /// Folder runs are written to when none is picked, empty for the app data directory
+    pub default_output_folder: String,
+    /// JSON array of the labels the library can be filtered by
+    pub tags: String,
+    /// Bundled font id (see `list_available_fonts`) or font file path the number is drawn with,
+    /// empty for the default font
+    pub font_id: String,
+    /// UTC
+    pub created_at: chrono::NaiveDateTime,
+    /// UTC, refreshed on every edit
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -19,4 +37,139 @@ pub struct NewPhotoTemplate {
     pub crop_photo: String,
     pub crop_number: String,
     pub template_img: String,
-}
\ No newline at end of file
+    pub image_width: i32,
+    pub image_height: i32,
+    pub default_watermark: String,
+    pub font_size: f32,
+    pub text_color: String,
+    pub number_format: String,
+    pub number_padding: i32,
+    pub default_output_folder: String,
+    pub tags: String,
+    pub font_id: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+/// Partial update of a template, `None` fields are left untouched.
+#[derive(AsChangeset, Deserialize, Default)]
+#[diesel(table_name = crate::schema::photo_templates)]
+pub struct PhotoTemplatePatch {
+    pub name: Option<String>,
+    pub crop_photo: Option<String>,
+    pub crop_number: Option<String>,
+    pub template_img: Option<String>,
+    pub default_watermark: Option<String>,
+    pub font_size: Option<f32>,
+    pub text_color: Option<String>,
+    pub number_format: Option<String>,
+    pub number_padding: Option<i32>,
+    pub default_output_folder: Option<String>,
+    pub tags: Option<String>,
+    pub font_id: Option<String>,
+    // Derived from template_img, never sent by the frontend
+    #[serde(skip)]
+    pub image_width: Option<i32>,
+    #[serde(skip)]
+    pub image_height: Option<i32>,
+}
+
+impl PhotoTemplatePatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.crop_photo.is_none()
+            && self.crop_number.is_none()
+            && self.template_img.is_none()
+            && self.default_watermark.is_none()
+            && self.font_size.is_none()
+            && self.text_color.is_none()
+            && self.number_format.is_none()
+            && self.number_padding.is_none()
+            && self.default_output_folder.is_none()
+            && self.tags.is_none()
+            && self.font_id.is_none()
+            && self.image_width.is_none()
+            && self.image_height.is_none()
+    }
+}
+/// Prior state of a template, recorded before each edit so it can be reverted.
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = crate::schema::template_edits)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct TemplateEdit {
+    pub id: i32,
+    pub template_id: i32,
+    pub name: String,
+    pub crop_photo: String,
+    pub crop_number: String,
+    pub template_img: String,
+    pub image_width: i32,
+    pub image_height: i32,
+    pub default_watermark: String,
+    pub font_size: f32,
+    pub text_color: String,
+    pub number_format: String,
+    pub number_padding: i32,
+    pub default_output_folder: String,
+    pub tags: String,
+    pub font_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::template_edits)]
+pub struct NewTemplateEdit {
+    pub template_id: i32,
+    pub name: String,
+    pub crop_photo: String,
+    pub crop_number: String,
+    pub template_img: String,
+    pub image_width: i32,
+    pub image_height: i32,
+    pub default_watermark: String,
+    pub font_size: f32,
+    pub text_color: String,
+    pub number_format: String,
+    pub number_padding: i32,
+    pub default_output_folder: String,
+    pub tags: String,
+    pub font_id: String,
+}
+
+impl From<&PhotoTemplate> for NewTemplateEdit {
+    fn from(template: &PhotoTemplate) -> Self {
+        NewTemplateEdit {
+            template_id: template.id,
+            name: template.name.clone(),
+            crop_photo: template.crop_photo.clone(),
+            crop_number: template.crop_number.clone(),
+            template_img: template.template_img.clone(),
+            image_width: template.image_width,
+            image_height: template.image_height,
+            default_watermark: template.default_watermark.clone(),
+            font_size: template.font_size,
+            text_color: template.text_color.clone(),
+            number_format: template.number_format.clone(),
+            number_padding: template.number_padding,
+            default_output_folder: template.default_output_folder.clone(),
+            tags: template.tags.clone(),
+            font_id: template.font_id.clone(),
+        }
+    }
+}
+
+/// Named generation settings, stored as the JSON sent by the frontend.
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = crate::schema::generation_presets)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct GenerationPreset {
+    pub id: i32,
+    pub name: String,
+    pub settings: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::generation_presets)]
+pub struct NewGenerationPreset {
+    pub name: String,
+    pub settings: String,
+}
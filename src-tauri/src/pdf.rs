@@ -0,0 +1,155 @@
+use std::io::Write;
+
+/// A4 portrait, in points
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const PAGE_MARGIN: f32 = 36.0;
+/// Space between two cells of the grid
+const CELL_PADDING: f32 = 8.0;
+const LABEL_FONT_SIZE: f32 = 8.0;
+const LABEL_LINE_HEIGHT: f32 = 10.0;
+
+/// One thumbnail of a proof sheet with the lines of text printed under it.
+pub struct ProofCell {
+    /// Baseline JPEG in RGB
+    pub jpeg: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub label_lines: Vec<String>,
+}
+
+/// Lay `cells` out in a `columns` x `rows` grid per A4 page and return the encoded PDF.
+pub fn proof_sheet(cells: &[ProofCell], columns: u32, rows: u32) -> Vec<u8> {
+    let columns = columns.max(1);
+    let rows = rows.max(1);
+    let cells_per_page = (columns * rows) as usize;
+    let page_count = cells.len().div_ceil(cells_per_page).max(1);
+
+    // Object numbers: 1 catalog, 2 page tree, 3 font, then page, content and one image per cell
+    let mut objects: Vec<Vec<u8>> = vec![Vec::new(); 3];
+    let mut page_ids = Vec::new();
+
+    let cell_width = (PAGE_WIDTH - 2.0 * PAGE_MARGIN) / columns as f32;
+    let cell_height = (PAGE_HEIGHT - 2.0 * PAGE_MARGIN) / rows as f32;
+
+    for page_index in 0..page_count {
+        let page_cells = cells.iter().skip(page_index * cells_per_page).take(cells_per_page);
+        let page_id = objects.len() + 1;
+        let content_id = page_id + 1;
+        objects.push(Vec::new());
+        objects.push(Vec::new());
+
+        let mut content = Vec::new();
+        let mut image_refs = String::new();
+        for (slot, cell) in page_cells.enumerate() {
+            let image_id = objects.len() + 1;
+            objects.push(image_object(cell));
+            image_refs.push_str(&format!("/Im{} {} 0 R ", slot, image_id));
+
+            let column = slot as u32 % columns;
+            let row = slot as u32 / columns;
+            let cell_x = PAGE_MARGIN + column as f32 * cell_width;
+            let cell_top = PAGE_HEIGHT - PAGE_MARGIN - row as f32 * cell_height;
+
+            // Fit the thumbnail above its label, keeping its aspect ratio
+            let label_height = cell.label_lines.len() as f32 * LABEL_LINE_HEIGHT;
+            let max_width = cell_width - CELL_PADDING;
+            let max_height = cell_height - CELL_PADDING - label_height;
+            let scale = (max_width / cell.width.max(1) as f32).min(max_height / cell.height.max(1) as f32);
+            let (image_width, image_height) = (cell.width as f32 * scale, cell.height as f32 * scale);
+            let image_x = cell_x + (cell_width - image_width) / 2.0;
+            let image_y = cell_top - CELL_PADDING / 2.0 - image_height;
+            let _ = writeln!(
+                content,
+                "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q",
+                image_width, image_height, image_x, image_y, slot
+            );
+
+            for (line_index, line) in cell.label_lines.iter().enumerate() {
+                let baseline = image_y - (line_index + 1) as f32 * LABEL_LINE_HEIGHT + 2.0;
+                let _ = write!(content, "BT /F1 {} Tf {:.2} {:.2} Td (", LABEL_FONT_SIZE, image_x, baseline);
+                content.extend(encode_text(line));
+                let _ = writeln!(content, ") Tj ET");
+            }
+        }
+
+        objects[page_id - 1] = format!(
+            concat!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] ",
+                "/Resources << /Font << /F1 3 0 R >> /XObject << {}>> >> /Contents {} 0 R >>"
+            ),
+            PAGE_WIDTH, PAGE_HEIGHT, image_refs, content_id
+        )
+        .into_bytes();
+        objects[content_id - 1] = stream_object(&format!("/Length {}", content.len()), &content);
+        page_ids.push(page_id);
+    }
+
+    objects[0] = b"<< /Type /Catalog /Pages 2 0 R >>".to_vec();
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        page_ids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "),
+        page_ids.len()
+    )
+    .into_bytes();
+    objects[2] = b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec();
+
+    write_document(&objects)
+}
+
+fn image_object(cell: &ProofCell) -> Vec<u8> {
+    stream_object(
+        &format!(
+            "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode /Length {}",
+            cell.width, cell.height, cell.jpeg.len()
+        ),
+        &cell.jpeg,
+    )
+}
+
+fn stream_object(dictionary: &str, data: &[u8]) -> Vec<u8> {
+    let mut object = format!("<< {} >>\nstream\n", dictionary).into_bytes();
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// Serialize the objects, numbered from 1, with the cross-reference table and trailer.
+fn write_document(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut document = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(document.len());
+        let _ = writeln!(document, "{} 0 obj", index + 1);
+        document.extend_from_slice(object);
+        document.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = document.len();
+    let _ = write!(document, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(document, "{:010} 00000 n ", offset);
+    }
+    let _ = write!(
+        document,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref_offset
+    );
+    document
+}
+
+/// Encode a string for a WinAnsi literal, characters it can't represent become `?`.
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => encoded.extend_from_slice(&[b'\\', c as u8]),
+            ' '..='~' => encoded.push(c as u8),
+            // Latin-1 matches WinAnsi above 0xA0
+            '\u{A0}'..='\u{FF}' => encoded.push(c as u32 as u8),
+            _ => encoded.push(b'?'),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where outputs are pushed after being written locally.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+pub enum RemoteDestination {
+    S3 {
+        bucket: String,
+        /// Key prefix, e.g. `clients/smith/`
+        #[serde(default)]
+        prefix: String,
+        /// Taken from the AWS environment/profile when absent
+        #[serde(default)]
+        region: Option<String>,
+        /// S3-compatible services (MinIO, R2, ...)
+        #[serde(default)]
+        endpoint_url: Option<String>,
+    },
+    Sftp {
+        host: String,
+        #[serde(default = "default_sftp_port")]
+        port: u16,
+        username: String,
+        #[serde(default)]
+        password: Option<String>,
+        /// Used instead of the password when set
+        #[serde(default)]
+        private_key_path: Option<String>,
+        /// Passphrase of an encrypted private key
+        #[serde(default)]
+        private_key_passphrase: Option<String>,
+        /// Remote directory the files are written to
+        #[serde(default)]
+        directory: String,
+    },
+}
+
+fn default_sftp_port() -> u16 {
+    22
+}
+
+/// Sent as `upload-progress` after each output reaches the remote destination.
+#[derive(Serialize, Clone)]
+pub struct UploadProgress {
+    pub remote_name: String,
+    pub uploaded: usize,
+    pub total: usize,
+}
+
+/// `Send` so uploads can run from the render worker threads.
+pub trait Uploader: Send {
+    fn upload(&mut self, local_path: &Path, remote_name: &str) -> Result<(), String>;
+}
+
+/// Open a connection to the destination, failing before any image is rendered when it is unreachable.
+#[cfg(feature = "remote")]
+pub fn connect(destination: &RemoteDestination) -> Result<Box<dyn Uploader>, String> {
+    match destination {
+        RemoteDestination::S3 { bucket, prefix, region, endpoint_url } => Ok(Box::new(S3Uploader::connect(
+            bucket.clone(),
+            prefix.clone(),
+            region.clone(),
+            endpoint_url.clone(),
+        )?)),
+        RemoteDestination::Sftp { host, port, username, password, private_key_path, private_key_passphrase, directory } => {
+            Ok(Box::new(SftpUploader::connect(
+                host,
+                *port,
+                username,
+                password.as_deref(),
+                private_key_path.as_deref().map(|key_path| (key_path, private_key_passphrase.as_deref())),
+                directory,
+            )?))
+        }
+    }
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn connect(_destination: &RemoteDestination) -> Result<Box<dyn Uploader>, String> {
+    Err("This build has no S3/SFTP support, rebuild with the `remote` feature to upload outputs".to_string())
+}
+
+#[cfg(feature = "remote")]
+struct S3Uploader {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+    prefix: String,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(feature = "remote")]
+impl S3Uploader {
+    fn connect(bucket: String, prefix: String, region: Option<String>, endpoint_url: Option<String>) -> Result<Self, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Error starting S3 runtime: {}", e))?;
+
+        let client = block_on_own_thread(&runtime, async {
+            let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+            if let Some(region) = region {
+                loader = loader.region(aws_config::Region::new(region));
+            }
+            let custom_endpoint = endpoint_url.is_some();
+            if let Some(endpoint_url) = endpoint_url {
+                loader = loader.endpoint_url(endpoint_url);
+            }
+            let shared_config = loader.load().await;
+
+            // S3-compatible services usually don't support virtual-hosted bucket names
+            let config = aws_sdk_s3::config::Builder::from(&shared_config)
+                .force_path_style(custom_endpoint)
+                .build();
+            aws_sdk_s3::Client::from_conf(config)
+        })?;
+
+        Ok(S3Uploader { client, bucket, prefix, runtime })
+    }
+}
+
+#[cfg(feature = "remote")]
+impl Uploader for S3Uploader {
+    fn upload(&mut self, local_path: &Path, remote_name: &str) -> Result<(), String> {
+        let key = format!("{}{}", self.prefix, remote_name);
+        let local_path = local_path.to_path_buf();
+        let request = self.client.put_object().bucket(&self.bucket).key(&key);
+
+        block_on_own_thread(&self.runtime, async move {
+            let body = aws_sdk_s3::primitives::ByteStream::from_path(&local_path)
+                .await
+                .map_err(|e| format!("Error reading {:?} for upload: {}", local_path, e))?;
+            request
+                .body(body)
+                .send()
+                .await
+                .map_err(|e| format!("Error uploading {} to S3: {}", key, aws_sdk_s3::error::DisplayErrorContext(e)))?;
+            Ok(())
+        })?
+    }
+}
+
+/// Run `future` to completion on `runtime` from a fresh thread, so it also works when the
+/// caller is already inside the app's async runtime.
+#[cfg(feature = "remote")]
+fn block_on_own_thread<F>(runtime: &tokio::runtime::Runtime, future: F) -> Result<F::Output, String>
+where
+    F: std::future::Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| runtime.block_on(future))
+            .join()
+            .map_err(|_| "S3 upload thread panicked".to_string())
+    })
+}
+
+#[cfg(feature = "remote")]
+struct SftpUploader {
+    // Kept alive for the lifetime of the SFTP channel
+    _session: ssh2::Session,
+    sftp: ssh2::Sftp,
+    directory: String,
+    /// Remote directories known to exist, so each is only created once per run
+    created_directories: std::collections::HashSet<String>,
+}
+
+#[cfg(feature = "remote")]
+impl SftpUploader {
+    fn connect(
+        host: &str,
+        port: u16,
+        username: &str,
+        password: Option<&str>,
+        private_key: Option<(&str, Option<&str>)>,
+        directory: &str,
+    ) -> Result<Self, String> {
+        let tcp = std::net::TcpStream::connect((host, port))
+            .map_err(|e| format!("Error connecting to {}:{}: {}", host, port, e))?;
+        let mut session = ssh2::Session::new().map_err(|e| format!("Error creating SSH session: {}", e))?;
+        session.set_tcp_stream(tcp);
+        session.handshake().map_err(|e| format!("SSH handshake with {} failed: {}", host, e))?;
+
+        match (private_key, password) {
+            (Some((key_path, passphrase)), _) => session.userauth_pubkey_file(username, None, Path::new(key_path), passphrase),
+            (None, Some(password)) => session.userauth_password(username, password),
+            (None, None) => session.userauth_agent(username),
+        }
+        .map_err(|e| format!("SSH authentication as {} failed: {}", username, e))?;
+
+        let sftp = session.sftp().map_err(|e| format!("Error opening SFTP channel: {}", e))?;
+        Ok(SftpUploader {
+            _session: session,
+            sftp,
+            directory: directory.to_string(),
+            created_directories: std::collections::HashSet::new(),
+        })
+    }
+
+    /// Create the missing directories above `remote_path`, e.g. the subfolders of a recursive run.
+    fn create_parent_directories(&mut self, remote_path: &str) -> Result<(), String> {
+        let Some((parent, _)) = remote_path.rsplit_once('/') else {
+            return Ok(());
+        };
+        let mut directory = String::new();
+        for component in parent.split('/') {
+            if !directory.is_empty() || parent.starts_with('/') {
+                directory.push('/');
+            }
+            directory.push_str(component);
+            if component.is_empty() || self.created_directories.contains(&directory) {
+                continue;
+            }
+            if self.sftp.stat(Path::new(&directory)).is_err() {
+                self.sftp
+                    .mkdir(Path::new(&directory), 0o755)
+                    .map_err(|e| format!("Error creating remote directory {}: {}", directory, e))?;
+            }
+            self.created_directories.insert(directory.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "remote")]
+impl Uploader for SftpUploader {
+    fn upload(&mut self, local_path: &Path, remote_name: &str) -> Result<(), String> {
+        let remote_path = remote_file_path(&self.directory, remote_name);
+        self.create_parent_directories(&remote_path)?;
+        let mut local = std::fs::File::open(local_path)
+            .map_err(|e| format!("Error reading {:?} for upload: {}", local_path, e))?;
+        let mut remote = self.sftp
+            .create(Path::new(&remote_path))
+            .map_err(|e| format!("Error creating remote file {}: {}", remote_path, e))?;
+        std::io::copy(&mut local, &mut remote)
+            .map_err(|e| format!("Error uploading to {}: {}", remote_path, e))?;
+        Ok(())
+    }
+}
+
+/// `/`-separated path of an output on the server whatever the local platform, `remote_name`
+/// may contain subfolders.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn remote_file_path(directory: &str, remote_name: &str) -> String {
+    let directory = directory.trim_end_matches('/');
+    let remote_name = remote_name.replace('\\', "/");
+    if directory.is_empty() {
+        remote_name
+    } else {
+        format!("{}/{}", directory, remote_name.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_file_path_joins_with_forward_slashes() {
+        assert_eq!(remote_file_path("/srv/outputs/", "a/b/photo.jpg"), "/srv/outputs/a/b/photo.jpg");
+        assert_eq!(remote_file_path("outputs", "photo.jpg"), "outputs/photo.jpg");
+        assert_eq!(remote_file_path("", "sub\\photo.jpg"), "sub/photo.jpg");
+    }
+}
@@ -1,5 +1,13 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    generation_presets (id) {
+        id -> Integer,
+        name -> Text,
+        settings -> Text,
+    }
+}
+
 diesel::table! {
     photo_templates (id) {
         id -> Integer,
@@ -7,5 +15,46 @@ diesel::table! {
         crop_photo -> Text,
         crop_number -> Text,
         template_img -> Text,
+        image_width -> Integer,
+        image_height -> Integer,
+        default_watermark -> Text,
+        font_size -> Float,
+        text_color -> Text,
+        number_format -> Text,
+        number_padding -> Integer,
+        default_output_folder -> Text,
+        tags -> Text,
+        font_id -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
-}
\ No newline at end of file
+}
+
+diesel::table! {
+    template_edits (id) {
+        id -> Integer,
+        template_id -> Integer,
+        name -> Text,
+        crop_photo -> Text,
+        crop_number -> Text,
+        template_img -> Text,
+        image_width -> Integer,
+        image_height -> Integer,
+        default_watermark -> Text,
+        font_size -> Float,
+        text_color -> Text,
+        number_format -> Text,
+        number_padding -> Integer,
+        default_output_folder -> Text,
+        tags -> Text,
+        font_id -> Text,
+    }
+}
+
+diesel::joinable!(template_edits -> photo_templates (template_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    generation_presets,
+    photo_templates,
+    template_edits,
+);
@@ -0,0 +1,131 @@
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::CropCoordinates;
+
+pub const PHOTO_RECT_ID: &str = "crop_photo";
+pub const NUMBER_RECT_ID: &str = "crop_number";
+
+/// Build an SVG referencing the template image with one labeled `<rect>` per crop region.
+pub fn template_to_svg(
+    template_img: &str,
+    width: u32,
+    height: u32,
+    crop_photo: Option<&CropCoordinates>,
+    crop_number: Option<&CropCoordinates>,
+) -> String {
+    let mut svg = format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" ",
+            "width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n",
+            "  <image id=\"template\" x=\"0\" y=\"0\" width=\"{width}\" height=\"{height}\" xlink:href=\"{href}\"/>\n"
+        ),
+        width = width,
+        height = height,
+        href = escape_attribute(&file_url(template_img)),
+    );
+
+    for (id, label, color, crop) in [
+        (PHOTO_RECT_ID, "Photo", "#2563eb", crop_photo),
+        (NUMBER_RECT_ID, "Number", "#dc2626", crop_number),
+    ] {
+        if let Some(crop) = crop {
+            svg.push_str(&format!(
+                "  <rect id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                id, crop.x, crop.y, crop.width, crop.height, color
+            ));
+            svg.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" fill=\"{}\" font-family=\"sans-serif\" font-size=\"16\">{}</text>\n",
+                crop.x + 4.0, crop.y + 18.0, color, label
+            ));
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Read the photo and number crop rectangles back from an SVG produced by `template_to_svg`.
+pub fn parse_crop_rects(svg: &str) -> Result<(Option<CropCoordinates>, Option<CropCoordinates>), String> {
+    let rect_re = Regex::new(r"<rect\b([^>]*)>").unwrap();
+    let attr_re = Regex::new(r#"([A-Za-z_:][\w:.-]*)\s*=\s*"([^"]*)""#).unwrap();
+
+    let mut crop_photo = None;
+    let mut crop_number = None;
+
+    for rect in rect_re.captures_iter(svg) {
+        let attributes: HashMap<&str, &str> = attr_re
+            .captures_iter(rect.get(1).map_or("", |m| m.as_str()))
+            .filter_map(|attr| Some((attr.get(1)?.as_str(), attr.get(2)?.as_str())))
+            .collect();
+
+        let target = match attributes.get("id") {
+            Some(&PHOTO_RECT_ID) => &mut crop_photo,
+            Some(&NUMBER_RECT_ID) => &mut crop_number,
+            _ => continue,
+        };
+
+        *target = Some(CropCoordinates {
+            x: parse_length(&attributes, "x")?,
+            y: parse_length(&attributes, "y")?,
+            width: parse_length(&attributes, "width")?,
+            height: parse_length(&attributes, "height")?,
+        });
+    }
+
+    Ok((crop_photo, crop_number))
+}
+
+fn parse_length(attributes: &HashMap<&str, &str>, name: &str) -> Result<f32, String> {
+    let value = attributes.get(name).copied().unwrap_or("0");
+    value
+        .trim()
+        .trim_end_matches("px")
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid rect attribute {}=\"{}\": {}", name, value, e))
+}
+
+fn file_url(path: &str) -> String {
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        format!("file:///{}", path.replace('\\', "/"))
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svg_has_a_rect_matching_each_crop() {
+        let crop_photo = CropCoordinates { x: 20.0, y: 30.0, width: 360.0, height: 200.5 };
+        let crop_number = CropCoordinates { x: 20.0, y: 240.0, width: 360.0, height: 40.0 };
+
+        let svg = template_to_svg("/templates/a&b.png", 400, 300, Some(&crop_photo), Some(&crop_number));
+
+        assert!(svg.contains(r#"<rect id="crop_photo" x="20" y="30" width="360" height="200.5""#), "{}", svg);
+        assert!(svg.contains(r#"xlink:href="file:///templates/a&amp;b.png""#), "{}", svg);
+        let (photo, number) = parse_crop_rects(&svg).unwrap();
+        assert_eq!(photo, Some(crop_photo));
+        assert_eq!(number, Some(crop_number));
+    }
+
+    #[test]
+    fn crop_rects_accept_pixel_units_and_ignore_other_rects() {
+        let svg = r#"<svg><rect id="frame" x="0" y="0" width="10" height="10"/><rect width="50px" height="60" id="crop_photo" x="1" y="2"/></svg>"#;
+
+        let (photo, number) = parse_crop_rects(svg).unwrap();
+
+        assert_eq!(photo, Some(CropCoordinates { x: 1.0, y: 2.0, width: 50.0, height: 60.0 }));
+        assert_eq!(number, None);
+        assert!(parse_crop_rects(r#"<rect id="crop_photo" x="a"/>"#).is_err());
+    }
+}
@@ -0,0 +1,2413 @@
+use super::*;
+use image::RgbaImage;
+
+/// Directory under the system temp dir, removed with everything in it when dropped.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    pub(crate) fn new(name: &str) -> Self {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "photo_template_test_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub(crate) fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub(crate) fn crop(x: f32, y: f32, width: f32, height: f32) -> String {
+    serde_json::to_string(&CropCoordinates { x, y, width, height }).unwrap()
+}
+
+/// 400x300 light gray template with the photo area on top and the number area below it.
+pub(crate) fn test_template(dir: &TestDir) -> PhotoTemplate {
+    let template_path = dir.join("template.png");
+    ImageBuffer::from_pixel(400, 300, Rgba([240u8, 240, 240, 255])).save(&template_path).unwrap();
+    PhotoTemplate {
+        id: 0,
+        name: "Test".to_string(),
+        crop_photo: crop(20.0, 20.0, 360.0, 200.0),
+        crop_number: crop(20.0, 240.0, 360.0, 40.0),
+        template_img: template_path.to_string_lossy().to_string(),
+        image_width: 400,
+        image_height: 300,
+        default_watermark: String::new(),
+        font_size: 0.0,
+        text_color: DEFAULT_TEXT_COLOR.to_string(),
+        number_format: DEFAULT_NUMBER_FORMAT.to_string(),
+        number_padding: 0,
+        default_output_folder: String::new(),
+        tags: "[]".to_string(),
+        font_id: String::new(),
+        created_at: chrono::Utc::now().naive_utc(),
+        updated_at: chrono::Utc::now().naive_utc(),
+    }
+}
+
+/// `test_template` drawn with `image` instead of the plain gray one, crops unchanged.
+pub(crate) fn template_with_image(dir: &TestDir, image: RgbaImage) -> PhotoTemplate {
+    let template = test_template(dir);
+    image.save(&template.template_img).unwrap();
+    PhotoTemplate {
+        image_width: image.width() as i32,
+        image_height: image.height() as i32,
+        ..template
+    }
+}
+
+/// Write a gradient photo, in the format given by the extension of `path`.
+pub(crate) fn write_photo(path: &Path, width: u32, height: u32) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image::RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]))
+        .save(path)
+        .unwrap();
+}
+
+/// Keeps every event a run reports.
+#[derive(Default)]
+pub(crate) struct RecordingObserver {
+    pub(crate) progress: Vec<GenerationProgress>,
+    pub(crate) uploads: Vec<UploadProgress>,
+    pub(crate) cancelled: Vec<GenerationCancelled>,
+    pub(crate) skipped: Vec<SkippedSource>,
+}
+
+impl GenerationObserver for RecordingObserver {
+    fn on_progress(&mut self, progress: GenerationProgress) {
+        self.progress.push(progress);
+    }
+
+    fn on_upload(&mut self, upload: UploadProgress) {
+        self.uploads.push(upload);
+    }
+
+    fn on_cancelled(&mut self, cancelled: GenerationCancelled) {
+        self.cancelled.push(cancelled);
+    }
+
+    fn on_skipped(&mut self, skipped: SkippedSource) {
+        self.skipped.push(skipped);
+    }
+}
+
+pub(crate) fn generate(
+    template: &PhotoTemplate,
+    photos_dir: &Path,
+    output_dir: &Path,
+    options: &GenerationOptions,
+    observer: &mut RecordingObserver,
+) -> Result<GenerationRun, String> {
+    fs::create_dir_all(output_dir).unwrap();
+    generate_images(
+        template,
+        &OrientationTemplates::default(),
+        &photos_dir.to_string_lossy(),
+        output_dir,
+        options,
+        &AtomicBool::new(false),
+        observer,
+    )
+}
+
+/// Minimal S3-compatible endpoint answering every request with 200, keeping "METHOD /path" of each without the query.
+#[cfg(feature = "remote")]
+fn spawn_mock_s3() -> (String, std::sync::Arc<Mutex<Vec<String>>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let requests = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let received = received.clone();
+            std::thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let (mut content_length, mut chunked) = (0, false);
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).unwrap();
+                        let header = header.trim_end().to_ascii_lowercase();
+                        if header.is_empty() {
+                            break;
+                        }
+                        if let Some(value) = header.strip_prefix("content-length:") {
+                            content_length = value.trim().parse().unwrap();
+                        } else if header == "expect: 100-continue" {
+                            writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").unwrap();
+                        } else if header.starts_with("transfer-encoding:") && header.contains("chunked") {
+                            chunked = true;
+                        }
+                    }
+                    if chunked {
+                        loop {
+                            let mut size = String::new();
+                            reader.read_line(&mut size).unwrap();
+                            let size = usize::from_str_radix(size.trim().split(';').next().unwrap(), 16).unwrap();
+                            let mut chunk = vec![0; size + 2];
+                            reader.read_exact(&mut chunk).unwrap();
+                            if size == 0 {
+                                break;
+                            }
+                        }
+                    } else {
+                        let mut body = vec![0; content_length];
+                        reader.read_exact(&mut body).unwrap();
+                    }
+                    let mut parts = request_line.split_whitespace();
+                    received
+                        .lock()
+                        .unwrap()
+                        .push(format!("{} {}", parts.next().unwrap_or(""), parts.next().unwrap_or("").split('?').next().unwrap()));
+                    writer
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nETag: \"mock\"\r\n\r\n")
+                        .unwrap();
+                }
+            });
+        }
+    });
+    (endpoint, requests)
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn each_output_is_uploaded_to_the_remote_destination() {
+    std::env::set_var("AWS_ACCESS_KEY_ID", "test");
+    std::env::set_var("AWS_SECRET_ACCESS_KEY", "test");
+    std::env::set_var("AWS_EC2_METADATA_DISABLED", "true");
+    let (endpoint, requests) = spawn_mock_s3();
+
+    let dir = TestDir::new("upload");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    write_photo(&dir.join("photos/photo_2.png"), 120, 90);
+    write_photo(&dir.join("photos/photo_3.png"), 120, 90);
+    let options = GenerationOptions {
+        remote_destination: Some(RemoteDestination::S3 {
+            bucket: "outputs".to_string(),
+            prefix: "run/".to_string(),
+            region: Some("us-east-1".to_string()),
+            endpoint_url: Some(endpoint),
+        }),
+        ..Default::default()
+    };
+
+    let mut observer = RecordingObserver::default();
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut observer).unwrap();
+
+    assert_eq!(run.images.len(), 3);
+    let mut uploaded = requests.lock().unwrap().clone();
+    uploaded.sort();
+    assert_eq!(
+        uploaded,
+        [
+            "PUT /outputs/run/photo_1_processed.jpg",
+            "PUT /outputs/run/photo_2_processed.jpg",
+            "PUT /outputs/run/photo_3_processed.jpg",
+        ]
+    );
+    assert_eq!(observer.uploads.len(), 3);
+    assert_eq!(observer.uploads.iter().map(|upload| upload.uploaded).max(), Some(3));
+}
+
+#[cfg(not(feature = "remote"))]
+#[test]
+fn remote_destination_needs_the_remote_feature() {
+    let dir = TestDir::new("no_remote");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let options = GenerationOptions {
+        remote_destination: Some(RemoteDestination::S3 {
+            bucket: "outputs".to_string(),
+            prefix: String::new(),
+            region: None,
+            endpoint_url: None,
+        }),
+        ..Default::default()
+    };
+
+    let error = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .err()
+        .unwrap();
+    assert!(error.contains("`remote` feature"), "{}", error);
+    assert!(!dir.join("output/photo_1_processed.jpg").exists());
+}
+
+#[test]
+fn empty_source_is_skipped_and_reported() {
+    let dir = TestDir::new("empty_source");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    fs::write(dir.join("photos/photo_2.jpg"), b"").unwrap();
+
+    let mut observer = RecordingObserver::default();
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &GenerationOptions::default(), &mut observer)
+        .unwrap();
+
+    assert_eq!(run.images.len(), 1);
+    assert_eq!(observer.skipped.len(), 1);
+    assert!(observer.skipped[0].path.ends_with("photo_2.jpg"));
+    assert_eq!(run.failures.len(), 1);
+    assert_eq!(run.failures[0].sources, [dir.join("photos/photo_2.jpg")]);
+    assert_eq!(run.failures[0].reason, EMPTY_SOURCE_REASON);
+}
+
+#[test]
+fn truncated_jpeg_is_reported_as_truncated() {
+    let dir = TestDir::new("truncated_source");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.jpg"), 120, 90);
+    write_photo(&dir.join("photo_2.jpg"), 120, 90);
+    let complete = fs::read(dir.join("photo_2.jpg")).unwrap();
+    fs::write(dir.join("photos/photo_2.jpg"), &complete[..complete.len() / 2]).unwrap();
+    let options = GenerationOptions {
+        skip_failed_files: true,
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .unwrap();
+
+    assert_eq!(run.images.len(), 1);
+    assert_eq!(run.failures.len(), 1);
+    assert!(run.failures[0].reason.contains("file appears truncated"), "{}", run.failures[0].reason);
+}
+
+#[test]
+fn run_thumbnail_is_a_small_grid_of_the_first_outputs() {
+    let dir = TestDir::new("run_thumbnail");
+    let template = test_template(&dir);
+    for index in 1..=5 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 120, 90);
+    }
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &GenerationOptions::default(), &mut RecordingObserver::default())
+        .unwrap();
+
+    let thumbnail_path = write_run_thumbnail(&run.images, &dir.join("output")).unwrap().unwrap();
+
+    assert_eq!(thumbnail_path, dir.join("output").join(RUN_THUMBNAIL_FILENAME));
+    let thumbnail = image::open(&thumbnail_path).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (RUN_THUMBNAIL_SIZE, RUN_THUMBNAIL_SIZE));
+    assert!(fs::metadata(&thumbnail_path).unwrap().len() < 32 * 1024);
+    assert_eq!(write_run_thumbnail(&[], &dir.join("output")), Ok(None));
+}
+
+/// Open the database shared by the tests, in its own directory of the temp dir.
+pub(crate) fn test_database() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("photo_template_test_database_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        open_database(&dir.join(DATABASE_FILENAME)).unwrap();
+    });
+}
+
+#[test]
+fn legacy_database_is_moved_not_copied() {
+    let dir = TestDir::new("legacy_database");
+    let legacy_path = dir.join("legacy.db");
+    let database_path = dir.join("app_data/photo_templates.db");
+    fs::create_dir_all(database_path.parent().unwrap()).unwrap();
+    fs::write(&legacy_path, b"legacy").unwrap();
+
+    move_legacy_database(&legacy_path, &database_path).unwrap();
+
+    assert!(!legacy_path.exists());
+    assert_eq!(fs::read(&database_path).unwrap(), b"legacy");
+}
+
+#[test]
+fn legacy_database_never_replaces_the_current_one() {
+    let dir = TestDir::new("legacy_database_kept");
+    let legacy_path = dir.join("legacy.db");
+    let database_path = dir.join("photo_templates.db");
+    fs::write(&legacy_path, b"legacy").unwrap();
+    fs::write(&database_path, b"current").unwrap();
+
+    move_legacy_database(&legacy_path, &database_path).unwrap();
+
+    assert!(legacy_path.exists());
+    assert_eq!(fs::read(&database_path).unwrap(), b"current");
+}
+
+#[test]
+fn database_connections_come_from_the_initialized_pool() {
+    test_database();
+    let mut connection = establish_connection().unwrap();
+    let applied = connection.applied_migrations().unwrap();
+    assert_eq!(applied.len(), MigrationSource::<diesel::sqlite::Sqlite>::migrations(&MIGRATIONS).unwrap().len());
+}
+
+#[test]
+fn repair_recovers_from_a_migration_applied_but_not_recorded() {
+    let dir = TestDir::new("repair_database");
+    let mut connection = SqliteConnection::establish(&dir.join("repair.db").to_string_lossy()).unwrap();
+    run_migrations(&mut connection).unwrap();
+    // As if the app stopped between running the latest migration and recording it
+    diesel::sql_query(
+        "DELETE FROM __diesel_schema_migrations WHERE version = (SELECT MAX(version) FROM __diesel_schema_migrations)",
+    )
+    .execute(&mut connection)
+    .unwrap();
+    assert!(run_migrations(&mut connection).is_err());
+
+    let report = repair_database_schema(&mut connection).unwrap();
+
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+    assert!(report.actions.iter().any(|action| action.starts_with("Reverted and re-applied migration")), "{:?}", report.actions);
+    assert_eq!(report.integrity, "ok");
+    // The copy taken before reverting still has the data of the reverted migration's tables
+    let backup = report.backup.unwrap();
+    assert!(backup.starts_with(&*dir.join("repair.db").to_string_lossy()), "{}", backup);
+    let mut backup_connection = SqliteConnection::establish(&backup).unwrap();
+    assert_eq!(
+        backup_connection.applied_migrations().unwrap().len(),
+        MigrationSource::<diesel::sqlite::Sqlite>::migrations(&MIGRATIONS).unwrap().len() - 1
+    );
+    run_migrations(&mut connection).unwrap();
+    schema::photo_templates::table.load::<PhotoTemplate>(&mut connection).unwrap();
+}
+
+#[test]
+fn repair_reports_migrations_unknown_to_this_version() {
+    let dir = TestDir::new("repair_unknown_migration");
+    let mut connection = SqliteConnection::establish(&dir.join("repair.db").to_string_lossy()).unwrap();
+    run_migrations(&mut connection).unwrap();
+    diesel::sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ('29991231000000')")
+        .execute(&mut connection)
+        .unwrap();
+
+    let report = repair_database_schema(&mut connection).unwrap();
+
+    assert_eq!(report.problems, ["Unknown migration 29991231000000 is recorded as applied"]);
+    // Nothing was reverted, so nothing needed backing up
+    assert!(report.backup.is_none());
+}
+
+/// `test_template` stored in the test database, its image being in `dir`.
+pub(crate) fn stored_template(dir: &TestDir) -> PhotoTemplate {
+    test_database();
+    let template = test_template(dir);
+    add_photo_template(
+        template.name,
+        template.crop_photo,
+        template.crop_number,
+        template.template_img,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+#[test]
+fn deleting_a_template_removes_its_history_and_unused_image() {
+    let dir = TestDir::new("delete_template");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    record_template_edit(&mut connection, &template).unwrap();
+
+    delete_template(&mut connection, template.id, dir.path()).unwrap();
+
+    assert!(photo_templates::table.find(template.id).first::<PhotoTemplate>(&mut connection).is_err());
+    let edits: i64 = template_edits::table
+        .filter(template_edits::template_id.eq(template.id))
+        .count()
+        .get_result(&mut connection)
+        .unwrap();
+    assert_eq!(edits, 0);
+    assert!(!Path::new(&template.template_img).exists());
+    assert_eq!(delete_template(&mut connection, template.id, dir.path()), Err("Photo template not found".to_string()));
+}
+
+#[test]
+fn remapping_to_a_twice_larger_image_doubles_the_crops() {
+    let dir = TestDir::new("remap_crops");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+
+    let remapped = remap_template_crops(&mut connection, template.id, 800, 600, dir.path()).unwrap();
+
+    assert_eq!(remapped.crop_photo, crop(40.0, 40.0, 720.0, 400.0));
+    assert_eq!(remapped.crop_number, crop(40.0, 480.0, 720.0, 80.0));
+    assert_eq!((remapped.image_width, remapped.image_height), (800, 600));
+    // Still the 400x300 file, until the larger version is swapped in
+    assert_eq!(remapped.template_img, template.template_img);
+}
+
+#[test]
+fn generation_commands_share_output_dir_and_archive_settings() {
+    let dir = TestDir::new("request_options");
+    let options = generation_request_options(
+        None,
+        Some(serde_json::json!({ "recursive": true })),
+        Some(dir.path().to_string_lossy().to_string()),
+        Some(false),
+    )
+    .unwrap();
+
+    assert!(options.recursive);
+    assert!(options.loose_files);
+    assert_eq!(options.output_folder.as_deref(), Some(dir.path().to_string_lossy().as_ref()));
+
+    let error = generation_request_options(None, None, Some(dir.join("missing").to_string_lossy().to_string()), None)
+        .err()
+        .unwrap();
+    assert!(error.contains("missing"), "{}", error);
+    let defaults = generation_request_options(None, None, Some("  ".to_string()), None).unwrap();
+    assert!(!defaults.loose_files);
+    assert_eq!(defaults.output_folder, None);
+}
+
+#[test]
+fn number_is_written_to_the_output_metadata() {
+    let dir = TestDir::new("metadata_number");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_0042.png"), 120, 90);
+    let options = GenerationOptions {
+        write_number_to_metadata: true,
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .unwrap();
+
+    let output = fs::read(&run.images[0].output).unwrap();
+    let packet = String::from_utf8_lossy(&output);
+    assert!(packet.contains("<dc:identifier>0042</dc:identifier>"), "number {}", run.images[0].number);
+    image::load_from_memory(&output).unwrap();
+}
+
+#[test]
+fn number_metadata_is_refused_for_formats_without_xmp_support() {
+    let dir = TestDir::new("metadata_number_tiff");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_0042.png"), 120, 90);
+    let options = GenerationOptions {
+        write_number_to_metadata: true,
+        output_format: OutputFormat::Tiff,
+        ..Default::default()
+    };
+
+    let error = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .err()
+        .unwrap();
+    assert!(error.contains("TIFF outputs"), "{}", error);
+}
+
+#[test]
+fn sample_preview_shows_preview_watermarks_only() {
+    let dir = TestDir::new("sample_preview");
+    let template = test_template(&dir);
+    write_photo(&dir.join("sample.png"), 120, 90);
+    let render = |target: Option<watermark::WatermarkTarget>| {
+        let options = GenerationOptions {
+            watermark: target.map(|target| WatermarkOptions {
+                text: "PROOF".to_string(),
+                opacity: 1.0,
+                color: [255, 0, 0],
+                target,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        render_sample_preview(&template, &dir.join("sample.png"), &options).unwrap().to_rgba8()
+    };
+
+    let plain = render(None);
+    assert_eq!((plain.width(), plain.height()), (400, 300));
+    assert_ne!(render(Some(watermark::WatermarkTarget::Previews)), plain);
+    assert_eq!(render(Some(watermark::WatermarkTarget::Fullres)), plain);
+}
+
+#[test]
+fn template_font_must_be_loadable() {
+    assert!(list_available_fonts().iter().any(|font| font.id == "dejavu-sans"));
+    assert_eq!(validate_font_id(" dejavu-sans ".to_string()), Ok("dejavu-sans".to_string()));
+    assert_eq!(validate_font_id(String::new()), Ok(String::new()));
+    assert!(validate_font_id("missing-font.ttf".to_string()).is_err());
+
+    let dir = TestDir::new("template_font");
+    let template = PhotoTemplate {
+        font_id: "missing-font.ttf".to_string(),
+        ..test_template(&dir)
+    };
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let error = generate(&template, &dir.join("photos"), &dir.join("output"), &GenerationOptions::default(), &mut RecordingObserver::default())
+        .err()
+        .unwrap();
+    assert!(error.contains("missing-font.ttf"), "{}", error);
+}
+
+#[test]
+fn transparent_template_border_stays_transparent_in_png_outputs() {
+    let dir = TestDir::new("transparent_border");
+    let template = template_with_image(
+        &dir,
+        RgbaImage::from_fn(400, 300, |x, y| {
+            let border = x < 10 || y < 10 || x >= 390 || y >= 290;
+            Rgba([240, 240, 240, if border { 0 } else { 255 }])
+        }),
+    );
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let options = GenerationOptions {
+        output_format: OutputFormat::Png,
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .unwrap();
+
+    let output = image::open(&run.images[0].output).unwrap();
+    assert!(output.color().has_alpha());
+    let output = output.to_rgba8();
+    assert_eq!(output.get_pixel(2, 2)[3], 0);
+    assert_eq!(output.get_pixel(397, 150)[3], 0);
+    assert_eq!(output.get_pixel(200, 100)[3], 255);
+}
+
+#[test]
+fn crops_scaled_to_a_preview_keep_their_ratio() {
+    let dir = TestDir::new("scale_crops");
+    let template = stored_template(&dir);
+
+    let crops = scale_crops_to(template.id, 200, 150).unwrap();
+
+    assert_eq!(crops.crop_photo, CropCoordinates { x: 10.0, y: 10.0, width: 180.0, height: 100.0 });
+    assert_eq!(crops.crop_number, Some(CropCoordinates { x: 10.0, y: 120.0, width: 180.0, height: 20.0 }));
+    assert_eq!(crops.crop_photo.width / crops.crop_photo.height, 360.0 / 200.0);
+}
+
+#[test]
+fn template_image_report_classifies_each_template() {
+    let dir = TestDir::new("check_template_images");
+    let valid = stored_template(&dir);
+    let missing = stored_template(&dir);
+    let corrupt = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    for (template, path) in [(&missing, dir.join("missing.png")), (&corrupt, dir.join("corrupt.png"))] {
+        diesel::update(photo_templates::table.find(template.id))
+            .set(photo_templates::template_img.eq(path.to_string_lossy().to_string()))
+            .execute(&mut connection)
+            .unwrap();
+    }
+    fs::write(dir.join("corrupt.png"), b"not a png").unwrap();
+
+    let reports = check_template_images().unwrap();
+
+    let status_of = |id: i32| reports.iter().find(|report| report.id == id).map(|report| &report.status);
+    assert_eq!(status_of(valid.id), Some(&TemplateImageStatus::Ok));
+    assert_eq!(status_of(missing.id), Some(&TemplateImageStatus::Missing));
+    assert_eq!(status_of(corrupt.id), Some(&TemplateImageStatus::Corrupt));
+}
+
+#[test]
+fn mosaic_tiles_four_photos_into_a_two_by_two_grid() {
+    let dir = TestDir::new("mosaic");
+    let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 0]];
+    let photos: Vec<PathBuf> = colors
+        .iter()
+        .enumerate()
+        .map(|(index, color)| {
+            let path = dir.join(&format!("photo_{}.png", index));
+            image::RgbImage::from_pixel(60, 40, image::Rgb(*color)).save(&path).unwrap();
+            path
+        })
+        .collect();
+    let mosaic = MosaicOptions { columns: Some(2), rows: Some(2) };
+
+    let canvas = build_mosaic(&photos, 200, 100, mosaic, &GenerationOptions::default(), None).unwrap().to_rgba8();
+
+    let quadrant_centers = [(50, 25), (150, 25), (50, 75), (150, 75)];
+    for ((x, y), color) in quadrant_centers.into_iter().zip(colors) {
+        assert_eq!(canvas.get_pixel(x, y).0[..3], color, "quadrant at {}x{}", x, y);
+    }
+    assert_eq!(mosaic.grid_for(4), (2, 2));
+    assert_eq!(MosaicOptions::default().grid_for(5), (3, 2));
+}
+
+#[cfg(unix)]
+#[test]
+fn cancelling_while_archiving_leaves_no_archive() {
+    let dir = TestDir::new("archive_cancel");
+    write_photo(&dir.join("photo_1.png"), 20, 20);
+    write_photo(&dir.join("photo_3.png"), 20, 20);
+    // Reading this entry blocks until the test writes it, so the run is cancelled halfway through
+    let pipe = dir.join("photo_2.png");
+    assert!(std::process::Command::new("mkfifo").arg(&pipe).status().unwrap().success());
+    let entries = ["photo_1.png", "photo_2.png", "photo_3.png"]
+        .iter()
+        .map(|name| (dir.join(name), name.to_string()))
+        .collect();
+    let archive_path = dir.join("outputs.zip");
+    let cancelled = std::sync::Arc::new(AtomicBool::new(false));
+
+    let writer = {
+        let cancelled = cancelled.clone();
+        std::thread::spawn(move || {
+            let mut pipe = fs::OpenOptions::new().write(true).open(pipe).unwrap();
+            cancelled.store(true, Ordering::SeqCst);
+            std::io::Write::write_all(&mut pipe, b"image data").unwrap();
+        })
+    };
+    let result = create_archive(
+        entries,
+        &archive_path,
+        DuplicateEntryPolicy::Rename,
+        false,
+        ArchiveCompression::default(),
+        &cancelled,
+    );
+    writer.join().unwrap();
+
+    assert!(result.unwrap_err().contains("cancelled"));
+    assert!(!archive_path.exists());
+    assert!(!archive_path.with_extension("zip.part").exists());
+}
+
+#[test]
+fn syncing_a_config_updates_existing_templates_and_inserts_new_ones() {
+    let dir = TestDir::new("sync_templates");
+    let existing = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    diesel::update(photo_templates::table.find(existing.id))
+        .set(photo_templates::name.eq("Synced existing"))
+        .execute(&mut connection)
+        .unwrap();
+    let config: TemplatesConfig = serde_json::from_value(serde_json::json!({
+        "templates": [
+            { "name": "Synced existing", "crop_photo": { "x": 10.0, "y": 10.0, "width": 100.0, "height": 80.0 } },
+            {
+                "name": "Synced new",
+                "crop_photo": { "x": 0.0, "y": 0.0, "width": 50.0, "height": 50.0 },
+                "template_img": existing.template_img,
+            },
+        ]
+    }))
+    .unwrap();
+
+    let report = sync_templates(&mut connection, config, dir.path()).unwrap();
+
+    assert_eq!(report.updated, ["Synced existing"]);
+    assert_eq!(report.inserted, ["Synced new"]);
+    assert!(report.unchanged.is_empty());
+    let updated: PhotoTemplate = photo_templates::table.find(existing.id).first(&mut connection).unwrap();
+    assert_eq!(updated.crop_photo, crop(10.0, 10.0, 100.0, 80.0));
+    assert_eq!(updated.crop_number, "");
+    let inserted: PhotoTemplate = photo_templates::table
+        .filter(photo_templates::name.eq("Synced new"))
+        .first(&mut connection)
+        .unwrap();
+    assert_eq!(inserted.crop_photo, crop(0.0, 0.0, 50.0, 50.0));
+    assert_eq!((inserted.image_width, inserted.image_height), (400, 300));
+}
+
+#[test]
+fn synced_images_are_copied_into_managed_storage_once() {
+    let dir = TestDir::new("sync_template_images");
+    let existing = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    diesel::update(photo_templates::table.find(existing.id))
+        .set(photo_templates::name.eq("Synced image existing"))
+        .execute(&mut connection)
+        .unwrap();
+    let frame_path = dir.join("frame.png");
+    ImageBuffer::from_pixel(200, 100, Rgba([0u8, 0, 200, 255])).save(&frame_path).unwrap();
+    let images_dir = dir.join("images");
+    let config = || -> TemplatesConfig {
+        serde_json::from_value(serde_json::json!({
+            "templates": [
+                {
+                    "name": "Synced image existing",
+                    "crop_photo": { "x": 0.0, "y": 0.0, "width": 50.0, "height": 50.0 },
+                    "template_img": frame_path,
+                },
+                {
+                    "name": "Synced image new",
+                    "crop_photo": { "x": 0.0, "y": 0.0, "width": 50.0, "height": 50.0 },
+                    "template_img": frame_path,
+                },
+            ]
+        }))
+        .unwrap()
+    };
+    let managed_files = || fs::read_dir(&images_dir).map(|entries| entries.count()).unwrap_or(0);
+
+    sync_templates(&mut connection, config(), &images_dir).unwrap();
+
+    let updated: PhotoTemplate = photo_templates::table.find(existing.id).first(&mut connection).unwrap();
+    let inserted: PhotoTemplate = photo_templates::table
+        .filter(photo_templates::name.eq("Synced image new"))
+        .first(&mut connection)
+        .unwrap();
+    assert!(Path::new(&updated.template_img).starts_with(&images_dir));
+    assert!(Path::new(&inserted.template_img).starts_with(&images_dir));
+    assert_eq!((updated.image_width, updated.image_height), (200, 100));
+    // The previous image lives outside managed storage and is left alone
+    assert!(Path::new(&existing.template_img).exists());
+    assert_eq!(managed_files(), 2);
+
+    // Syncing the same config again changes nothing and copies nothing
+    let report = sync_templates(&mut connection, config(), &images_dir).unwrap();
+    assert_eq!(report.unchanged, ["Synced image existing", "Synced image new"]);
+    assert_eq!(managed_files(), 2);
+
+    // A sync rolled back by a later entry leaves none of its copies behind
+    let failing: TemplatesConfig = serde_json::from_value(serde_json::json!({
+        "templates": [
+            {
+                "name": "Synced image rolled back",
+                "crop_photo": { "x": 0.0, "y": 0.0, "width": 50.0, "height": 50.0 },
+                "template_img": frame_path,
+            },
+            { "name": "Synced image missing", "crop_photo": { "x": 0.0, "y": 0.0, "width": 50.0, "height": 50.0 } },
+        ]
+    }))
+    .unwrap();
+    assert!(sync_templates(&mut connection, failing, &images_dir).is_err());
+    assert_eq!(managed_files(), 2);
+}
+
+#[test]
+fn template_on_top_keeps_the_frame_over_the_photo() {
+    let dir = TestDir::new("template_on_top");
+    // Opaque red frame overlapping the photo crop, with a transparent window in its middle
+    let template = template_with_image(
+        &dir,
+        RgbaImage::from_fn(400, 300, |x, y| {
+            let window = (40..360).contains(&x) && (40..200).contains(&y);
+            if window { Rgba([0, 0, 0, 0]) } else { Rgba([200, 0, 0, 255]) }
+        }),
+    );
+    fs::create_dir_all(dir.join("photos")).unwrap();
+    image::RgbImage::from_pixel(120, 90, image::Rgb([0, 0, 255])).save(dir.join("photos/photo_1.png")).unwrap();
+
+    for template_on_top in [false, true] {
+        let options = GenerationOptions {
+            output_format: OutputFormat::Png,
+            template_on_top,
+            ..Default::default()
+        };
+        let output_dir = dir.join(&format!("output_{}", template_on_top));
+        let run = generate(&template, &dir.join("photos"), &output_dir, &options, &mut RecordingObserver::default())
+            .unwrap();
+
+        let output = image::open(&run.images[0].output).unwrap().to_rgba8();
+        let frame_pixel = if template_on_top { [200, 0, 0] } else { [0, 0, 255] };
+        assert_eq!(output.get_pixel(200, 25).0[..3], frame_pixel, "template_on_top: {}", template_on_top);
+        assert_eq!(output.get_pixel(200, 120).0[..3], [0, 0, 255], "template_on_top: {}", template_on_top);
+    }
+}
+
+#[test]
+fn sixteen_bit_source_keeps_its_depth_in_sixteen_bit_png_outputs() {
+    let dir = TestDir::new("sixteen_bit");
+    let template = test_template(&dir);
+    fs::create_dir_all(dir.join("photos")).unwrap();
+    // 1000 is between two 8-bit levels (771 and 1028), lost by any 8-bit step
+    image::ImageBuffer::from_pixel(120, 90, image::Rgb([1000u16, 20000, 40000]))
+        .save(dir.join("photos/photo_1.png"))
+        .unwrap();
+    let options = GenerationOptions {
+        output_format: OutputFormat::Png,
+        color_type: OutputColorType::Rgb16,
+        filename_caption: Some(caption::FilenameCaptionOptions {
+            caption_box: CropCoordinates { x: 20.0, y: 240.0, width: 100.0, height: 40.0 },
+            transform: Default::default(),
+            color: None,
+        }),
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .unwrap();
+
+    let output = image::open(&run.images[0].output).unwrap();
+    assert_eq!(output.color(), image::ColorType::Rgb16);
+    assert_eq!(output.to_rgb16().get_pixel(200, 120).0, [1000, 20000, 40000]);
+}
+
+#[test]
+fn reset_crops_cover_the_full_template_image() {
+    let dir = TestDir::new("reset_crops");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+
+    let reset = reset_crops_to_full_image(&mut connection, template.id, dir.path()).unwrap();
+
+    assert_eq!(reset.crop_photo, crop(0.0, 0.0, 400.0, 300.0));
+    assert_eq!(reset.crop_number, "");
+}
+
+#[test]
+fn blur_gap_fill_fills_the_slot_corners_from_the_photo() {
+    let dir = TestDir::new("gap_fill_blur");
+    let template = template_with_image(&dir, RgbaImage::from_pixel(400, 300, Rgba([240, 240, 240, 255])));
+    fs::create_dir_all(dir.join("photos")).unwrap();
+    // Much wider than the slot, contained it leaves gaps above and below
+    image::RgbImage::from_pixel(360, 60, image::Rgb([0, 160, 0])).save(dir.join("photos/photo_1.png")).unwrap();
+
+    for gap_fill in [GapFill::None, GapFill::Blur] {
+        let options = GenerationOptions {
+            output_format: OutputFormat::Png,
+            gap_fill,
+            ..Default::default()
+        };
+        let output_dir = dir.join(if gap_fill == GapFill::Blur { "output_blur" } else { "output_none" });
+        let run = generate(&template, &dir.join("photos"), &output_dir, &options, &mut RecordingObserver::default())
+            .unwrap();
+
+        let output = image::open(&run.images[0].output).unwrap().to_rgba8();
+        let expected = if gap_fill == GapFill::Blur { [0, 160, 0] } else { [240, 240, 240] };
+        for (x, y) in [(22, 22), (377, 22), (22, 217), (377, 217)] {
+            assert_eq!(output.get_pixel(x, y).0[..3], expected, "corner {}x{}", x, y);
+        }
+    }
+}
+
+/// Run an async command to completion.
+pub(crate) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+/// Image encoded in a `data:` URL returned to the frontend.
+pub(crate) fn decode_data_url(data_url: &str) -> DynamicImage {
+    use base64::Engine;
+
+    let (_, encoded) = data_url.split_once(";base64,").unwrap();
+    image::load_from_memory(&base64::engine::general_purpose::STANDARD.decode(encoded).unwrap()).unwrap()
+}
+
+#[test]
+fn source_thumbnails_are_small_data_urls_in_processing_order() {
+    let dir = TestDir::new("source_thumbnails");
+    write_photo(&dir.join("photos/photo_2.png"), 1200, 900);
+    write_photo(&dir.join("photos/photo_10.jpg"), 300, 200);
+    write_photo(&dir.join("photos/cover.png"), 40, 40);
+    fs::write(dir.join("photos/notes.txt"), "not a photo").unwrap();
+
+    let thumbnails = block_on(list_source_thumbnails(dir.join("photos").to_string_lossy().to_string(), 2000)).unwrap();
+
+    assert_eq!(thumbnails.len(), 3);
+    let expected = find_image_files(&dir.join("photos").to_string_lossy(), &GenerationOptions::default()).unwrap();
+    let filenames: Vec<_> = thumbnails.iter().map(|thumbnail| thumbnail.filename.as_str()).collect();
+    let expected_filenames: Vec<_> = expected.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(filenames, expected_filenames);
+    let photo_2 = thumbnails.iter().find(|thumbnail| thumbnail.filename == "photo_2.png").unwrap();
+    assert_eq!(photo_2.number, "2");
+    assert!(photo_2.data_url.starts_with("data:image/jpeg;base64,"));
+    let thumbnail = decode_data_url(&photo_2.data_url);
+    assert_eq!((thumbnail.width(), thumbnail.height()), (MAX_THUMBNAIL_DIMENSION, 384));
+}
+
+#[test]
+fn updating_the_image_removes_the_old_file_and_refreshes_the_dimensions() {
+    let dir = TestDir::new("update_template_image");
+    let template = stored_template(&dir);
+    let source = TestDir::new("update_template_image_source");
+    let new_image = source.join("portrait.png");
+    RgbaImage::from_pixel(200, 300, Rgba([10, 20, 30, 255])).save(&new_image).unwrap();
+    let mut connection = establish_connection().unwrap();
+    let mut update_image = |template_img: String| {
+        update_template(
+            &mut connection,
+            dir.path(),
+            template.id,
+            template.name.clone(),
+            template.crop_photo.clone(),
+            template.crop_number.clone(),
+            template_img,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+    };
+
+    let update = update_image(new_image.to_string_lossy().to_string());
+
+    assert!(Path::new(&update.template.template_img).starts_with(dir.path()));
+    assert!(new_image.exists());
+    assert_eq!((update.template.image_width, update.template.image_height), (200, 300));
+    assert!(update.crops_may_be_misaligned);
+    // Kept for reverting until the edit that uses it leaves the history
+    assert!(Path::new(&template.template_img).exists());
+    for _ in 0..MAX_TEMPLATE_EDITS {
+        update_image(update.template.template_img.clone());
+    }
+    assert!(!Path::new(&template.template_img).exists());
+    assert!(Path::new(&update.template.template_img).exists());
+}
+
+#[test]
+fn progress_events_are_throttled_to_the_configured_step() {
+    let dir = TestDir::new("progress_throttle");
+    let template = test_template(&dir);
+    for index in 1..=40 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 16, 12);
+    }
+    let options = GenerationOptions {
+        progress_step_percent: Some(20.0),
+        ..Default::default()
+    };
+
+    let mut observer = RecordingObserver::default();
+    generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut observer).unwrap();
+
+    let percents: Vec<f32> = observer.progress.iter().map(|progress| progress.percent).collect();
+    assert!((5..=7).contains(&percents.len()), "{:?}", percents);
+    assert_eq!(percents.last(), Some(&100.0));
+    for pair in percents[..percents.len() - 1].windows(2) {
+        assert!(pair[1] - pair[0] >= 20.0, "{:?}", percents);
+    }
+}
+
+#[test]
+fn progress_interval_holds_back_events_until_it_elapses() {
+    let mut throttle = ProgressThrottle::new(&GenerationOptions {
+        progress_interval_ms: Some(60_000),
+        ..Default::default()
+    });
+
+    let emitted: Vec<bool> = [1.0, 2.0, 50.0, 99.0, 100.0].into_iter().map(|percent| throttle.should_emit(percent)).collect();
+
+    assert_eq!(emitted, [true, false, false, false, true]);
+}
+
+#[test]
+fn template_default_watermark_applies_without_a_run_override() {
+    let dir = TestDir::new("default_watermark");
+    let plain_template = test_template(&dir);
+    let watermarked_template = PhotoTemplate {
+        default_watermark: r#"{"text":"PROOF","opacity":1.0,"angle":0.0,"color":[255,0,0]}"#.to_string(),
+        ..test_template(&dir)
+    };
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let options = GenerationOptions {
+        output_format: OutputFormat::Png,
+        ..Default::default()
+    };
+    let render = |template: &PhotoTemplate, options: &GenerationOptions, output: &str| {
+        let run = generate(template, &dir.join("photos"), &dir.join(output), options, &mut RecordingObserver::default())
+            .unwrap();
+        image::open(&run.images[0].output).unwrap().to_rgba8()
+    };
+
+    let plain = render(&plain_template, &options, "output_plain");
+    let watermarked = render(&watermarked_template, &options, "output_watermarked");
+
+    assert_ne!(watermarked, plain);
+    assert_eq!(prepare_template(&watermarked_template, &options).unwrap().watermark.unwrap().text, "PROOF");
+    let overridden = GenerationOptions {
+        watermark: Some(WatermarkOptions {
+            text: "SAMPLE".to_string(),
+            ..Default::default()
+        }),
+        ..GenerationOptions::default()
+    };
+    assert_eq!(prepare_template(&watermarked_template, &overridden).unwrap().watermark.unwrap().text, "SAMPLE");
+}
+
+#[test]
+fn include_patterns_select_files_before_exclude_patterns() {
+    let dir = TestDir::new("include_patterns");
+    for name in ["a_final.jpg", "b_final.png", "b_final_old.png", "c_draft.jpg", "final_d.jpg"] {
+        write_photo(&dir.join(&format!("photos/{}", name)), 8, 8);
+    }
+    let options = GenerationOptions {
+        include_patterns: vec!["*_final.*".to_string(), "*_final_*".to_string()],
+        exclude_patterns: vec!["*_old.*".to_string()],
+        ..Default::default()
+    };
+
+    let files = find_image_files(&dir.join("photos").to_string_lossy(), &options).unwrap();
+
+    let names: Vec<_> = files.iter().map(|path| path.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(names, ["a_final.jpg", "b_final.png"]);
+}
+
+/// Names of the entries of a ZIP archive, in order.
+pub(crate) fn archive_entry_names(archive_path: &Path) -> Vec<String> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(archive_path).unwrap()).unwrap();
+    (0..archive.len()).map(|index| archive.by_index(index).unwrap().name().to_string()).collect()
+}
+
+/// `generate` followed by the archiving of `run_generation`.
+pub(crate) fn generate_and_package(
+    template: &PhotoTemplate,
+    photos_dir: &Path,
+    output_dir: &Path,
+    options: &GenerationOptions,
+) -> (GenerationRun, GenerationResult) {
+    let run = generate(template, photos_dir, output_dir, options, &mut RecordingObserver::default()).unwrap();
+    let result = package_run(&run, output_dir, options, &AtomicBool::new(false)).unwrap();
+    (run, result)
+}
+
+#[test]
+fn archive_includes_the_original_of_each_output() {
+    let dir = TestDir::new("include_originals");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    write_photo(&dir.join("photos/photo_2.jpg"), 120, 90);
+    let options = GenerationOptions {
+        include_originals: true,
+        ..Default::default()
+    };
+
+    let (_, result) = generate_and_package(&template, &dir.join("photos"), &dir.join("output"), &options);
+
+    let mut names = archive_entry_names(Path::new(&result.archive_path));
+    names.sort();
+    assert_eq!(
+        names,
+        ["originals/photo_1.png", "originals/photo_2.jpg", "photo_1_processed.jpg", "photo_2_processed.jpg"]
+    );
+}
+
+#[test]
+fn region_color_is_the_mean_of_the_sampled_pixels() {
+    let dir = TestDir::new("region_color");
+    let template = stored_template(&dir);
+    RgbaImage::from_fn(400, 300, |x, _| if x < 200 { Rgba([20, 30, 90, 255]) } else { Rgba([250, 240, 120, 255]) })
+        .save(&template.template_img)
+        .unwrap();
+
+    let dark = sample_region_color(template.id, CropCoordinates { x: 10.0, y: 10.0, width: 100.0, height: 50.0 }).unwrap();
+    let light = sample_region_color(template.id, CropCoordinates { x: 300.0, y: 250.0, width: 200.0, height: 200.0 }).unwrap();
+
+    assert_eq!(dark.color, [20, 30, 90, 255]);
+    assert_eq!(dark.suggested_text_color, "#FFFFFF");
+    assert_eq!(light.color, [250, 240, 120, 255]);
+    assert_eq!(light.suggested_text_color, "#000000");
+    assert!(sample_region_color(template.id, CropCoordinates { x: 500.0, y: 0.0, width: 10.0, height: 10.0 }).is_err());
+}
+
+#[test]
+fn patching_crop_number_leaves_the_other_fields_alone() {
+    let dir = TestDir::new("patch_template");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    let new_crop_number = crop(50.0, 250.0, 300.0, 30.0);
+
+    let patched = patch_template(
+        &mut connection,
+        dir.path(),
+        template.id,
+        PhotoTemplatePatch {
+            crop_number: Some(new_crop_number.clone()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    assert_eq!(patched.crop_number, new_crop_number);
+    assert_eq!(patched.name, template.name);
+    assert_eq!(patched.crop_photo, template.crop_photo);
+    assert_eq!(patched.template_img, template.template_img);
+    assert!(patched.updated_at >= template.updated_at);
+    let error = patch_template(
+        &mut connection,
+        dir.path(),
+        template.id,
+        PhotoTemplatePatch {
+            text_color: Some("not a color".to_string()),
+            ..Default::default()
+        },
+    );
+    assert!(error.is_err());
+}
+
+#[test]
+fn before_after_shows_the_photo_beside_the_result() {
+    let dir = TestDir::new("before_after");
+    let template = stored_template(&dir);
+    write_photo(&dir.join("sample.png"), 120, 90);
+
+    let data_url = block_on(render_before_after(template.id, dir.join("sample.png").to_string_lossy().to_string())).unwrap();
+
+    assert!(data_url.starts_with("data:image/png;base64,"));
+    let comparison = decode_data_url(&data_url).to_rgba8();
+    let divider_width = 4;
+    assert_eq!((comparison.width(), comparison.height()), (400 * 2 + divider_width, 300));
+    let before = image::imageops::crop_imm(&comparison, 0, 0, 400, 300).to_image();
+    let after = image::imageops::crop_imm(&comparison, 400 + divider_width, 0, 400, 300).to_image();
+    assert_ne!(before, after);
+}
+
+/// Write a ZIP holding a small photo under each of `names`.
+pub(crate) fn write_photo_zip(path: &Path, names: &[&str]) {
+    let staging = path.with_extension("staging");
+    let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+    for name in names {
+        let photo = staging.join(name);
+        write_photo(&photo, 60, 40);
+        zip.start_file(*name, zip::write::FileOptions::default()).unwrap();
+        std::io::Write::write_all(&mut zip, &fs::read(&photo).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+    fs::remove_dir_all(staging).unwrap();
+}
+
+#[test]
+fn folder_and_zip_sources_are_merged_in_a_stable_order() {
+    let dir = TestDir::new("mixed_sources");
+    let template = test_template(&dir);
+    for name in ["a_1.png", "c_3.png", "dup_9.png"] {
+        write_photo(&dir.join(&format!("photos/{}", name)), 60, 40);
+    }
+    write_photo_zip(&dir.join("album.zip"), &["b_2.png", "dup_9.png"]);
+    let options = GenerationOptions {
+        sources: vec![dir.join("album.zip").to_string_lossy().to_string()],
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default())
+        .unwrap();
+
+    let outputs: Vec<_> = run.images.iter().map(|image| image.output.file_name().unwrap().to_str().unwrap()).collect();
+    assert_eq!(
+        outputs,
+        [
+            "a_1_processed.jpg",
+            "b_2_processed.jpg",
+            "c_3_processed.jpg",
+            "photos_dup_9_processed.jpg",
+            "album_dup_9_processed.jpg",
+        ]
+    );
+    assert!(run.images[3].sources[0].starts_with(dir.join("photos")));
+}
+
+#[test]
+fn failures_bundle_holds_the_corrupt_input_and_its_reason() {
+    use std::io::Read;
+
+    let dir = TestDir::new("failures_bundle");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 60, 40);
+    fs::write(dir.join("photos/photo_2.jpg"), b"not a jpeg at all").unwrap();
+    let options = GenerationOptions {
+        skip_failed_files: true,
+        collect_failures_bundle: true,
+        ..Default::default()
+    };
+
+    let (_, result) = generate_and_package(&template, &dir.join("photos"), &dir.join("output"), &options);
+
+    let bundle_path = result.failures_bundle_path.unwrap();
+    assert_eq!(archive_entry_names(Path::new(&bundle_path)), ["failures.json", "1_photo_2.jpg"]);
+    let mut archive = zip::ZipArchive::new(fs::File::open(&bundle_path).unwrap()).unwrap();
+    let mut report = String::new();
+    archive.by_name("failures.json").unwrap().read_to_string(&mut report).unwrap();
+    let records: serde_json::Value = serde_json::from_str(&report).unwrap();
+    assert!(records[0]["source"].as_str().unwrap().ends_with("photo_2.jpg"));
+    assert!(records[0]["reason"].as_str().unwrap().contains("Error loading image"), "{}", report);
+    assert!(!dir.join("output/failures.json").exists());
+}
+
+#[test]
+fn half_opacity_photo_blends_with_the_template_underneath() {
+    let dir = TestDir::new("photo_opacity");
+    let template = template_with_image(&dir, RgbaImage::from_pixel(400, 300, Rgba([0, 0, 200, 255])));
+    fs::create_dir_all(dir.join("photos")).unwrap();
+    image::RgbImage::from_pixel(360, 200, image::Rgb([250, 250, 0])).save(dir.join("photos/photo_1.png")).unwrap();
+    let render = |photo_opacity: Option<f32>, output: &str| {
+        let options = GenerationOptions {
+            output_format: OutputFormat::Png,
+            photo_opacity,
+            ..Default::default()
+        };
+        let run = generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default())
+            .unwrap();
+        *image::open(&run.images[0].output).unwrap().to_rgba8().get_pixel(200, 120)
+    };
+
+    let opaque = render(None, "output_opaque");
+    let faded = render(Some(0.5), "output_faded");
+
+    assert_eq!(opaque.0[..3], [250, 250, 0]);
+    for (channel, expected) in faded.0[..3].iter().zip([125, 125, 100]) {
+        assert!(channel.abs_diff(expected) <= 2, "{:?}", faded);
+    }
+}
+
+#[test]
+fn config_hash_changes_only_when_the_template_changes() {
+    let dir = TestDir::new("config_hash");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+
+    let hash = template_config_hash(template.id).unwrap();
+    assert_eq!(hash.len(), 64);
+    assert_eq!(template_config_hash(template.id).unwrap(), hash);
+
+    let crop_number = PhotoTemplatePatch {
+        crop_number: Some(crop(20.0, 250.0, 360.0, 30.0)),
+        ..Default::default()
+    };
+    patch_template(&mut connection, dir.path(), template.id, crop_number).unwrap();
+    let edited_hash = template_config_hash(template.id).unwrap();
+    assert_ne!(edited_hash, hash);
+    assert_eq!(template_config_hash(template.id).unwrap(), edited_hash);
+
+    let font = PhotoTemplatePatch {
+        font_id: Some("dejavu-sans".to_string()),
+        ..Default::default()
+    };
+    patch_template(&mut connection, dir.path(), template.id, font).unwrap();
+    let font_hash = template_config_hash(template.id).unwrap();
+    assert_ne!(font_hash, edited_hash);
+
+    RgbaImage::from_pixel(400, 300, Rgba([1, 2, 3, 255])).save(&template.template_img).unwrap();
+    assert_ne!(template_config_hash(template.id).unwrap(), font_hash);
+}
+
+#[test]
+fn extra_extensions_add_renamed_jpegs_to_the_run() {
+    let dir = TestDir::new("extra_extensions");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.jpg"), 60, 40);
+    write_photo(&dir.join("photo_2.jpg"), 60, 40);
+    fs::rename(dir.join("photo_2.jpg"), dir.join("photos/photo_2.JFIF")).unwrap();
+    let run = |extra_extensions: Vec<String>, output: &str| {
+        let options = GenerationOptions { extra_extensions, ..Default::default() };
+        generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default()).unwrap()
+    };
+
+    let default_run = run(Vec::new(), "output_default");
+    let extended_run = run(vec![".jfif".to_string()], "output_extended");
+
+    assert_eq!(default_run.images.len(), 1);
+    assert_eq!(extended_run.images.len(), 2);
+    assert!(extended_run.failures.is_empty());
+    assert!(extended_run.images[1].sources[0].ends_with("photo_2.JFIF"));
+}
+
+#[test]
+fn reverting_once_after_two_edits_restores_the_intermediate_state() {
+    let dir = TestDir::new("revert_template");
+    let template = stored_template(&dir);
+    let mut connection = establish_connection().unwrap();
+    let edit = |connection: &mut SqliteConnection, name: &str, crop_number: String| {
+        let fields = PhotoTemplatePatch {
+            name: Some(name.to_string()),
+            crop_number: Some(crop_number),
+            ..Default::default()
+        };
+        patch_template(connection, dir.path(), template.id, fields).unwrap()
+    };
+
+    let intermediate = edit(&mut connection, "First edit", crop(20.0, 250.0, 360.0, 30.0));
+    edit(&mut connection, "Second edit", crop(30.0, 260.0, 340.0, 20.0));
+    let reverted = revert_last_edit(&mut connection, template.id, dir.path()).unwrap();
+
+    assert_eq!(reverted.name, intermediate.name);
+    assert_eq!(reverted.crop_number, intermediate.crop_number);
+    assert_eq!(reverted.crop_photo, intermediate.crop_photo);
+    let original = revert_last_edit(&mut connection, template.id, dir.path()).unwrap();
+    assert_eq!((original.name, original.crop_number), (template.name, template.crop_number));
+    assert!(revert_last_edit(&mut connection, template.id, dir.path()).is_err());
+}
+
+#[test]
+fn outputs_are_centered_on_the_print_canvas() {
+    let dir = TestDir::new("print_canvas");
+    let template = template_with_image(&dir, RgbaImage::from_pixel(400, 300, Rgba([0, 0, 200, 255])));
+    write_photo(&dir.join("photos/photo_1.png"), 60, 40);
+    let render = |width_px: u32, height_px: u32, output: &str| {
+        let options = GenerationOptions {
+            output_format: OutputFormat::Png,
+            print_canvas: Some(PrintCanvasOptions { width_px, height_px, margin_px: 10, bg_color: None }),
+            ..Default::default()
+        };
+        let run = generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default())
+            .unwrap();
+        image::open(&run.images[0].output).unwrap().to_rgba8()
+    };
+    let is_template = |pixel: &Rgba<u8>| pixel.0 == [0, 0, 200, 255];
+    let is_background = |pixel: &Rgba<u8>| pixel.0 == [255, 255, 255, 255];
+
+    let larger = render(600, 500, "output_larger");
+    assert_eq!(larger.dimensions(), (600, 500));
+    assert!(is_template(larger.get_pixel(100, 100)) && is_template(larger.get_pixel(499, 399)));
+    assert!(is_background(larger.get_pixel(99, 100)) && is_background(larger.get_pixel(500, 399)));
+
+    // Scaled down to the 280x280 area inside the margins, keeping the template's ratio
+    let smaller = render(300, 300, "output_smaller");
+    assert_eq!(smaller.dimensions(), (300, 300));
+    assert!(is_template(smaller.get_pixel(10, 45)) && is_template(smaller.get_pixel(289, 254)));
+    assert!(is_background(smaller.get_pixel(9, 150)) && is_background(smaller.get_pixel(150, 44)));
+}
+
+#[test]
+fn queued_jobs_run_one_after_the_other() {
+    let state = std::sync::Arc::new(GenerationState::default());
+    let first = state.enqueue(1, "first");
+    let second = state.enqueue(2, "second");
+    let steps = std::sync::Arc::new(Mutex::new(Vec::new()));
+    let spawn_job = |job_id: u64| {
+        let (state, steps) = (state.clone(), steps.clone());
+        async move {
+            state
+                .run_job(job_id, |_| {}, |job| async move {
+                    steps.lock().unwrap().push(format!("start {}", job.image_folder_path));
+                    // Gives the other job every chance to start meanwhile
+                    for _ in 0..10 {
+                        tokio::task::yield_now().await;
+                    }
+                    steps.lock().unwrap().push(format!("end {}", job.image_folder_path));
+                    Ok(GenerationResult {
+                        archive_path: job.image_folder_path,
+                        archive_paths: Vec::new(),
+                        failures_bundle_path: None,
+                        thumbnail_path: None,
+                    })
+                })
+                .await
+        }
+    };
+
+    let (first_result, second_result) = block_on(async {
+        let first_task = tokio::spawn(spawn_job(first.id));
+        let second_task = tokio::spawn(spawn_job(second.id));
+        (first_task.await.unwrap(), second_task.await.unwrap())
+    });
+
+    assert_eq!(*steps.lock().unwrap(), ["start first", "end first", "start second", "end second"]);
+    assert_eq!(first_result.unwrap().archive_path, "first");
+    assert_eq!(second_result.unwrap().archive_path, "second");
+    let statuses: Vec<JobStatus> = state.all_jobs().iter().map(|job| job.status).collect();
+    assert!(statuses == [JobStatus::Completed, JobStatus::Completed]);
+}
+
+#[test]
+fn crop_json_numbers_sent_as_strings_are_coerced() {
+    let normalized = normalize_crop_json(r#"{"X": "20", "y": 30.5, "Width": " 360 ", "HEIGHT": "200", "label": "photo"}"#.to_string())
+        .unwrap();
+
+    assert_eq!(normalized, r#"{"x":20.0,"y":30.5,"width":360.0,"height":200.0}"#);
+    assert_eq!(normalize_crop_json(normalized.clone()).unwrap(), normalized);
+}
+
+#[test]
+fn crop_json_with_negative_or_missing_dimensions_is_rejected() {
+    let negative = normalize_crop_json(r#"{"x": 0, "y": 0, "width": -10, "height": 20}"#.to_string()).unwrap_err();
+    assert!(negative.contains("must not be negative"), "{}", negative);
+
+    let missing = normalize_crop_json(r#"{"x": 0, "y": 0, "width": 10}"#.to_string()).unwrap_err();
+    assert!(missing.contains("\"height\""), "{}", missing);
+    assert!(normalize_crop_json(r#"{"x": "left", "y": 0, "width": 10, "height": 10}"#.to_string()).is_err());
+    assert!(normalize_crop_json("[1, 2, 3, 4]".to_string()).is_err());
+}
+
+#[test]
+fn self_test_passes_on_a_healthy_build() {
+    let report = run_self_test();
+
+    let failed: Vec<_> = report.steps.iter().filter(|step| !step.passed).map(|step| (&step.name, &step.error)).collect();
+    assert!(report.passed, "{:?}", failed);
+    assert!(report.steps.len() > 1);
+}
+
+#[test]
+fn digitless_file_names_follow_the_no_number_behavior() {
+    let dir = TestDir::new("no_number_behavior");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/beach.png"), 60, 40);
+    let run = |no_number_behavior: NoNumberBehavior, output: &str| {
+        let options = GenerationOptions { no_number_behavior, ..Default::default() };
+        generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default())
+    };
+
+    assert_eq!(run(NoNumberBehavior::Sequence, "output_sequence").unwrap().images[0].number, "1");
+    let blank = run(NoNumberBehavior::Blank, "output_blank").unwrap();
+    assert_eq!(blank.images[0].number, "");
+    let sequence_output = image::open(dir.join("output_sequence/beach_processed.jpg")).unwrap();
+    let blank_output = image::open(&blank.images[0].output).unwrap();
+    assert_ne!(sequence_output.to_rgb8(), blank_output.to_rgb8());
+    let error = run(NoNumberBehavior::Error, "output_error").err().unwrap();
+    assert!(error.contains("No number found in file name \"beach\""), "{}", error);
+}
+
+#[test]
+fn generating_with_a_preset_applies_its_quality() {
+    test_database();
+    let dir = TestDir::new("generation_preset");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 360, 200);
+    save_generation_preset("Low quality proofs".to_string(), serde_json::json!({ "jpeg_quality": 10 })).unwrap();
+    assert!(list_generation_presets().unwrap().iter().any(|preset| preset.name == "Low quality proofs"));
+    let render = |options: GenerationOptions, output: &str| {
+        let run = generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default())
+            .unwrap();
+        fs::metadata(&run.images[0].output).unwrap().len()
+    };
+
+    let preset_options = generation_request_options(Some("Low quality proofs"), None, None, None).unwrap();
+    assert_eq!(preset_options.jpeg_quality.0, 10);
+    let overridden =
+        generation_request_options(Some("Low quality proofs"), Some(serde_json::json!({ "jpeg_quality": 95 })), None, None)
+            .unwrap();
+    assert_eq!(overridden.jpeg_quality.0, 95);
+    assert!(render(preset_options, "output_preset") < render(overridden, "output_overridden"));
+    assert!(save_generation_preset("Broken".to_string(), serde_json::json!({ "jpeg_quality": 0 })).is_err());
+}
+
+#[test]
+fn archive_verification_detects_corrupted_entries() {
+    let dir = TestDir::new("verify_archive");
+    write_photo(&dir.join("photo_1.png"), 60, 40);
+    fs::write(dir.join("notes.jpg"), b"not an image").unwrap();
+    let archive_path = dir.join("outputs.zip");
+    let stored = ArchiveCompression { method: ArchiveCompressionMethod::Stored, level: None };
+    let entries = vec![(dir.join("photo_1.png"), "photo_1.png".to_string()), (dir.join("notes.jpg"), "notes.jpg".to_string())];
+    create_archive(entries, &archive_path, DuplicateEntryPolicy::Error, false, stored, &AtomicBool::new(false)).unwrap();
+    let path = archive_path.to_string_lossy().to_string();
+
+    let clean = verify_archive(path.clone(), None).unwrap();
+    assert!(clean.valid);
+    assert_eq!(clean.entry_count, 2);
+    let decoded = verify_archive(path.clone(), Some(true)).unwrap();
+    assert_eq!(decoded.bad_entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>(), ["notes.jpg"]);
+
+    // Flip a byte of the stored PNG data, past its signature
+    let mut bytes = fs::read(&archive_path).unwrap();
+    let png_start = bytes.windows(4).position(|window| window == b"\x89PNG").unwrap();
+    bytes[png_start + 40] ^= 0xFF;
+    fs::write(&archive_path, bytes).unwrap();
+
+    let corrupted = verify_archive(path, None).unwrap();
+    assert!(!corrupted.valid);
+    assert_eq!(corrupted.bad_entries[0].name, "photo_1.png");
+    assert!(corrupted.bad_entries[0].error.contains("Error decompressing entry"), "{}", corrupted.bad_entries[0].error);
+}
+
+#[test]
+fn rotated_photo_leaves_the_slot_edges_but_stays_inside_the_slot() {
+    let photo = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(200, 100, image::Rgb([200, 0, 0])));
+    let slot = |photo_rotation_degrees: f32| {
+        let options = GenerationOptions {
+            fit_mode: FitMode::Cover,
+            photo_rotation_degrees,
+            ..Default::default()
+        };
+        prepare_slot_image(photo.clone(), 200, 100, &options).to_rgba8()
+    };
+    let is_photo = |pixel: &Rgba<u8>| pixel.0 == [200, 0, 0, 255];
+
+    let straight = slot(0.0);
+    assert!([(0, 0), (199, 0), (0, 99), (199, 99)].iter().all(|&(x, y)| is_photo(straight.get_pixel(x, y))));
+
+    let tilted = slot(10.0);
+    assert_eq!(tilted.dimensions(), (200, 100));
+    assert!(is_photo(tilted.get_pixel(100, 50)));
+    // The corners of the slot are no longer covered, nothing but the photo was drawn
+    assert!([(0, 0), (199, 0), (0, 99), (199, 99)].iter().all(|&(x, y)| tilted.get_pixel(x, y)[3] == 0));
+    assert!(tilted.pixels().all(|pixel| pixel[3] == 0 || (pixel[0] > 0 && pixel[1] == 0 && pixel[2] == 0)));
+}
+
+#[test]
+fn seven_photos_in_three_slot_outputs_make_three_pages() {
+    let dir = TestDir::new("estimate_pages");
+    let template = stored_template(&dir);
+    for index in 1..=7 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 8, 8);
+    }
+    let folder = dir.join("photos").to_string_lossy().to_string();
+    let three_slots = GenerationOptions {
+        mosaic: Some(MosaicOptions { columns: Some(3), rows: Some(1) }),
+        ..Default::default()
+    };
+
+    let estimate = estimate_pages(template.id, folder.clone(), Some(three_slots)).unwrap();
+
+    assert_eq!((estimate.image_count, estimate.photos_per_page, estimate.pages), (7, 3, 3));
+    assert_eq!(estimate_pages(template.id, folder, None).unwrap().pages, 7);
+}
+
+#[test]
+fn duplicate_archive_entry_names_are_renamed_or_refused() {
+    let dir = TestDir::new("duplicate_entries");
+    write_photo(&dir.join("2023/photo.png"), 8, 8);
+    write_photo(&dir.join("2024/photo.png"), 8, 8);
+    write_photo(&dir.join("2025/PHOTO.png"), 8, 8);
+    let entries = || {
+        ["2023/photo.png", "2024/photo.png", "2025/PHOTO.png"]
+            .iter()
+            .map(|path| (dir.join(path), archive_entry_name(&dir.join(path))))
+            .collect::<Vec<_>>()
+    };
+    let archive = |policy: DuplicateEntryPolicy, name: &str| {
+        create_archive(entries(), &dir.join(name), policy, false, ArchiveCompression::default(), &AtomicBool::new(false))
+    };
+
+    archive(DuplicateEntryPolicy::Rename, "renamed.zip").unwrap();
+    assert_eq!(archive_entry_names(&dir.join("renamed.zip")), ["photo.png", "photo_2.png", "PHOTO_3.png"]);
+    assert!(verify_archive(dir.join("renamed.zip").to_string_lossy().to_string(), Some(true)).unwrap().valid);
+
+    let error = archive(DuplicateEntryPolicy::Error, "refused.zip").unwrap_err();
+    assert!(error.contains("Duplicate archive entry name: photo.png"), "{}", error);
+    assert!(!dir.join("refused.zip").exists());
+    assert!(!dir.join("refused.zip.part").exists());
+}
+
+#[test]
+fn top_crop_focus_keeps_the_upper_part_of_a_portrait() {
+    // Upper third red like a face, the rest blue
+    let portrait = DynamicImage::ImageRgb8(image::RgbImage::from_fn(100, 300, |_, y| {
+        if y < 100 { image::Rgb([200, 0, 0]) } else { image::Rgb([0, 0, 200]) }
+    }));
+    let slot = |crop_focus: CropFocus| {
+        let options = GenerationOptions {
+            fit_mode: FitMode::Cover,
+            crop_focus,
+            ..Default::default()
+        };
+        prepare_slot_image(portrait.clone(), 100, 100, &options).to_rgb8()
+    };
+
+    let top = slot(CropFocus { x: 0.5, y: 0.0 });
+    assert!(top.pixels().all(|pixel| pixel.0 == [200, 0, 0]));
+
+    let centered = slot(CropFocus::default());
+    assert!(centered.pixels().all(|pixel| pixel.0 == [0, 0, 200]));
+}
+
+#[test]
+fn scanning_a_large_folder_reports_periodic_counts() {
+    let dir = TestDir::new("scan_progress");
+    for index in 1..=3 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 4, 4);
+    }
+    for index in 0..1197 {
+        fs::write(dir.join(&format!("photos/notes_{}.txt", index)), "").unwrap();
+    }
+    let mut events = Vec::new();
+
+    let files = scan_image_files(
+        &dir.join("photos").to_string_lossy(),
+        &GenerationOptions::default(),
+        |scan| events.push((scan.scanned, scan.done)),
+        &mut Vec::new(),
+    )
+    .unwrap();
+
+    assert_eq!(files.len(), 3);
+    assert_eq!(events, [(SCAN_PROGRESS_EVERY, false), (2 * SCAN_PROGRESS_EVERY, false), (1200, true)]);
+}
+
+#[test]
+fn number_filter_keeps_the_number_off_unmatched_files() {
+    let dir = TestDir::new("number_filter");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/print_1.png"), 60, 40);
+    write_photo(&dir.join("photos/clean_2.png"), 60, 40);
+    let options = GenerationOptions {
+        number_filter: Some("print_*".to_string()),
+        ..Default::default()
+    };
+
+    let run = generate(&template, &dir.join("photos"), &dir.join("output"), &options, &mut RecordingObserver::default()).unwrap();
+
+    // The number area of the template is plain gray, anything else there is the number
+    let has_number = |source: &str| {
+        let image = run.images.iter().find(|image| image.sources[0].ends_with(source)).unwrap();
+        let output = image::open(&image.output).unwrap().to_rgba8();
+        (240..280).any(|y| (20..380).any(|x| output.get_pixel(x, y).0 != [240, 240, 240, 255]))
+    };
+    assert_eq!(run.images.len(), 2);
+    assert!(has_number("print_1.png"));
+    assert!(!has_number("clean_2.png"));
+}
+
+#[test]
+fn importing_a_folder_copies_every_image_into_the_workspace() {
+    let dir = TestDir::new("import_sources");
+    write_photo(&dir.join("usb/photo_1.png"), 8, 8);
+    write_photo(&dir.join("usb/photo_2.jpg"), 8, 8);
+    fs::write(dir.join("usb/notes.txt"), "not a photo").unwrap();
+    let image_files = find_image_files(&dir.join("usb").to_string_lossy(), &GenerationOptions::default()).unwrap();
+    let workspace = dir.join("app_data/imported/import_1");
+    let mut events = Vec::new();
+
+    copy_source_files(&image_files, &workspace, |progress| events.push((progress.copied, progress.total))).unwrap();
+
+    let mut copied: Vec<String> = fs::read_dir(&workspace)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    copied.sort();
+    assert_eq!(copied, ["photo_1.png", "photo_2.jpg"]);
+    assert_eq!(fs::read(workspace.join("photo_1.png")).unwrap(), fs::read(dir.join("usb/photo_1.png")).unwrap());
+    assert_eq!(events, [(1, 2), (2, 2)]);
+}
+
+#[test]
+fn deterministic_runs_produce_byte_identical_files() {
+    let dir = TestDir::new("deterministic");
+    let template = test_template(&dir);
+    for index in 1..=3 {
+        write_photo(&dir.join(&format!("photos/photo_{}.jpg", index)), 60, 40);
+    }
+    let options = GenerationOptions {
+        deterministic: true,
+        ..Default::default()
+    };
+    let run = |name: &str| {
+        let output_dir = dir.join(name);
+        let (run, result) = generate_and_package(&template, &dir.join("photos"), &output_dir, &options);
+        let outputs: Vec<(String, Vec<u8>)> = run
+            .images
+            .iter()
+            .map(|image| (image.output.file_name().unwrap().to_string_lossy().to_string(), fs::read(&image.output).unwrap()))
+            .collect();
+        (outputs, fs::read(&result.archive_path).unwrap())
+    };
+
+    let (first_outputs, first_archive) = run("first");
+    // Archive entries would otherwise get a different modification time
+    std::thread::sleep(std::time::Duration::from_millis(2100));
+    let (second_outputs, second_archive) = run("second");
+
+    assert_eq!(first_outputs.len(), 3);
+    assert!(first_outputs == second_outputs);
+    assert!(first_archive == second_archive);
+}
+
+#[test]
+fn template_region_is_the_requested_rectangle_scaled_down() {
+    let dir = TestDir::new("template_region");
+    let template = stored_template(&dir);
+    // Each pixel encodes its own position, so the region shows where it was taken from
+    image::RgbImage::from_fn(400, 300, |x, y| image::Rgb([(x / 2) as u8, (y / 2) as u8, 0]))
+        .save(&template.template_img)
+        .unwrap();
+
+    let full = block_on(render_template_region(template.id, 100, 50, 80, 40, 200)).unwrap();
+    assert_eq!(full.scale, 1.0);
+    let region = decode_data_url(&full.data_url).to_rgb8();
+    assert_eq!(region.dimensions(), (80, 40));
+    assert_eq!(region.get_pixel(0, 0).0, [50, 25, 0]);
+    assert_eq!(region.get_pixel(79, 39).0, [89, 44, 0]);
+
+    let scaled = block_on(render_template_region(template.id, 100, 50, 80, 40, 40)).unwrap();
+    assert_eq!(scaled.scale, 0.5);
+    let region = decode_data_url(&scaled.data_url).to_rgb8();
+    assert_eq!(region.dimensions(), (40, 20));
+    let [red, green, _] = region.get_pixel(20, 10).0;
+    assert!((69..=71).contains(&red) && (34..=36).contains(&green), "{:?}", (red, green));
+
+    assert!(block_on(render_template_region(template.id, 350, 0, 80, 40, 200)).is_err());
+}
+
+#[test]
+fn unwritable_app_data_fails_early_or_uses_the_fallback() {
+    let dir = TestDir::new("unwritable_output");
+    // A file where the outputs directory should be, it can't be created even with elevated rights
+    let app_data_dir = dir.join("app_data");
+    fs::create_dir_all(&app_data_dir).unwrap();
+    fs::write(app_data_dir.join(GENERATED_IMAGES_DIR), "").unwrap();
+
+    let template = test_template(&dir);
+    let error = run_output_dir(&app_data_dir, &template, "run_1", &GenerationOptions::default()).unwrap_err();
+    assert!(error.contains("is not writable"), "{}", error);
+    assert!(error.contains("set a fallback output directory"), "{}", error);
+
+    let fallback = GenerationOptions {
+        fallback_output_dir: Some(dir.join("fallback").to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    assert_eq!(run_output_dir(&app_data_dir, &template, "run_1", &fallback).unwrap(), dir.join("fallback/run_1"));
+    assert!(dir.join("fallback/run_1").is_dir());
+
+    // A folder chosen for the run is never swapped for the fallback
+    let chosen = GenerationOptions {
+        output_folder: Some(app_data_dir.join(GENERATED_IMAGES_DIR).to_string_lossy().to_string()),
+        ..fallback
+    };
+    assert!(run_output_dir(&app_data_dir, &template, "run_2", &chosen).unwrap_err().contains("is not writable"));
+    assert!(!dir.join("fallback/run_2").exists());
+}
+
+#[test]
+fn bulk_image_swap_shares_one_copy_and_cleans_up_the_old_image() {
+    let dir = TestDir::new("bulk_template_image");
+    let templates: Vec<PhotoTemplate> = (0..3).map(|_| stored_template(&dir)).collect();
+    let ids: Vec<i32> = templates.iter().map(|template| template.id).collect();
+    let old_image = PathBuf::from(&templates[0].template_img);
+    let source = TestDir::new("bulk_template_image_source");
+    let new_image = source.join("rebrand.png");
+    RgbaImage::from_pixel(200, 300, Rgba([10, 20, 30, 255])).save(&new_image).unwrap();
+    let mut connection = establish_connection().unwrap();
+
+    let updated = set_templates_image(&mut connection, dir.path(), &ids, new_image.to_string_lossy().to_string()).unwrap();
+
+    let shared = &updated[0].template_img;
+    assert!(Path::new(shared).starts_with(dir.path()));
+    assert_eq!(updated.iter().map(|template| template.id).collect::<Vec<_>>(), ids);
+    assert!(updated.iter().all(|template| &template.template_img == shared));
+    assert!(updated.iter().all(|template| (template.image_width, template.image_height) == (200, 300)));
+    // Kept for reverting until the edits that use it leave the history
+    assert!(old_image.exists());
+    for _ in 0..MAX_TEMPLATE_EDITS {
+        set_templates_image(&mut connection, dir.path(), &ids, shared.clone()).unwrap();
+    }
+    assert!(!old_image.exists());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    assert!(set_templates_image(&mut connection, dir.path(), &ids, dir.join("missing.png").to_string_lossy().to_string()).is_err());
+}
+
+#[test]
+fn swapping_to_a_differently_shaped_image_flags_the_crops() {
+    let dir = TestDir::new("aspect_ratio_change");
+    let template = stored_template(&dir);
+    let source = TestDir::new("aspect_ratio_change_source");
+    let mut connection = establish_connection().unwrap();
+    let mut swap_to = |name: &str, width: u32, height: u32| {
+        let image_path = source.join(name);
+        RgbaImage::from_pixel(width, height, Rgba([10, 20, 30, 255])).save(&image_path).unwrap();
+        update_template(
+            &mut connection,
+            dir.path(),
+            template.id,
+            template.name.clone(),
+            template.crop_photo.clone(),
+            template.crop_number.clone(),
+            image_path.to_string_lossy().to_string(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap()
+        .crops_may_be_misaligned
+    };
+
+    // 400x300 to twice the size, same shape
+    assert!(!swap_to("larger.png", 800, 600));
+    assert!(swap_to("square.png", 600, 600));
+}
+
+#[test]
+fn proof_pdf_of_five_photos_in_a_two_by_two_grid_has_two_pages() {
+    let dir = TestDir::new("proof_pdf");
+    for index in 1..=5 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 60, 40);
+    }
+    let folder = dir.join("photos").to_string_lossy().to_string();
+
+    let pdf_path = write_proof_pdf(None, &folder, 2, 2, &dir.join("proofs")).unwrap();
+
+    let pdf = fs::read(&pdf_path).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(pdf_path.starts_with(dir.join("proofs")));
+    assert!(text.starts_with("%PDF"));
+    assert_eq!(text.matches("/Type /Page ").count(), 2);
+    assert!(text.contains("/Count 2"));
+    assert!(text.contains("(photo_5.png)"));
+    assert!(write_proof_pdf(None, &folder, 0, 2, &dir.join("proofs")).is_err());
+}
+
+#[test]
+fn templates_sharing_an_image_are_both_reported() {
+    let dir = TestDir::new("templates_using_image");
+    let first = stored_template(&dir);
+    let second = stored_template(&dir);
+    let other_dir = TestDir::new("templates_using_image_other");
+    let other = stored_template(&other_dir);
+    // Spelled differently from the stored path
+    let path = dir.path().join("..").join(dir.path().file_name().unwrap()).join("template.png");
+
+    let references = templates_using_image(path.to_string_lossy().to_string()).unwrap();
+
+    let ids: Vec<i32> = references.iter().map(|reference| reference.id).collect();
+    assert_eq!(ids, [first.id, second.id]);
+    assert!(!ids.contains(&other.id));
+    assert_eq!(references[0].name, first.name);
+}
+
+#[test]
+fn memory_estimate_grows_with_the_thread_count() {
+    let dir = TestDir::new("estimate_memory");
+    let template = stored_template(&dir);
+    write_photo(&dir.join("photos/small.png"), 100, 50);
+    write_photo(&dir.join("photos/large.png"), 500, 400);
+    let folder = dir.join("photos").to_string_lossy().to_string();
+
+    let one_thread = estimate_memory(template.id, folder.clone(), Some(1)).unwrap();
+    let four_threads = estimate_memory(template.id, folder, Some(4)).unwrap();
+
+    // One 400x300 RGBA template per thread on top of the largest source
+    assert_eq!(one_thread.estimated_bytes, 400 * 300 * 4 + 500 * 400 * 4);
+    assert_eq!(four_threads.estimated_bytes - one_thread.estimated_bytes, 3 * 400 * 300 * 4);
+    assert_eq!(four_threads.threads, 4);
+    assert_eq!(four_threads.warning.is_some(), four_threads.estimated_bytes > four_threads.available_bytes);
+}
+
+#[test]
+fn template_default_output_folder_receives_the_outputs() {
+    let dir = TestDir::new("default_output_folder");
+    test_database();
+    let template = test_template(&dir);
+    let delivery = dir.join("clients/anna");
+    let template = add_photo_template(
+        template.name,
+        template.crop_photo,
+        template.crop_number,
+        template.template_img,
+        None,
+        None,
+        None,
+        None,
+        None,
+        Some(delivery.to_string_lossy().to_string()),
+        None,
+        None,
+    )
+    .unwrap();
+    write_photo(&dir.join("photos/photo_1.png"), 60, 40);
+    let app_data_dir = dir.join("app_data");
+
+    let output_dir = run_output_dir(&app_data_dir, &template, "run_1", &GenerationOptions::default()).unwrap();
+    let run = generate(&template, &dir.join("photos"), &output_dir, &GenerationOptions::default(), &mut RecordingObserver::default()).unwrap();
+
+    assert_eq!(output_dir, delivery.join("run_1"));
+    assert!(run.images[0].output.starts_with(&delivery) && run.images[0].output.exists());
+    assert!(!app_data_dir.exists());
+    // A folder picked for the run wins
+    let picked = GenerationOptions {
+        output_folder: Some(dir.join("picked").to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    assert_eq!(run_output_dir(&app_data_dir, &template, "run_2", &picked).unwrap(), dir.join("picked/run_2"));
+}
+
+#[test]
+fn hash_manifest_holds_the_sha256_of_each_source() {
+    let dir = TestDir::new("hash_manifest");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 60, 40);
+    write_photo(&dir.join("photos/photo_2.png"), 40, 60);
+    let options = GenerationOptions {
+        write_hash_manifest: true,
+        ..Default::default()
+    };
+
+    let (_, result) = generate_and_package(&template, &dir.join("photos"), &dir.join("output"), &options);
+
+    let manifest: Vec<serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(dir.join("output/hash_manifest.json")).unwrap()).unwrap();
+    assert_eq!(manifest.len(), 2);
+    for record in &manifest {
+        let source = PathBuf::from(record["source"].as_str().unwrap());
+        let expected: String = Sha256::digest(fs::read(&source).unwrap()).iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(record["sha256"], expected.as_str());
+        assert!(dir.join("output").join(record["output"].as_str().unwrap()).exists());
+    }
+    let numbers: Vec<&str> = manifest.iter().map(|record| record["number"].as_str().unwrap()).collect();
+    assert_eq!(numbers, ["1", "2"]);
+    assert!(archive_entry_names(Path::new(&result.archive_path)).contains(&"hash_manifest.json".to_string()));
+}
+
+#[test]
+fn cover_and_contain_previews_differ_for_a_wide_photo() {
+    let dir = TestDir::new("preview_fit");
+    let template = stored_template(&dir);
+    // Much wider than the 360x200 slot
+    let wide = dir.join("wide.png");
+    image::RgbImage::from_pixel(900, 100, image::Rgb([200, 0, 0])).save(&wide).unwrap();
+    let preview = |fit: FitMode| {
+        decode_data_url(&block_on(preview_fit(template.id, 0, wide.to_string_lossy().to_string(), fit, None, None)).unwrap())
+            .to_rgba8()
+    };
+
+    let cover = preview(FitMode::Cover);
+    let contain = preview(FitMode::Contain);
+
+    assert_eq!(cover.dimensions(), (360, 200));
+    assert_eq!(contain.dimensions(), (360, 200));
+    assert!(cover.pixels().all(|pixel| pixel.0 == [200, 0, 0, 255]));
+    // Letterboxed: the template shows above and below the photo
+    assert_eq!(contain.get_pixel(180, 100).0, [200, 0, 0, 255]);
+    assert_ne!(contain.get_pixel(180, 5).0, [200, 0, 0, 255]);
+    assert!(block_on(preview_fit(template.id, 1, wide.to_string_lossy().to_string(), FitMode::Cover, None, None)).is_err());
+}
+
+#[test]
+fn number_rules_format_each_range_of_outputs() {
+    let dir = TestDir::new("number_rules");
+    for index in 1..=4 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 60, 40);
+    }
+    let rule = |from: usize, to: usize, format: &str| NumberRule { from, to, format: format.to_string(), padding: Some(2) };
+    let options = GenerationOptions {
+        output_format: OutputFormat::Png,
+        number_rules: vec![rule(1, 2, "A{n}"), rule(3, 4, "B{n}")],
+        ..Default::default()
+    };
+    // Outputs of a run without rules, the template formatting every number as `number_format`
+    let outputs = |number_format: &str, options: &GenerationOptions, name: &str| {
+        let template = PhotoTemplate {
+            number_format: number_format.to_string(),
+            number_padding: 2,
+            ..test_template(&dir)
+        };
+        let run = generate(&template, &dir.join("photos"), &dir.join(name), options, &mut RecordingObserver::default()).unwrap();
+        run.images.iter().map(|image| image::open(&image.output).unwrap().to_rgba8()).collect::<Vec<_>>()
+    };
+    let plain = GenerationOptions {
+        output_format: OutputFormat::Png,
+        ..Default::default()
+    };
+
+    let ruled = outputs(DEFAULT_NUMBER_FORMAT, &options, "ruled");
+    let formatted_a = outputs("A{n}", &plain, "formatted_a");
+    let formatted_b = outputs("B{n}", &plain, "formatted_b");
+
+    assert_eq!(ruled[..2], formatted_a[..2]);
+    assert_eq!(ruled[2..], formatted_b[2..]);
+    assert_ne!(ruled[0], formatted_b[0]);
+}
+
+#[test]
+fn app_data_snapshot_counts_the_files_of_each_directory() {
+    let dir = TestDir::new("snapshot_app_data");
+    fs::create_dir_all(dir.join(TEMPLATE_IMAGES_DIR)).unwrap();
+    fs::write(dir.join(&format!("{}/frame.png", TEMPLATE_IMAGES_DIR)), [0u8; 10]).unwrap();
+    fs::write(dir.join(&format!("{}/banner.png", TEMPLATE_IMAGES_DIR)), [0u8; 20]).unwrap();
+    fs::create_dir_all(dir.join(&format!("{}/run_1/previews", GENERATED_IMAGES_DIR))).unwrap();
+    for name in ["photo_1.jpg", "photo_2.jpg", "previews/photo_1.jpg"] {
+        fs::write(dir.join(&format!("{}/run_1/{}", GENERATED_IMAGES_DIR, name)), [0u8; 5]).unwrap();
+    }
+    fs::write(dir.join(DATABASE_FILENAME), [0u8; 100]).unwrap();
+
+    let snapshot = snapshot_directory(dir.path()).unwrap();
+
+    let entry = |path: &str| snapshot.entries.iter().find(|entry| entry.path == path).unwrap();
+    assert_eq!((entry(TEMPLATE_IMAGES_DIR).file_count, entry(TEMPLATE_IMAGES_DIR).size), (2, 30));
+    assert_eq!(entry(GENERATED_IMAGES_DIR).file_count, 3);
+    assert_eq!(entry(&format!("{}/run_1", GENERATED_IMAGES_DIR)).file_count, 3);
+    assert!(entry(DATABASE_FILENAME).file_count == 1 && !entry(DATABASE_FILENAME).is_dir);
+    // Deeper than `SNAPSHOT_DEPTH`, only counted in the directories above
+    assert!(!snapshot.entries.iter().any(|entry| entry.path.ends_with("previews")));
+    assert_eq!((snapshot.total_files, snapshot.total_size), (6, 145));
+    assert_eq!(snapshot.missing, [IMPORTED_SOURCES_DIR, PROOFS_DIR]);
+}
+
+#[test]
+fn seven_outputs_in_chunks_of_three_make_three_archives() {
+    let dir = TestDir::new("chunked_archives");
+    let template = test_template(&dir);
+    for index in 1..=7 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 30, 20);
+    }
+    let options = GenerationOptions {
+        chunk_size: NonZeroUsize::new(3),
+        ..Default::default()
+    };
+
+    let (run, result) = generate_and_package(&template, &dir.join("photos"), &dir.join("output"), &options);
+
+    let names: Vec<String> = result
+        .archive_paths
+        .iter()
+        .map(|path| Path::new(path).file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, ["generated_images_part1.zip", "generated_images_part2.zip", "generated_images_part3.zip"]);
+    assert_eq!(result.archive_path, result.archive_paths[0]);
+    let entries: Vec<Vec<String>> = result.archive_paths.iter().map(|path| archive_entry_names(Path::new(path))).collect();
+    assert_eq!(entries.iter().map(Vec::len).collect::<Vec<_>>(), [3, 3, 1]);
+    // Chunks follow the output order
+    let outputs: Vec<String> = run.images.iter().map(|image| output_entry_name(&image.output, &dir.join("output"))).collect();
+    assert_eq!(entries.concat(), outputs);
+}
+
+#[test]
+fn preview_watermark_leaves_the_full_res_outputs_clean() {
+    let dir = TestDir::new("watermark_previews");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let outputs = |watermark: Option<WatermarkOptions>, name: &str| {
+        let options = GenerationOptions {
+            output_format: OutputFormat::Png,
+            preview_max_dimension: Some(200),
+            watermark,
+            ..Default::default()
+        };
+        let run = generate(&template, &dir.join("photos"), &dir.join(name), &options, &mut RecordingObserver::default()).unwrap();
+        let image = &run.images[0];
+        let open = |path: &Path| image::open(path).unwrap().to_rgba8();
+        (open(&image.output), open(image.preview.as_ref().unwrap()))
+    };
+    let proof = WatermarkOptions {
+        text: "PROOF".to_string(),
+        opacity: 1.0,
+        color: [255, 0, 0],
+        target: watermark::WatermarkTarget::Previews,
+        ..Default::default()
+    };
+
+    let (clean_fullres, clean_preview) = outputs(None, "clean");
+    let (fullres, preview) = outputs(Some(proof), "watermarked");
+
+    assert_eq!(fullres, clean_fullres);
+    assert_eq!(preview.dimensions(), clean_preview.dimensions());
+    assert_ne!(preview, clean_preview);
+    assert!(preview.pixels().any(|pixel| pixel[0] > 200 && pixel[1] < 50 && pixel[2] < 50));
+}
+
+#[test]
+fn transparent_template_areas_turn_white_in_jpeg_outputs() {
+    let dir = TestDir::new("transparent_jpeg");
+    // Rounded frame: the corners of the template are transparent
+    let template = template_with_image(
+        &dir,
+        RgbaImage::from_fn(400, 300, |x, y| {
+            let corner = !(30..370).contains(&x) && !(30..270).contains(&y);
+            Rgba([240, 240, 240, if corner { 0 } else { 255 }])
+        }),
+    );
+    write_photo(&dir.join("photos/photo_1.png"), 120, 90);
+    let output = |output_format: OutputFormat, name: &str| {
+        let options = GenerationOptions { output_format, ..Default::default() };
+        let run = generate(&template, &dir.join("photos"), &dir.join(name), &options, &mut RecordingObserver::default()).unwrap();
+        image::open(&run.images[0].output).unwrap().to_rgba8()
+    };
+
+    let png = output(OutputFormat::Png, "png");
+    let jpeg = output(OutputFormat::Jpeg, "jpeg");
+
+    assert_eq!(png.get_pixel(5, 5)[3], 0);
+    assert_eq!(png.get_pixel(395, 295)[3], 0);
+    // The number is drawn on the opaque part, leaving the corners alone
+    assert_eq!(png.get_pixel(200, 260)[3], 255);
+    assert!(jpeg.get_pixel(5, 5).0.iter().all(|&channel| channel > 245), "{:?}", jpeg.get_pixel(5, 5));
+}
+
+#[test]
+fn template_tags_are_listed_once_with_their_counts() {
+    let dir = TestDir::new("template_tags");
+    test_database();
+    // Unique to this test, other tests share the database
+    let tagged = |tags: &str| {
+        let template = test_template(&dir);
+        add_photo_template(
+            template.name,
+            template.crop_photo,
+            template.crop_number,
+            template.template_img,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(tags.to_string()),
+            None,
+        )
+        .unwrap()
+    };
+    tagged(r#"["tags-test-wedding", "tags-test-print"]"#);
+    tagged(r#"["tags-test-wedding"]"#);
+    tagged(r#"["tags-test-studio", "tags-test-print"]"#);
+
+    let tags: Vec<(String, usize)> = list_template_tags()
+        .unwrap()
+        .into_iter()
+        .filter(|tag| tag.tag.starts_with("tags-test-"))
+        .map(|tag| (tag.tag, tag.count))
+        .collect();
+
+    assert_eq!(
+        tags,
+        [("tags-test-print".to_string(), 2), ("tags-test-studio".to_string(), 1), ("tags-test-wedding".to_string(), 2)]
+    );
+}
+
+#[test]
+fn portrait_and_landscape_photos_use_their_own_templates() {
+    let dir = TestDir::new("orientation_templates");
+    let primary = test_template(&dir);
+    let colored = |name: &str, width: u32, height: u32, color: [u8; 4]| {
+        let template_img = dir.join(name);
+        RgbaImage::from_pixel(width, height, Rgba(color)).save(&template_img).unwrap();
+        PhotoTemplate {
+            template_img: template_img.to_string_lossy().to_string(),
+            image_width: width as i32,
+            image_height: height as i32,
+            ..test_template(&dir)
+        }
+    };
+    let orientation_templates = OrientationTemplates {
+        landscape: Some(colored("landscape.png", 400, 300, [200, 0, 0, 255])),
+        portrait: Some(colored("portrait.png", 300, 420, [0, 0, 200, 255])),
+    };
+    write_photo(&dir.join("photos/photo_1.png"), 120, 80);
+    write_photo(&dir.join("photos/photo_2.png"), 80, 120);
+    let run = |orientation_templates: &OrientationTemplates, name: &str| {
+        let output_dir = dir.join(name);
+        fs::create_dir_all(&output_dir).unwrap();
+        generate_images(
+            &primary,
+            orientation_templates,
+            &dir.join("photos").to_string_lossy(),
+            &output_dir,
+            &GenerationOptions::default(),
+            &AtomicBool::new(false),
+            &mut RecordingObserver::default(),
+        )
+        .unwrap()
+    };
+    // Template an output was made with, from its size and the color of its corner
+    let output_of = |run: &GenerationRun, source: &str| {
+        let image = run.images.iter().find(|image| image.sources[0].ends_with(source)).unwrap();
+        let output = image::open(&image.output).unwrap().to_rgb8();
+        let template = match output.get_pixel(2, 2).0 {
+            [red, _, blue] if red > 150 && blue < 50 => "landscape",
+            [red, _, blue] if red < 50 && blue > 150 => "portrait",
+            _ => "primary",
+        };
+        (output.dimensions(), template)
+    };
+
+    let routed = run(&orientation_templates, "routed");
+    assert_eq!(output_of(&routed, "photo_1.png"), ((400, 300), "landscape"));
+    assert_eq!(output_of(&routed, "photo_2.png"), ((300, 420), "portrait"));
+
+    // Without a portrait template, portrait photos fall back to the primary one
+    let landscape_only = OrientationTemplates { portrait: None, ..orientation_templates };
+    let fallback = run(&landscape_only, "fallback");
+    assert_eq!(output_of(&fallback, "photo_2.png"), ((400, 300), "primary"));
+}
+
+#[test]
+fn image_dropped_from_the_history_survives_a_rolled_back_edit() {
+    let dir = TestDir::new("history_rollback");
+    let template = stored_template(&dir);
+    let first_image = PathBuf::from(&template.template_img);
+    let source = TestDir::new("history_rollback_source");
+    let new_image = source.join("rebrand.png");
+    RgbaImage::from_pixel(400, 300, Rgba([10, 20, 30, 255])).save(&new_image).unwrap();
+    let mut connection = establish_connection().unwrap();
+    let shared = set_templates_image(&mut connection, dir.path(), &[template.id], new_image.to_string_lossy().to_string())
+        .unwrap()[0]
+        .template_img
+        .clone();
+    // Fill the history, the first image is only kept by its oldest entry
+    for _ in 1..MAX_TEMPLATE_EDITS {
+        set_templates_image(&mut connection, dir.path(), &[template.id], shared.clone()).unwrap();
+    }
+
+    // The second template doesn't exist, after the first one's history was pruned
+    assert!(set_templates_image(&mut connection, dir.path(), &[template.id, -1], shared.clone()).is_err());
+    assert!(first_image.exists());
+    let oldest: TemplateEdit = template_edits::table
+        .filter(template_edits::template_id.eq(template.id))
+        .order(template_edits::id.asc())
+        .first(&mut connection)
+        .unwrap();
+    assert_eq!(Path::new(&oldest.template_img), first_image);
+
+    set_templates_image(&mut connection, dir.path(), &[template.id], shared).unwrap();
+    assert!(!first_image.exists());
+}
+
+/// Make SQLite abort every update of template `id`, to fail an edit after its history entry was recorded.
+pub(crate) fn refuse_template_updates(connection: &mut SqliteConnection, id: i32) {
+    diesel::sql_query(format!(
+        "CREATE TRIGGER refuse_template_{id} BEFORE UPDATE ON photo_templates WHEN OLD.id = {id} \
+         BEGIN SELECT RAISE(ABORT, 'update refused'); END"
+    ))
+    .execute(connection)
+    .unwrap();
+}
+
+pub(crate) fn template_edit_count(connection: &mut SqliteConnection, id: i32) -> i64 {
+    template_edits::table
+        .filter(template_edits::template_id.eq(id))
+        .count()
+        .get_result(connection)
+        .unwrap()
+}
+
+#[test]
+fn failed_patch_leaves_no_history_entry_or_imported_image() {
+    let dir = TestDir::new("patch_rollback");
+    let template = stored_template(&dir);
+    let source = TestDir::new("patch_rollback_source");
+    let new_image = source.join("frame.png");
+    RgbaImage::from_pixel(400, 300, Rgba([10, 20, 30, 255])).save(&new_image).unwrap();
+    let mut connection = establish_connection().unwrap();
+    refuse_template_updates(&mut connection, template.id);
+
+    let error = patch_template(
+        &mut connection,
+        dir.path(),
+        template.id,
+        PhotoTemplatePatch {
+            template_img: Some(new_image.to_string_lossy().to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+
+    assert!(error.contains("update refused"), "{}", error);
+    assert_eq!(template_edit_count(&mut connection, template.id), 0);
+    // Only the original image is left in managed storage
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    assert!(Path::new(&template.template_img).exists());
+}
+
+#[test]
+fn failed_template_edits_leave_no_history_entry() {
+    let dir = TestDir::new("update_rollback");
+    let template = stored_template(&dir);
+    let source = TestDir::new("update_rollback_source");
+    let new_image = source.join("frame.png");
+    RgbaImage::from_pixel(400, 300, Rgba([10, 20, 30, 255])).save(&new_image).unwrap();
+    let mut connection = establish_connection().unwrap();
+    refuse_template_updates(&mut connection, template.id);
+
+    let error = update_template(
+        &mut connection,
+        dir.path(),
+        template.id,
+        template.name.clone(),
+        template.crop_photo.clone(),
+        template.crop_number.clone(),
+        new_image.to_string_lossy().to_string(),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .err()
+    .unwrap();
+    assert!(error.contains("update refused"), "{}", error);
+    // The imported copy went with the rollback
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    assert!(remap_template_crops(&mut connection, template.id, 800, 600, dir.path()).is_err());
+    assert!(reset_crops_to_full_image(&mut connection, template.id, dir.path()).is_err());
+    assert_eq!(template_edit_count(&mut connection, template.id), 0);
+}
+
+#[test]
+fn run_number_padding_is_checked_like_the_template_one() {
+    let dir = TestDir::new("run_number_padding");
+    let template = test_template(&dir);
+    write_photo(&dir.join("photos/photo_7.png"), 60, 40);
+    let padded = |number_padding: usize, output: &str| {
+        let options = GenerationOptions {
+            number_padding: Some(number_padding),
+            ..Default::default()
+        };
+        generate(&template, &dir.join("photos"), &dir.join(output), &options, &mut RecordingObserver::default())
+    };
+
+    assert_eq!(padded(3, "output_padded").unwrap().images[0].number, "007");
+    let error = padded(4_000_000_000, "output_huge").err().unwrap();
+    assert!(error.contains("Invalid number padding"), "{}", error);
+}
+
+#[test]
+fn failed_archive_leaves_no_partial_file() {
+    let dir = TestDir::new("archive_failure");
+    write_photo(&dir.join("photo_1.png"), 8, 8);
+    let entries = vec![
+        (dir.join("photo_1.png"), "photo_1.png".to_string()),
+        (dir.join("missing.png"), "missing.png".to_string()),
+    ];
+    let archive_path = dir.join("outputs.zip");
+
+    let error = create_archive(entries, &archive_path, DuplicateEntryPolicy::Error, false, ArchiveCompression::default(), &AtomicBool::new(false))
+        .unwrap_err();
+
+    assert!(error.contains("Error reading image file"), "{}", error);
+    assert!(!archive_path.exists());
+    assert!(!dir.join("outputs.zip.part").exists());
+}
+
+/// Threads the progress events of a run were sent from, each output's event coming from the thread that rendered it.
+#[derive(Default)]
+pub(crate) struct ThreadRecorder(std::collections::HashSet<std::thread::ThreadId>);
+
+impl GenerationObserver for ThreadRecorder {
+    fn on_progress(&mut self, _progress: GenerationProgress) {
+        self.0.insert(std::thread::current().id());
+    }
+}
+
+#[test]
+fn max_threads_limits_the_render_threads() {
+    let dir = TestDir::new("max_threads");
+    let template = test_template(&dir);
+    for index in 1..=6 {
+        write_photo(&dir.join(&format!("photos/photo_{}.png", index)), 200, 150);
+    }
+    let options = GenerationOptions {
+        max_threads: NonZeroUsize::new(1),
+        progress_step_percent: None,
+        ..Default::default()
+    };
+    let mut threads = ThreadRecorder::default();
+    fs::create_dir_all(dir.join("output")).unwrap();
+
+    let run = generate_images(
+        &template,
+        &OrientationTemplates::default(),
+        &dir.join("photos").to_string_lossy(),
+        &dir.join("output"),
+        &options,
+        &AtomicBool::new(false),
+        &mut threads,
+    )
+    .unwrap();
+
+    assert_eq!(run.images.len(), 6);
+    assert_eq!(threads.0.len(), 1);
+}
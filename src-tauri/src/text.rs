@@ -0,0 +1,414 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+use serde::Deserialize;
+use std::sync::OnceLock;
+
+/// A font shipped inside the binary, referenced by its id instead of a file path.
+pub struct BundledFont {
+    pub id: &'static str,
+    pub name: &'static str,
+    data: &'static [u8],
+}
+
+/// Fonts bundled so text rendering never depends on system fonts, the first one is the default.
+pub static BUNDLED_FONTS: &[BundledFont] = &[BundledFont {
+    id: "dejavu-sans",
+    name: "DejaVu Sans",
+    data: include_bytes!("../fonts/DejaVuSans.ttf"),
+}];
+
+/// The first bundled font, parsed once for the whole process.
+pub fn default_font() -> &'static Font<'static> {
+    static DEFAULT_FONT: OnceLock<Font<'static>> = OnceLock::new();
+    DEFAULT_FONT.get_or_init(|| Font::try_from_bytes(BUNDLED_FONTS[0].data).expect("bundled font is a valid TrueType file"))
+}
+
+/// Load a bundled font by id or a TrueType/OpenType font file by path, or the default font when none is given.
+pub fn load_font(font_path: Option<&str>) -> Result<Font<'static>, String> {
+    let Some(path) = font_path.filter(|path| !path.trim().is_empty()) else {
+        return Ok(default_font().clone());
+    };
+
+    match BUNDLED_FONTS.iter().find(|font| font.id == path) {
+        Some(font) => Font::try_from_bytes(font.data).ok_or(format!("Invalid bundled font {}", font.id)),
+        None => {
+            let data = std::fs::read(path)
+                .map_err(|e| format!("Error reading font file {}: {}", path, e))?;
+            Font::try_from_vec(data).ok_or(format!("Invalid font file {}", path))
+        }
+    }
+}
+
+/// Width and height in pixels of `text` laid out on a single line at `font_size`.
+pub fn measure_text(font: &Font, font_size: f32, text: &str) -> (u32, u32) {
+    let scale = Scale::uniform(font_size);
+    let v_metrics = font.v_metrics(scale);
+
+    let width = font
+        .layout(text, scale, point(0.0, v_metrics.ascent))
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .fold(0.0f32, f32::max);
+    let height = v_metrics.ascent - v_metrics.descent;
+
+    (width.ceil() as u32, height.ceil() as u32)
+}
+
+/// Render `text` into a transparent layer exactly `measure_text` pixels large.
+///
+/// Glyph coverage is written to the alpha channel, so overlaying the layer gives anti-aliased text.
+pub fn render_text(font: &Font, font_size: f32, text: &str, color: Rgba<u8>) -> RgbaImage {
+    let (width, height) = measure_text(font, font_size, text);
+    let mut layer = RgbaImage::new(width.max(1), height.max(1));
+
+    let scale = Scale::uniform(font_size);
+    let ascent = font.v_metrics(scale).ascent;
+
+    for glyph in font.layout(text, scale, point(0.0, ascent)) {
+        let Some(bounding_box) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+
+        glyph.draw(|glyph_x, glyph_y, coverage| {
+            let x = glyph_x as i32 + bounding_box.min.x;
+            let y = glyph_y as i32 + bounding_box.min.y;
+            if x < 0 || y < 0 || x as u32 >= layer.width() || y as u32 >= layer.height() {
+                return;
+            }
+
+            let alpha = (coverage * color[3] as f32).round() as u8;
+            let pixel = layer.get_pixel_mut(x as u32, y as u32);
+            // Overlapping glyph edges keep the strongest coverage
+            if alpha > pixel[3] {
+                *pixel = Rgba([color[0], color[1], color[2], alpha]);
+            }
+        });
+    }
+
+    layer
+}
+
+/// Whether rendered text is painted with its flat color or with `TextGradient`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextFill {
+    #[default]
+    Solid,
+    Gradient,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GradientDirection {
+    /// Start color at the top of the glyphs, end color at the bottom
+    #[default]
+    Vertical,
+    /// Start color on the left, end color on the right
+    Horizontal,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TextGradient {
+    pub start: [u8; 4],
+    pub end: [u8; 4],
+    pub direction: GradientDirection,
+}
+
+impl Default for TextGradient {
+    fn default() -> Self {
+        TextGradient {
+            start: [0, 0, 0, 255],
+            end: [128, 128, 128, 255],
+            direction: GradientDirection::Vertical,
+        }
+    }
+}
+
+/// Repaint a layer from `render_text` with a gradient, keeping its glyph coverage.
+pub fn apply_gradient(layer: &mut RgbaImage, gradient: &TextGradient) {
+    let (width, height) = layer.dimensions();
+    for (x, y, pixel) in layer.enumerate_pixels_mut() {
+        let t = match gradient.direction {
+            GradientDirection::Vertical => y as f32 / (height.max(2) - 1) as f32,
+            GradientDirection::Horizontal => x as f32 / (width.max(2) - 1) as f32,
+        };
+        let channel = |index: usize| {
+            let (start, end) = (gradient.start[index] as f32, gradient.end[index] as f32);
+            start + (end - start) * t
+        };
+        let coverage = pixel[3] as f32 / 255.0;
+        *pixel = Rgba([
+            channel(0).round() as u8,
+            channel(1).round() as u8,
+            channel(2).round() as u8,
+            (channel(3) * coverage).round() as u8,
+        ]);
+    }
+}
+
+/// Whether rendered text runs on a straight line or follows `TextArc`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextPath {
+    #[default]
+    Straight,
+    Arc,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TextArc {
+    /// Radius in pixels of the circle the middle of the text follows. Positive values arch the
+    /// text upwards like a banner, negative values bend it downwards like a smile.
+    pub radius: f32,
+}
+
+impl Default for TextArc {
+    fn default() -> Self {
+        TextArc { radius: 400.0 }
+    }
+}
+
+/// Bend a layer from `render_text` along `arc`.
+///
+/// Each column of the layer is rotated about the circle center, so every glyph is turned to
+/// follow the curve while keeping its distance along the text. The returned layer is sized to
+/// the bent text.
+pub fn bend_along_arc(layer: &RgbaImage, arc: &TextArc) -> RgbaImage {
+    let (width, height) = (layer.width() as f32, layer.height() as f32);
+    if arc.radius == 0.0 || !arc.radius.is_finite() {
+        return layer.clone();
+    }
+    // Upwards arches keep the circle center below the text, downwards ones above it
+    let sign = arc.radius.signum();
+    // The text never wraps more than once around the circle
+    let radius = arc.radius.abs().max(width / (2.0 * std::f32::consts::PI)).max(height / 2.0);
+
+    // Position relative to the circle center of a point of the straight layer
+    let forward = |x: f32, y: f32| {
+        let angle = (x - width / 2.0) / radius;
+        let distance = radius + sign * (height / 2.0 - y);
+        (distance * angle.sin(), -sign * distance * angle.cos())
+    };
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    let steps = (width.max(height).ceil() as u32).max(1);
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        for (x, y) in [(t * width, 0.0), (t * width, height), (0.0, t * height), (width, t * height)] {
+            let (bent_x, bent_y) = forward(x, y);
+            min_x = min_x.min(bent_x);
+            min_y = min_y.min(bent_y);
+            max_x = max_x.max(bent_x);
+            max_y = max_y.max(bent_y);
+        }
+    }
+    let (min_x, min_y) = (min_x.floor(), min_y.floor());
+    let bent_width = (max_x.ceil() - min_x).max(1.0) as u32;
+    let bent_height = (max_y.ceil() - min_y).max(1.0) as u32;
+
+    let mut bent = RgbaImage::new(bent_width, bent_height);
+    for (x, y, pixel) in bent.enumerate_pixels_mut() {
+        let bent_x = x as f32 + 0.5 + min_x;
+        let bent_y = y as f32 + 0.5 + min_y;
+        let distance = bent_x.hypot(bent_y);
+        let angle = bent_x.atan2(-sign * bent_y);
+        let source_x = angle * radius + width / 2.0;
+        let source_y = height / 2.0 - sign * (distance - radius);
+        *pixel = sample_bilinear(layer, source_x - 0.5, source_y - 0.5);
+    }
+    bent
+}
+
+/// Sample `layer` between pixel centers, interpolating colors weighted by their alpha so
+/// transparent neighbours don't darken glyph edges.
+fn sample_bilinear(layer: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (left, top) = (x.floor(), y.floor());
+    let (fraction_x, fraction_y) = (x - left, y - top);
+    let mut premultiplied = [0.0f32; 3];
+    let mut alpha = 0.0f32;
+
+    for (offset_x, offset_y, weight) in [
+        (0, 0, (1.0 - fraction_x) * (1.0 - fraction_y)),
+        (1, 0, fraction_x * (1.0 - fraction_y)),
+        (0, 1, (1.0 - fraction_x) * fraction_y),
+        (1, 1, fraction_x * fraction_y),
+    ] {
+        let (sample_x, sample_y) = (left as i64 + offset_x, top as i64 + offset_y);
+        if weight == 0.0
+            || sample_x < 0
+            || sample_y < 0
+            || sample_x >= layer.width() as i64
+            || sample_y >= layer.height() as i64
+        {
+            continue;
+        }
+        let pixel = layer.get_pixel(sample_x as u32, sample_y as u32);
+        let pixel_alpha = pixel[3] as f32 * weight;
+        for channel in 0..3 {
+            premultiplied[channel] += pixel[channel] as f32 * pixel_alpha;
+        }
+        alpha += pixel_alpha;
+    }
+
+    if alpha <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    Rgba([
+        (premultiplied[0] / alpha).round() as u8,
+        (premultiplied[1] / alpha).round() as u8,
+        (premultiplied[2] / alpha).round() as u8,
+        alpha.round().min(255.0) as u8,
+    ])
+}
+
+/// Largest font size (up to 80% of the box height) at which `text` fits inside a `width` x `height` box.
+pub fn fit_font_size(font: &Font, text: &str, width: f32, height: f32) -> f32 {
+    let font_size = (height * 0.8).max(1.0);
+    let (text_width, _) = measure_text(font, font_size, text);
+    if text_width as f32 > width && text_width > 0 {
+        (font_size * width / text_width as f32).max(1.0)
+    } else {
+        font_size
+    }
+}
+
+/// Draw `text` centered in the box at (`x`, `y`), at `font_size` or sized with `fit_font_size` when absent.
+///
+/// The text is painted with `gradient` when given, with `color` otherwise, and bent along `arc` when given.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text_in_box(
+    image: &mut RgbaImage,
+    font: &Font,
+    text: &str,
+    (x, y, width, height): (f32, f32, f32, f32),
+    font_size: Option<f32>,
+    color: Rgba<u8>,
+    gradient: Option<&TextGradient>,
+    arc: Option<&TextArc>,
+) {
+    let font_size = font_size.unwrap_or_else(|| fit_font_size(font, text, width, height));
+    let mut layer = match gradient {
+        Some(gradient) => {
+            // Full-alpha white keeps the raw coverage in the alpha channel for the gradient
+            let mut layer = render_text(font, font_size, text, Rgba([255, 255, 255, 255]));
+            apply_gradient(&mut layer, gradient);
+            layer
+        }
+        None => render_text(font, font_size, text, color),
+    };
+    if let Some(arc) = arc {
+        layer = bend_along_arc(&layer, arc);
+    }
+    let text_x = x + (width - layer.width() as f32) / 2.0;
+    let text_y = y + (height - layer.height() as f32) / 2.0;
+    image::imageops::overlay(image, &layer, text_x.round() as i64, text_y.round() as i64);
+}
+
+/// Apply an 8-bit drawing to `image`, 16-bit images only take the pixels it changed and keep their depth elsewhere.
+pub fn draw_keeping_depth(image: DynamicImage, draw: impl FnOnce(&mut RgbaImage)) -> DynamicImage {
+    let mut drawn = image.to_rgba8();
+    let DynamicImage::ImageRgba16(mut rgba16) = image else {
+        draw(&mut drawn);
+        return DynamicImage::ImageRgba8(drawn);
+    };
+
+    let undrawn = drawn.clone();
+    draw(&mut drawn);
+    for ((after, before), pixel) in drawn.pixels().zip(undrawn.pixels()).zip(rgba16.pixels_mut()) {
+        if after != before {
+            *pixel = Rgba(after.0.map(|channel| channel as u16 * 257));
+        }
+    }
+    DynamicImage::ImageRgba16(rgba16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_font_is_bundled_and_parsed_once() {
+        assert_eq!(BUNDLED_FONTS[0].id, "dejavu-sans");
+        assert!(std::ptr::eq(default_font(), default_font()));
+        assert!(load_font(Some("dejavu-sans")).is_ok());
+        assert!(load_font(Some("   ")).is_ok());
+        assert!(load_font(Some("no-such-font.ttf")).is_err());
+    }
+
+    #[test]
+    fn longer_text_measures_wider_at_the_same_size() {
+        let font = default_font();
+
+        let (short_width, short_height) = measure_text(font, 40.0, "12");
+        let (long_width, long_height) = measure_text(font, 40.0, "12345");
+
+        assert!(long_width > short_width);
+        assert_eq!(long_height, short_height);
+        assert!(measure_text(font, 80.0, "12").1 > short_height);
+        assert_eq!(render_text(font, 40.0, "12345", Rgba([0, 0, 0, 255])).dimensions(), (long_width, long_height));
+    }
+
+    #[test]
+    fn gradient_fills_the_top_and_bottom_of_the_glyphs_differently() {
+        let mut layer = render_text(default_font(), 80.0, "8", Rgba([0, 0, 0, 255]));
+        let gradient = TextGradient {
+            start: [255, 0, 0, 255],
+            end: [0, 0, 255, 255],
+            direction: GradientDirection::Vertical,
+        };
+
+        apply_gradient(&mut layer, &gradient);
+
+        let solid: Vec<(u32, &Rgba<u8>)> = layer
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[3] > 200)
+            .map(|(_, y, pixel)| (y, pixel))
+            .collect();
+        let top = solid.iter().min_by_key(|(y, _)| *y).unwrap().1;
+        let bottom = solid.iter().max_by_key(|(y, _)| *y).unwrap().1;
+        assert!(top[0] > top[2], "top {:?}", top);
+        assert!(bottom[2] > bottom[0], "bottom {:?}", bottom);
+    }
+
+    #[test]
+    fn drawing_on_a_16_bit_image_keeps_the_untouched_pixels() {
+        let image = DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(4, 4, Rgba([1000u16, 2000, 3000, u16::MAX])));
+
+        let drawn = draw_keeping_depth(image, |rgba| rgba.put_pixel(1, 1, Rgba([255, 0, 0, 255])));
+
+        let DynamicImage::ImageRgba16(drawn) = drawn else {
+            panic!("16-bit image drawn as {:?}", drawn.color());
+        };
+        assert_eq!(drawn.get_pixel(1, 1).0, [u16::MAX, 0, 0, u16::MAX]);
+        assert_eq!(drawn.get_pixel(2, 2).0, [1000, 2000, 3000, u16::MAX]);
+        let eight_bit = DynamicImage::ImageRgb8(image::RgbImage::new(2, 2));
+        assert!(matches!(draw_keeping_depth(eight_bit, |_| {}), DynamicImage::ImageRgba8(_)));
+    }
+
+    #[test]
+    fn arched_number_has_its_glyphs_at_different_heights() {
+        let layer = render_text(default_font(), 60.0, "88888", Rgba([0, 0, 0, 255]));
+        // Top of the ink in the first, middle and last fifth of the columns
+        let glyph_tops = |layer: &RgbaImage| {
+            let fifth = layer.width() / 5;
+            [0, 2, 4].map(|glyph| {
+                layer
+                    .enumerate_pixels()
+                    .filter(|(x, _, pixel)| (glyph * fifth..(glyph + 1) * fifth).contains(x) && pixel[3] > 128)
+                    .map(|(_, y, _)| y)
+                    .min()
+                    .unwrap()
+            })
+        };
+
+        let [first, middle, last] = glyph_tops(&layer);
+        assert!(first == middle && middle == last);
+
+        let [first, middle, last] = glyph_tops(&bend_along_arc(&layer, &TextArc { radius: 150.0 }));
+        assert!(middle + 5 < first && middle + 5 < last, "{:?}", (first, middle, last));
+
+        let [first, middle, last] = glyph_tops(&bend_along_arc(&layer, &TextArc { radius: -150.0 }));
+        assert!(middle > first + 5 && middle > last + 5, "{:?}", (first, middle, last));
+    }
+}
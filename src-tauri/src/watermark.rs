@@ -0,0 +1,90 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use serde::{Deserialize, Serialize};
+
+use crate::text;
+
+/// Text stamped diagonally across the whole output, typically for proofs.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct WatermarkOptions {
+    pub text: String,
+    /// 0 (invisible) to 1 (opaque)
+    pub opacity: f32,
+    /// Rotation in degrees, clockwise
+    pub angle: f32,
+    /// Auto-sized to span about 60% of the image width when absent
+    pub font_size: Option<f32>,
+    pub color: [u8; 3],
+    /// Outputs of a run the watermark is stamped on
+    pub target: WatermarkTarget,
+}
+
+/// Which outputs get the watermark, e.g. only the low-res proofs when the full-res files are paid for.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkTarget {
+    #[default]
+    All,
+    Previews,
+    Fullres,
+}
+
+impl WatermarkTarget {
+    pub fn covers(self, preview: bool) -> bool {
+        match self {
+            WatermarkTarget::All => true,
+            WatermarkTarget::Previews => preview,
+            WatermarkTarget::Fullres => !preview,
+        }
+    }
+}
+
+impl Default for WatermarkOptions {
+    fn default() -> Self {
+        WatermarkOptions {
+            text: String::new(),
+            opacity: 0.3,
+            angle: -30.0,
+            font_size: None,
+            color: [128, 128, 128],
+            target: WatermarkTarget::All,
+        }
+    }
+}
+
+pub fn apply_watermark(image: DynamicImage, watermark: &WatermarkOptions) -> DynamicImage {
+    if watermark.text.trim().is_empty() {
+        return image;
+    }
+
+    let font = text::default_font();
+    let font_size = watermark.font_size.unwrap_or_else(|| {
+        let (reference_width, _) = text::measure_text(font, 100.0, &watermark.text);
+        100.0 * (image.width() as f32 * 0.6) / reference_width.max(1) as f32
+    });
+
+    let alpha = (watermark.opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let [red, green, blue] = watermark.color;
+    let layer = text::render_text(font, font_size, &watermark.text, Rgba([red, green, blue, alpha]));
+
+    // Pad to the layer's diagonal so the rotation doesn't clip the text
+    let diagonal = ((layer.width() as f32).hypot(layer.height() as f32)).ceil() as u32;
+    let mut padded = RgbaImage::new(diagonal, diagonal);
+    image::imageops::overlay(
+        &mut padded,
+        &layer,
+        ((diagonal - layer.width()) / 2) as i64,
+        ((diagonal - layer.height()) / 2) as i64,
+    );
+    let rotated = rotate_about_center(
+        &padded,
+        watermark.angle.to_radians(),
+        Interpolation::Bilinear,
+        Rgba([0, 0, 0, 0]),
+    );
+
+    let x = (image.width() as i64 - diagonal as i64) / 2;
+    let y = (image.height() as i64 - diagonal as i64) / 2;
+    text::draw_keeping_depth(image, |result| image::imageops::overlay(result, &rotated, x, y))
+}
@@ -2,7 +2,7 @@ import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import TemplateListView from "./components/TemplateListView";
-import { PhotoTemplate } from "./types/photoTemplate";
+import { GenerationProgress, GenerationResult, PhotoTemplate } from "./types/photoTemplate";
 import TemplateGenerationView from "./components/TemplateGenerationView";
 import "./App.css";
 
@@ -62,12 +62,12 @@ function App() {
     setMessage("");
     
     try {
-      const archivePath = await invoke<string>("generate_images_with_template", {
+      const result = await invoke<GenerationResult>("generate_images_with_template", {
         templateId: selectedTemplate.id,
         imageFolderPath: selectedImageFolder,
       });
       
-      setArchivePath(archivePath);
+      setArchivePath(result.archive_path);
       setMessage("Génération terminée avec succès!");
     } catch (error) {
       setMessage(`Erreur lors de la génération: ${error}`);
@@ -99,8 +99,8 @@ function App() {
   // Set up event listeners for progress updates
   useEffect(() => {
     const setupProgressListener = async () => {
-      const unlisten = await listen<number>('generation-progress', (event) => {
-        setGenerationProgress(event.payload);
+      const unlisten = await listen<GenerationProgress>('generation-progress', (event) => {
+        setGenerationProgress(event.payload.percent);
       });
       
       return unlisten;
@@ -4,4 +4,34 @@ export interface PhotoTemplate {
   crop_photo: string;
   crop_number: string;
   template_img: string;
+  image_width: number;
+  image_height: number;
+  default_watermark: string;
+  font_size: number;
+  text_color: string;
+  number_format: string;
+  number_padding: number;
+  default_output_folder: string;
+  tags: string;
+  font_id: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface TemplateUpdate extends PhotoTemplate {
+  crops_may_be_misaligned: boolean;
+}
+
+export interface GenerationResult {
+  archive_path: string;
+  archive_paths: string[];
+  failures_bundle_path: string | null;
+  thumbnail_path: string | null;
+}
+
+export interface GenerationProgress {
+  current: number;
+  total: number;
+  filename: string;
+  percent: number;
 }